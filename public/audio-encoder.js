@@ -7,9 +7,19 @@ var audioSampleRate;
 const audioEncoder = new AudioEncoder({
   /**
    * @param {EncodedAudioChunk} chunk
-   * @param {any} _metadata
+   * @param {EncodedAudioChunkMetadata} metadata
    */
-  output(chunk, _metadata) {
+  output(chunk, metadata) {
+    // Recordings and voicemails need to know how the audio was encoded
+    const decoderConfig = metadata?.decoderConfig;
+    if (decoderConfig) {
+      postMessage({
+        decoderConfig: {
+          sampleRate: decoderConfig.sampleRate,
+          numberOfChannels: decoderConfig.numberOfChannels,
+        },
+      });
+    }
     postMessage(chunk);
   },
   error(error) {
@@ -46,7 +56,8 @@ onmessage = (event) => {
     sampleRate: audioSampleRate || 48000,
     numberOfFrames: pcm.length,
     numberOfChannels: 1,
-    timestamp: performance.now(),
+    // WebCodecs timestamps are in microseconds
+    timestamp: Math.round(performance.now() * 1000),
     data: pcm.buffer,
   });
   audioEncoder.encode(audioData);
@@ -0,0 +1,781 @@
+mod media;
+mod storage;
+
+use std::{path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use free_voip_core::{
+    backup::BackupSource,
+    call::{CallMedia, CallStats},
+    echo::{self, EchoStats},
+    link::DeepLink,
+    logging,
+    network::{DiscoveryMode, RelayMode},
+    presence::{Presence, PresenceState},
+    qr::QrFormat,
+    Event, FreeVoipError, Node, NodeConfig, NodeId, RelayUrl,
+};
+use tokio::{
+    io::AsyncWrite,
+    sync::broadcast::{error::RecvError, Receiver},
+    task::JoinHandle,
+};
+
+use crate::{
+    media::{MediaPath, Pacer},
+    storage::FileStorage,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Free VoIP from the command line")]
+struct Cli {
+    /// Where the identity, contacts and everything else is kept.
+    #[arg(
+        long,
+        global = true,
+        env = "FREE_VOIP_DATA_DIR",
+        default_value = "free-voip-data"
+    )]
+    data_dir: PathBuf,
+
+    /// Passphrase the identity is encrypted with.
+    #[arg(
+        long,
+        global = true,
+        env = "FREE_VOIP_PASSPHRASE",
+        hide_env_values = true
+    )]
+    passphrase: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a new identity and print its contact ticket.
+    Login { nickname: String },
+    /// Print your contact ticket.
+    Ticket,
+    /// Save your contact ticket as a QR code image.
+    Qr {
+        output: PathBuf,
+        #[arg(long, value_enum, default_value_t = QrArg::Svg)]
+        format: QrArg,
+    },
+    /// Print the contact or invite ticket in a QR code image, for `add`.
+    ScanQr { image: PathBuf },
+    /// Change your nickname. Contacts are told the next time they are reachable.
+    UpdateProfile {
+        #[arg(long)]
+        nickname: String,
+    },
+    /// Set the presence contacts see while you are listening. `offline` hides you from everyone.
+    SetPresence {
+        #[arg(value_enum)]
+        state: PresenceArg,
+        /// A short status, like "Back at 3".
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Change the relays and how peers are found. Prints the settings.
+    SetNetwork {
+        #[arg(long, value_enum)]
+        relay_mode: Option<RelayArg>,
+        /// A relay for `--relay-mode custom`, can be given several times.
+        #[arg(long = "relay-url")]
+        relay_urls: Vec<RelayUrl>,
+        /// Publish and look up addresses on n0's DNS servers.
+        #[arg(long)]
+        dns_discovery: Option<bool>,
+        /// Find and be found by devices on the local network.
+        #[arg(long)]
+        local_discovery: Option<bool>,
+        /// Send all traffic through a relay and keep your IP address out of tickets and discovery.
+        #[arg(long)]
+        hide_ip: Option<bool>,
+    },
+    /// Set your avatar to an image file, or remove it if none is given.
+    SetAvatar { image: Option<PathBuf> },
+    /// Print the recovery phrase of the identity, or save a backup file with contacts instead.
+    ExportIdentity {
+        /// Save a backup file encrypted with this passphrase and print its path.
+        #[arg(long, env = "FREE_VOIP_BACKUP_PASSPHRASE", hide_env_values = true)]
+        backup_passphrase: Option<String>,
+    },
+    /// Restore an identity from a recovery phrase or backup file, encrypted with `--passphrase`.
+    ImportIdentity {
+        /// The 24 words printed by `export-identity`.
+        #[arg(
+            long,
+            required_unless_present = "file",
+            conflicts_with = "file",
+            requires = "nickname"
+        )]
+        mnemonic: Option<String>,
+        /// Nickname to go by, as a recovery phrase doesn't include it.
+        #[arg(long)]
+        nickname: Option<String>,
+        /// Backup file saved by `export-identity --backup-passphrase`.
+        #[arg(long, requires = "backup_passphrase")]
+        file: Option<PathBuf>,
+        /// Passphrase the backup file is encrypted with.
+        #[arg(long, env = "FREE_VOIP_BACKUP_PASSPHRASE", hide_env_values = true)]
+        backup_passphrase: Option<String>,
+    },
+    /// Encrypt the identity with a new passphrase, given the current one with `--passphrase`.
+    ChangePassphrase {
+        #[arg(env = "FREE_VOIP_NEW_PASSPHRASE", hide_env_values = true)]
+        new_passphrase: String,
+    },
+    /// Delete the identity with its contacts, call history and voicemails. Its node ID can never
+    /// be recovered, so contacts will have to add the next one.
+    DeleteIdentity {
+        /// Confirm that the node ID is lost for good.
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Send a contact request to the owner of a contact or invite ticket.
+    Add { ticket: String },
+    /// List your contacts.
+    Contacts,
+    /// List devices running Free VoIP on the local network.
+    Nearby {
+        /// How long to look for devices.
+        #[arg(long, default_value_t = 5)]
+        wait_secs: u64,
+    },
+    /// Print an invite ticket. Contact requests that come with it are accepted without asking.
+    Invite {
+        /// How long the invite can be used for.
+        #[arg(long, default_value_t = 24)]
+        valid_for_hours: u64,
+        /// How many people can use the invite.
+        #[arg(long, default_value_t = 1)]
+        max_uses: u32,
+    },
+    /// List the invites that can still be used.
+    Invites,
+    /// Revoke an invite, given by the ID printed by `invites`.
+    RevokeInvite { id: String },
+    /// Stay online, answering or declining rings and contact requests until interrupted.
+    Listen {
+        /// What to do with incoming rings.
+        #[arg(long, value_enum, default_value_t = Policy::Accept)]
+        rings: Policy,
+        /// What to do with incoming contact requests.
+        #[arg(long, value_enum, default_value_t = Policy::Decline)]
+        contact_requests: Policy,
+        #[command(flatten)]
+        media: MediaArgs,
+    },
+    /// Ring a contact, given by nickname or node ID, and stream media once they answer.
+    Call {
+        contact: String,
+        /// Report round-trip latency and loss, for calls with an echo bot.
+        #[arg(long)]
+        echo_test: bool,
+        #[command(flatten)]
+        media: MediaArgs,
+    },
+    /// Open a `free-voip://add` or `free-voip://call` link.
+    Open {
+        uri: String,
+        #[command(flatten)]
+        media: MediaArgs,
+    },
+    /// Run an echo bot that accepts every contact request and sends all call media back.
+    Echo,
+    /// Save the logs with identifying data stripped, for bug reports, and print their path.
+    ExportLogs,
+}
+
+/// Media is read and written as length-prefixed, postcard-encoded frames.
+#[derive(Debug, Clone, Args)]
+struct MediaArgs {
+    /// Send frames read from this file, or from stdin if `-`. Hangs up once the input ends.
+    #[arg(long)]
+    media_in: Option<MediaPath>,
+    /// Write received frames to this file, or to stdout if `-`. Files are overwritten every call.
+    #[arg(long)]
+    media_out: Option<MediaPath>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PresenceArg {
+    Online,
+    Away,
+    Busy,
+    Offline,
+}
+
+impl From<PresenceArg> for PresenceState {
+    fn from(state: PresenceArg) -> Self {
+        match state {
+            PresenceArg::Online => PresenceState::Online,
+            PresenceArg::Away => PresenceState::Away,
+            PresenceArg::Busy => PresenceState::Busy,
+            PresenceArg::Offline => PresenceState::Offline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RelayArg {
+    Default,
+    Custom,
+    Disabled,
+}
+
+impl From<RelayArg> for RelayMode {
+    fn from(mode: RelayArg) -> Self {
+        match mode {
+            RelayArg::Default => RelayMode::Default,
+            RelayArg::Custom => RelayMode::Custom,
+            RelayArg::Disabled => RelayMode::Disabled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum QrArg {
+    Svg,
+    Png,
+}
+
+impl From<QrArg> for QrFormat {
+    fn from(format: QrArg) -> Self {
+        match format {
+            QrArg::Svg => QrFormat::Svg,
+            QrArg::Png => QrFormat::Png,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Policy {
+    Accept,
+    Decline,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {e}");
+        match e {
+            FreeVoipError::NotLoggedIn => eprintln!("Run `login` first"),
+            FreeVoipError::Locked => eprintln!("Pass the passphrase with `--passphrase`"),
+            _ => {}
+        }
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+async fn run(cli: Cli) -> Result<(), FreeVoipError> {
+    let storage = Arc::new(FileStorage::new(&cli.data_dir)?);
+    let config = NodeConfig {
+        download_dir: cli.data_dir.join("downloads"),
+        recordings_dir: cli.data_dir.join("recordings"),
+        data_dir: cli.data_dir,
+    };
+    let _log_guard = logging::init(&config.log_dir(), true)?;
+    let node = Node::new(storage, config).await?;
+    let passphrase = cli.passphrase.as_deref();
+
+    match cli.command {
+        Command::Login { nickname } => {
+            node.login(nickname, required_passphrase(passphrase)?, None)
+                .await?;
+            println!("{}", node.serialized_self_ticket().await?);
+        }
+        Command::UpdateProfile { nickname } => {
+            go_online(&node, passphrase).await?;
+            node.update_profile(nickname).await?;
+            println!("{}", node.serialized_self_ticket().await?);
+        }
+        Command::SetPresence { state, status } => node.set_presence(Presence {
+            state: state.into(),
+            status_text: status,
+        })?,
+        Command::SetNetwork {
+            relay_mode,
+            relay_urls,
+            dns_discovery,
+            local_discovery,
+            hide_ip,
+        } => {
+            let mut settings = node.network_settings()?;
+            if let Some(relay_mode) = relay_mode {
+                settings.relay_mode = relay_mode.into();
+            }
+            if !relay_urls.is_empty() {
+                settings.relay_urls = relay_urls;
+            }
+            if let Some(dns_discovery) = dns_discovery {
+                settings.discovery = if dns_discovery {
+                    DiscoveryMode::Default
+                } else {
+                    DiscoveryMode::Disabled
+                };
+            }
+            if let Some(local_discovery) = local_discovery {
+                settings.local_discovery = local_discovery;
+            }
+            if let Some(hide_ip) = hide_ip {
+                settings.hide_ip = hide_ip;
+            }
+            node.set_network_settings(settings.clone()).await?;
+
+            println!("Relay mode: {:?}", settings.relay_mode);
+            for url in &settings.relay_urls {
+                println!("Relay URL: {url}");
+            }
+            println!("Discovery: {:?}", settings.discovery);
+            println!("Local discovery: {}", settings.local_discovery);
+            println!("Hide IP: {}", settings.hide_ip);
+        }
+        Command::SetAvatar { image } => {
+            go_online(&node, passphrase).await?;
+            let image = match image {
+                Some(path) => Some(tokio::fs::read(path).await?),
+                None => None,
+            };
+            node.set_avatar(image).await?;
+        }
+        Command::ExportIdentity { backup_passphrase } => {
+            go_online(&node, passphrase).await?;
+            match backup_passphrase {
+                Some(backup_passphrase) => {
+                    let path = node.export_identity_file(&backup_passphrase).await?;
+                    println!("{}", path.display());
+                }
+                None => println!("{}", node.export_mnemonic().await?),
+            }
+        }
+        Command::ImportIdentity {
+            mnemonic,
+            nickname,
+            file,
+            backup_passphrase,
+        } => {
+            let source = match (mnemonic, file) {
+                (Some(phrase), _) => BackupSource::Mnemonic {
+                    phrase,
+                    nickname: nickname.unwrap_or_default(),
+                },
+                (None, Some(file)) => BackupSource::File {
+                    contents: tokio::fs::read_to_string(file).await?,
+                    passphrase: backup_passphrase.unwrap_or_default(),
+                },
+                (None, None) => unreachable!("clap requires one of them"),
+            };
+            node.import_identity(source, required_passphrase(passphrase)?)
+                .await?;
+            println!("{}", node.serialized_self_ticket().await?);
+        }
+        Command::Ticket => {
+            go_online(&node, passphrase).await?;
+            println!("{}", node.serialized_self_ticket().await?);
+        }
+        Command::Qr { output, format } => {
+            go_online(&node, passphrase).await?;
+            tokio::fs::write(&output, node.self_ticket_qr(format.into()).await?).await?;
+            eprintln!("Saved to {}", output.display());
+        }
+        Command::ScanQr { image } => {
+            println!("{}", node.decode_ticket_qr(&tokio::fs::read(image).await?)?);
+        }
+        Command::ChangePassphrase { new_passphrase } => {
+            node.change_passphrase(passphrase.unwrap_or_default(), &new_passphrase)
+                .await?;
+            eprintln!("Passphrase changed");
+        }
+        Command::DeleteIdentity { yes } => {
+            if !yes {
+                return Err(FreeVoipError::invalid_state(
+                    "Your node ID cannot be recovered once deleted, pass --yes to delete it anyway",
+                ));
+            }
+            node.delete_identity().await?;
+            eprintln!("Identity deleted");
+        }
+        Command::Add { ticket } => add(&node, passphrase, &ticket).await?,
+        Command::Contacts => {
+            for contact in node.contacts()? {
+                println!("{}\t{}", contact.nickname, contact.node_id);
+            }
+        }
+        Command::Nearby { wait_secs } => {
+            go_online(&node, passphrase).await?;
+            tokio::time::sleep(Duration::from_secs(wait_secs)).await;
+            for device in node.nearby_devices()? {
+                let nickname = device.nickname.as_deref().unwrap_or("-");
+                println!("{nickname}\t{}", device.node_id);
+            }
+        }
+        Command::Invite {
+            valid_for_hours,
+            max_uses,
+        } => {
+            go_online(&node, passphrase).await?;
+            let valid_for = Duration::from_secs(valid_for_hours * 60 * 60);
+            println!("{}", node.create_invite(valid_for, Some(max_uses)).await?);
+        }
+        Command::Invites => {
+            for invite in node.invites() {
+                let max_uses = invite.max_uses.unwrap_or(1);
+                println!(
+                    "{}\t{}/{} uses\texpires {}",
+                    invite.id, invite.uses, max_uses, invite.expires_at
+                );
+            }
+        }
+        Command::RevokeInvite { id } => {
+            if !node.revoke_invite(&id)? {
+                return Err(FreeVoipError::invalid_state("No invite with this ID"));
+            }
+            eprintln!("Invite revoked");
+        }
+        Command::Listen {
+            rings,
+            contact_requests,
+            media,
+        } => listen(&node, passphrase, rings, contact_requests, &media).await?,
+        Command::Call {
+            contact,
+            echo_test,
+            media,
+        } => call(&node, passphrase, &contact, echo_test, &media).await?,
+        Command::Open { uri, media } => match DeepLink::parse(&uri)? {
+            DeepLink::Add {
+                serialized_ticket, ..
+            } => add(&node, passphrase, &serialized_ticket).await?,
+            DeepLink::Call { node_id } => {
+                call(&node, passphrase, &node_id.to_string(), false, &media).await?
+            }
+        },
+        Command::Echo => {
+            go_online(&node, passphrase).await?;
+            eprintln!("Echo bot ticket:");
+            println!("{}", node.serialized_self_ticket().await?);
+
+            tokio::select! {
+                result = echo::serve(&node) => result?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Command::ExportLogs => println!("{}", node.export_log_bundle().await?.display()),
+    }
+
+    Ok(())
+}
+
+/// The passphrase to encrypt a new identity with.
+fn required_passphrase(passphrase: Option<&str>) -> Result<&str, FreeVoipError> {
+    passphrase.ok_or_else(|| {
+        FreeVoipError::invalid_state("Pass a passphrase to encrypt the identity with")
+    })
+}
+
+/// Brings the identity online, unlocking it with `passphrase` if it is encrypted.
+async fn go_online(node: &Node, passphrase: Option<&str>) -> Result<(), FreeVoipError> {
+    match node.restore_login().await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(FreeVoipError::NotLoggedIn),
+        Err(FreeVoipError::Locked) => node.unlock(passphrase.ok_or(FreeVoipError::Locked)?).await,
+        Err(e) => Err(e),
+    }
+}
+
+async fn add(node: &Node, passphrase: Option<&str>, ticket: &str) -> Result<(), FreeVoipError> {
+    go_online(node, passphrase).await?;
+
+    let (contact, accepted) = node.send_contact_request(ticket).await?;
+    match accepted {
+        Some(true) => {
+            node.add_contact(contact.clone())?;
+            eprintln!("{} accepted your contact request", contact.nickname);
+        }
+        Some(false) => return Err(FreeVoipError::Declined),
+        None => eprintln!(
+            "{} is offline, the request is queued and will be sent while listening",
+            contact.nickname
+        ),
+    }
+    Ok(())
+}
+
+async fn listen(
+    node: &Node,
+    passphrase: Option<&str>,
+    rings: Policy,
+    contact_requests: Policy,
+    media: &MediaArgs,
+) -> Result<(), FreeVoipError> {
+    let mut events = node.events();
+    go_online(node, passphrase).await?;
+    eprintln!("Listening as {}", node.self_ticket().await?.node_id);
+
+    let mut streaming: Option<JoinHandle<()>> = None;
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        match event {
+            Ok(Event::ContactRequest(ticket)) => {
+                let accept = contact_requests == Policy::Accept;
+                node.respond_to_contact_request(accept).await?;
+                if accept {
+                    if let Err(e) = node.add_contact(ticket.clone()) {
+                        eprintln!("{e}");
+                    }
+                }
+                eprintln!(
+                    "{} contact request from {}",
+                    if accept { "Accepted" } else { "Declined" },
+                    ticket.nickname
+                );
+            }
+            Ok(Event::RingRequest(ticket)) => {
+                let accept = rings == Policy::Accept;
+
+                // Subscribe before answering so that no media is missed
+                let media_rx = node.subscribe_call_media().await?;
+                node.respond_to_ring(accept).await?;
+                if accept {
+                    streaming = Some(start_streaming(node, media, media_rx).await?);
+                }
+                eprintln!(
+                    "{} call from {}",
+                    if accept { "Answered" } else { "Declined" },
+                    ticket.nickname
+                );
+            }
+            Ok(Event::CallHangUp) => {
+                if let Some(streaming) = streaming.take() {
+                    streaming.abort();
+                }
+                eprintln!("Call ended");
+            }
+            Ok(Event::FileOffer { peer, offer }) => {
+                node.respond_to_file_offer(false).await?;
+                eprintln!("Declined file {} from {}", offer.name, peer);
+            }
+            Ok(Event::MissedCall(record)) => eprintln!("Missed a call from {}", record.peer),
+            Ok(Event::InviteRedeemed(ticket)) => {
+                eprintln!("Added {} who came with an invite", ticket.nickname)
+            }
+            Ok(Event::VoicemailReceived(info)) => {
+                eprintln!("Voicemail from {}", info.sender.nickname)
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    if streaming.is_some() {
+        node.hang_up().await?;
+    }
+    Ok(())
+}
+
+async fn call(
+    node: &Node,
+    passphrase: Option<&str>,
+    contact: &str,
+    echo_test: bool,
+    media: &MediaArgs,
+) -> Result<(), FreeVoipError> {
+    let node_id = match contact.parse::<NodeId>() {
+        Ok(node_id) => node_id,
+        Err(_) => {
+            node.contacts()?
+                .into_iter()
+                .find(|c| c.nickname == contact)
+                .ok_or_else(|| FreeVoipError::InvalidState(format!("No contact named {contact}")))?
+                .node_id
+        }
+    };
+
+    let mut events = node.events();
+    go_online(node, passphrase).await?;
+
+    let media_rx = node.subscribe_call_media().await?;
+    eprintln!("Ringing {node_id}");
+    let answered = tokio::select! {
+        answered = node.ring(node_id) => answered?,
+        _ = tokio::signal::ctrl_c() => {
+            node.hang_up().await?;
+            false
+        }
+    };
+    if !answered {
+        return Err(FreeVoipError::Declined);
+    }
+    eprintln!("Call answered");
+    print_call_stats(&node.call_stats().await?);
+    let streaming = start_streaming(node, media, media_rx).await?;
+    if echo_test {
+        node.start_echo_test().await?;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(Event::EchoStats(stats)) => print_echo_stats(&stats),
+                Ok(Event::CallHangUp) | Err(RecvError::Closed) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+            },
+            _ = tokio::signal::ctrl_c() => {
+                node.hang_up().await?;
+                break;
+            }
+        }
+    }
+
+    streaming.abort();
+    eprintln!("Call ended");
+    Ok(())
+}
+
+fn print_call_stats(stats: &CallStats) {
+    let path = match (stats.direct_addr, &stats.relay_url) {
+        (Some(addr), None) => format!("direct to {addr}"),
+        (Some(addr), Some(url)) => format!("direct to {addr} and relayed via {url}"),
+        (None, Some(url)) => format!("relayed via {url}"),
+        (None, None) => "no path yet".to_owned(),
+    };
+    let hidden = if stats.ip_hidden { ", IP hidden" } else { "" };
+    eprintln!(
+        "Connected {path}, round trip {:.1} ms{hidden}",
+        stats.rtt_ms
+    );
+}
+
+fn print_echo_stats(stats: &EchoStats) {
+    let rtt = match (stats.avg_rtt_ms, stats.min_rtt_ms, stats.max_rtt_ms) {
+        (Some(avg), Some(min), Some(max)) => {
+            format!("round trip {avg:.1} ms (min {min:.1}, max {max:.1})")
+        }
+        _ => "no echoes yet".to_owned(),
+    };
+    eprintln!(
+        "Sent {}, echoed {}, lost {} ({:.1}%), {}",
+        stats.sent,
+        stats.echoed,
+        stats.lost,
+        stats.loss_ratio * 100.0,
+        rtt
+    );
+}
+
+/// Streams media of the ongoing call, hanging up once the input runs out.
+///
+/// Runs until aborted when the call ends.
+async fn start_streaming(
+    node: &Node,
+    media: &MediaArgs,
+    media_rx: Receiver<CallMedia>,
+) -> Result<JoinHandle<()>, FreeVoipError> {
+    let writer = match media.media_out {
+        Some(ref path) => Some(path.writer().await?),
+        None => None,
+    };
+
+    let node = node.clone();
+    let media_in = media.media_in.clone();
+    Ok(tokio::spawn(async move {
+        let result = tokio::select! {
+            result = receive_media(media_rx, writer) => result,
+            result = send_media(&node, media_in) => result,
+        };
+        if let Err(e) = result {
+            eprintln!("Media streaming failed: {e}");
+        }
+    }))
+}
+
+async fn receive_media(
+    mut media_rx: Receiver<CallMedia>,
+    mut writer: Option<impl AsyncWrite + Unpin>,
+) -> Result<(), FreeVoipError> {
+    loop {
+        match media_rx.recv().await {
+            Ok(frame) => {
+                if let Some(ref mut writer) = writer {
+                    media::write_frame(writer, &frame).await?;
+                }
+            }
+            Err(RecvError::Lagged(n)) => eprintln!("Dropped {n} received media frames"),
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+async fn send_media(node: &Node, media_in: Option<MediaPath>) -> Result<(), FreeVoipError> {
+    let Some(path) = media_in else {
+        return std::future::pending().await;
+    };
+
+    let mut reader = path.reader().await?;
+    let mut pacer = Pacer::default();
+    while let Some(frame) = media::read_frame(&mut reader).await? {
+        pacer.wait(&frame).await;
+        node.send_call_media(frame).await?;
+    }
+
+    eprintln!("Media input ended, hanging up");
+    node.hang_up().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn import_identity_parses() {
+        let cli = Cli::try_parse_from([
+            "free-voip",
+            "--passphrase",
+            "secret",
+            "import-identity",
+            "--mnemonic",
+            "abandon ability",
+            "--nickname",
+            "Alice",
+        ])
+        .unwrap();
+        assert_eq!(cli.passphrase.as_deref(), Some("secret"));
+        assert!(matches!(
+            cli.command,
+            Command::ImportIdentity {
+                mnemonic: Some(ref mnemonic),
+                nickname: Some(ref nickname),
+                file: None,
+                ..
+            } if mnemonic == "abandon ability" && nickname == "Alice"
+        ));
+
+        // A recovery phrase doesn't include the nickname
+        assert!(Cli::try_parse_from([
+            "free-voip",
+            "import-identity",
+            "--mnemonic",
+            "abandon ability",
+        ])
+        .is_err());
+    }
+}
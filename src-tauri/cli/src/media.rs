@@ -0,0 +1,102 @@
+//! Call media as a byte stream: length-prefixed, postcard-encoded [`CallMedia`] frames, the same
+//! framing as voicemail files without their header.
+
+use std::{path::PathBuf, pin::Pin, str::FromStr, time::Duration};
+
+use free_voip_core::{call::CallMedia, FreeVoipError};
+use tokio::{
+    fs::File,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+
+/// Frames larger than this are assumed to be garbage rather than media.
+const MAX_FRAME_SIZE: u32 = 4 * 1024 * 1024;
+
+/// A file, or stdin/stdout when given as `-`.
+#[derive(Debug, Clone)]
+pub enum MediaPath {
+    Stdio,
+    File(PathBuf),
+}
+
+impl FromStr for MediaPath {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "-" => MediaPath::Stdio,
+            path => MediaPath::File(path.into()),
+        })
+    }
+}
+
+impl MediaPath {
+    pub async fn reader(&self) -> Result<Pin<Box<dyn AsyncRead + Send>>, FreeVoipError> {
+        Ok(match self {
+            MediaPath::Stdio => Box::pin(io::stdin()),
+            MediaPath::File(path) => Box::pin(File::open(path).await?),
+        })
+    }
+
+    pub async fn writer(&self) -> Result<Pin<Box<dyn AsyncWrite + Send>>, FreeVoipError> {
+        Ok(match self {
+            MediaPath::Stdio => Box::pin(io::stdout()),
+            MediaPath::File(path) => Box::pin(File::create(path).await?),
+        })
+    }
+}
+
+fn frame_timestamp(media: &CallMedia) -> u64 {
+    match media {
+        CallMedia::Audio { timestamp, .. } | CallMedia::Video { timestamp, .. } => *timestamp,
+    }
+}
+
+/// Reads the next frame, or `None` at the end of the stream.
+pub async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<CallMedia>, FreeVoipError> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_SIZE {
+        return Err(FreeVoipError::Io(format!(
+            "Media frame of {len} bytes is too large"
+        )));
+    }
+
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf).await?;
+    Ok(Some(postcard::from_bytes(&buf)?))
+}
+
+pub async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    media: &CallMedia,
+) -> Result<(), FreeVoipError> {
+    let serialized_frame = postcard::to_stdvec(media)?;
+    writer.write_u32(serialized_frame.len() as u32).await?;
+    writer.write_all(&serialized_frame).await?;
+    Ok(writer.flush().await?)
+}
+
+/// Paces frames by their microsecond timestamps so that recorded media plays in real time. Live
+/// input is effectively passed through as it arrives.
+#[derive(Debug, Default)]
+pub struct Pacer {
+    start: Option<(Instant, u64)>,
+}
+
+impl Pacer {
+    /// Waits until `frame` is due.
+    pub async fn wait(&mut self, frame: &CallMedia) {
+        let (start, first_timestamp) = *self
+            .start
+            .get_or_insert_with(|| (Instant::now(), frame_timestamp(frame)));
+        let offset = frame_timestamp(frame).saturating_sub(first_timestamp);
+        tokio::time::sleep_until(start + Duration::from_micros(offset)).await;
+    }
+}
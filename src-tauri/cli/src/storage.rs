@@ -0,0 +1,81 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use free_voip_core::{storage::Storage, FreeVoipError};
+use serde_json::{Map, Value};
+
+fn store_error(e: std::io::Error) -> FreeVoipError {
+    FreeVoipError::StoreError(e.to_string())
+}
+
+/// [`Storage`] keeping each store as a JSON object in its own file, the same layout as the GUI's
+/// store files.
+pub struct FileStorage {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileStorage {
+    pub fn new(dir: &Path) -> Result<Self, FreeVoipError> {
+        std::fs::create_dir_all(dir).map_err(store_error)?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn read(&self, store: &str) -> Result<Map<String, Value>, FreeVoipError> {
+        match std::fs::read(self.dir.join(store)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Map::new()),
+            Err(e) => Err(store_error(e)),
+        }
+    }
+
+    fn write(&self, store: &str, values: &Map<String, Value>) -> Result<(), FreeVoipError> {
+        let bytes = serde_json::to_vec_pretty(values)?;
+        std::fs::write(self.dir.join(store), bytes).map_err(store_error)
+    }
+
+    fn update(
+        &self,
+        store: &str,
+        f: impl FnOnce(&mut Map<String, Value>),
+    ) -> Result<(), FreeVoipError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut values = self.read(store)?;
+        f(&mut values);
+        self.write(store, &values)
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, store: &str, key: &str) -> Result<Option<Value>, FreeVoipError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read(store)?.remove(key))
+    }
+
+    fn set(&self, store: &str, key: &str, value: Value) -> Result<(), FreeVoipError> {
+        self.update(store, |values| {
+            values.insert(key.to_owned(), value);
+        })
+    }
+
+    fn delete(&self, store: &str, key: &str) -> Result<(), FreeVoipError> {
+        self.update(store, |values| {
+            values.remove(key);
+        })
+    }
+
+    fn entries(&self, store: &str) -> Result<Vec<(String, Value)>, FreeVoipError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read(store)?.into_iter().collect())
+    }
+
+    fn clear(&self, store: &str) -> Result<(), FreeVoipError> {
+        self.update(store, |values| values.clear())
+    }
+}
@@ -0,0 +1,82 @@
+//! Identity backups, so that an identity survives a reinstall: either a mnemonic phrase of the
+//! secret key, or a passphrase-encrypted file with the key and contacts.
+
+use bip39::Mnemonic;
+use iroh::SecretKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{contacts::ContactTicket, keystore::Sealed, FreeVoipError};
+
+/// Version of the [`BackupFile`] contents.
+const BACKUP_VERSION: u8 = 1;
+
+/// The 24 words encoding `secret_key`.
+pub fn to_mnemonic(secret_key: &SecretKey) -> String {
+    Mnemonic::from_entropy(&secret_key.to_bytes())
+        .expect("32 bytes are valid entropy")
+        .to_string()
+}
+
+pub fn from_mnemonic(phrase: &str) -> Result<SecretKey, FreeVoipError> {
+    let mnemonic = Mnemonic::parse_normalized(phrase).map_err(|_| FreeVoipError::InvalidBackup)?;
+    let entropy: [u8; 32] = mnemonic
+        .to_entropy()
+        .try_into()
+        .map_err(|_| FreeVoipError::InvalidBackup)?;
+    Ok(SecretKey::from_bytes(&entropy))
+}
+
+/// Everything needed to restore an identity on another device.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    pub self_ticket: ContactTicket,
+    pub secret_key: SecretKey,
+    pub contacts: Vec<ContactTicket>,
+}
+
+/// What a backup file contains: a versioned, sealed [`Backup`].
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    version: u8,
+    sealed: Sealed,
+}
+
+impl Backup {
+    /// The contents of a backup file, encrypted with `passphrase`.
+    pub fn encrypt(&self, passphrase: &str) -> Result<String, FreeVoipError> {
+        let file = BackupFile {
+            version: BACKUP_VERSION,
+            sealed: Sealed::seal(&serde_json::to_vec(self)?, passphrase)?,
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    pub fn decrypt(contents: &str, passphrase: &str) -> Result<Self, FreeVoipError> {
+        let file = serde_json::from_str::<BackupFile>(contents)
+            .map_err(|_| FreeVoipError::InvalidBackup)?;
+        if file.version != BACKUP_VERSION {
+            return Err(FreeVoipError::InvalidBackup);
+        }
+
+        serde_json::from_slice(&file.sealed.open(passphrase)?)
+            .map_err(|_| FreeVoipError::InvalidBackup)
+    }
+}
+
+/// Where to restore an identity from.
+#[derive(Debug, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum BackupSource {
+    /// A mnemonic only holds the key, so the nickname has to be chosen again.
+    Mnemonic { phrase: String, nickname: String },
+    /// The contents of a backup file and the passphrase it was encrypted with.
+    File {
+        contents: String,
+        passphrase: String,
+    },
+}
@@ -0,0 +1,553 @@
+use crate::{
+    contacts::ContactTicket,
+    error::{connection_lost, FreeVoipError},
+    history::{CallDirection, CallOutcome, CallRecord, PendingCall},
+    logging::remote_peer,
+};
+use iroh::{
+    endpoint::{Connection, ConnectionType, RecvStream, SendStream},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr, NodeId, RelayUrl, Watcher,
+};
+use iroh_base::ticket::Ticket;
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{
+        broadcast,
+        broadcast::error::{RecvError, TryRecvError},
+        mpsc, Mutex, Notify,
+    },
+};
+use tracing::{debug, field, info, instrument, warn, Instrument, Span};
+
+pub const ALPN: &[u8] = b"free-voip/call";
+
+const RESPONSE_ACCEPT: u8 = 1;
+const RESPONSE_DECLINE: u8 = 0;
+const RESPONSE_BUSY: u8 = 2;
+const RESPONSE_NO_ANSWER: u8 = 3;
+
+/// How long a ring is shown to the user before it counts as missed.
+const RING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An encoded frame as handed out by the GUI's WebCodecs encoders.
+///
+/// Timestamps and durations are in microseconds, like WebCodecs' own.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum CallMedia {
+    Video {
+        #[serde(rename = "type")]
+        frame_type: String,
+        timestamp: u64,
+        duration: Option<u64>,
+        byte_length: u64,
+        frame_data: Vec<u8>,
+    },
+    Audio {
+        #[serde(rename = "type")]
+        frame_type: String,
+        timestamp: u64,
+        duration: Option<u64>,
+        byte_length: u64,
+        frame_data: Vec<u8>,
+    },
+}
+
+/// Configuration of the GUI's AAC audio encoder, needed to decode its frames.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioConfig {
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            channels: 1,
+        }
+    }
+}
+
+/// Out-of-band messages about the call, each sent on its own unidirectional stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum CallControl {
+    /// The peer started or stopped recording the call.
+    Recording { active: bool },
+    /// How the peer's audio is encoded, sent once its encoder is configured.
+    AudioConfig(AudioConfig),
+}
+
+/// Upper bound on the size of a serialized [`CallControl`].
+const MAX_CONTROL_SIZE: usize = 1024;
+
+/// The path of an ongoing call.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallStats {
+    /// The peer's address, if media goes to them directly.
+    pub direct_addr: Option<SocketAddr>,
+    /// The relay media goes through, if any.
+    pub relay_url: Option<RelayUrl>,
+    pub rtt_ms: f64,
+    /// Whether our IP address is kept from the peer, so media only ever goes through the relay.
+    pub ip_hidden: bool,
+}
+
+#[derive(Debug)]
+pub struct CallProtocol {
+    ring_tx: broadcast::Sender<ContactTicket>,
+    response_rx: Mutex<broadcast::Receiver<bool>>,
+    in_media_tx: broadcast::Sender<CallMedia>,
+    out_media_rx: broadcast::Receiver<CallMedia>,
+    hang_up_tx: broadcast::Sender<()>,
+    extra_stream_tx: mpsc::UnboundedSender<(NodeId, SendStream, RecvStream)>,
+    history_tx: broadcast::Sender<CallRecord>,
+    control_tx: broadcast::Sender<CallControl>,
+    connection: Arc<Mutex<Option<Connection>>>,
+    /// The answered call on the connection with the given stable ID.
+    active_call: Arc<Mutex<Option<(usize, PendingCall)>>>,
+    ring_cancel: Arc<Notify>,
+}
+
+impl Clone for CallProtocol {
+    fn clone(&self) -> Self {
+        let response_rx = self.response_rx.try_lock().unwrap();
+
+        Self {
+            ring_tx: self.ring_tx.clone(),
+            response_rx: Mutex::new(response_rx.resubscribe()),
+            in_media_tx: self.in_media_tx.clone(),
+            out_media_rx: self.out_media_rx.resubscribe(),
+            hang_up_tx: self.hang_up_tx.clone(),
+            extra_stream_tx: self.extra_stream_tx.clone(),
+            history_tx: self.history_tx.clone(),
+            control_tx: self.control_tx.clone(),
+            connection: self.connection.clone(),
+            active_call: self.active_call.clone(),
+            ring_cancel: self.ring_cancel.clone(),
+        }
+    }
+}
+
+impl CallProtocol {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ring_tx: broadcast::Sender<ContactTicket>,
+        response_rx: broadcast::Receiver<bool>,
+        in_media_tx: broadcast::Sender<CallMedia>,
+        out_media_rx: broadcast::Receiver<CallMedia>,
+        hang_up_tx: broadcast::Sender<()>,
+        extra_stream_tx: mpsc::UnboundedSender<(NodeId, SendStream, RecvStream)>,
+        history_tx: broadcast::Sender<CallRecord>,
+        control_tx: broadcast::Sender<CallControl>,
+    ) -> Self {
+        Self {
+            ring_tx,
+            response_rx: Mutex::new(response_rx),
+            in_media_tx,
+            out_media_rx,
+            hang_up_tx,
+            extra_stream_tx,
+            history_tx,
+            control_tx,
+            connection: Arc::new(Mutex::new(None)),
+            active_call: Arc::new(Mutex::new(None)),
+            ring_cancel: Arc::new(Notify::new()),
+        }
+    }
+
+    /// The connection of the ongoing call, if any.
+    pub async fn connection(&self) -> Option<Connection> {
+        self.connection.lock().await.clone()
+    }
+
+    /// How the ongoing call reaches the peer through `endpoint`.
+    pub async fn stats(
+        &self,
+        endpoint: &Endpoint,
+        ip_hidden: bool,
+    ) -> Result<CallStats, FreeVoipError> {
+        let conn = self
+            .connection()
+            .await
+            .ok_or_else(|| FreeVoipError::invalid_state("Not in a call"))?;
+        let peer = conn.remote_node_id()?;
+
+        let (direct_addr, relay_url) = match endpoint.conn_type(peer).map(|mut c| c.get()) {
+            Some(ConnectionType::Direct(addr)) => (Some(addr), None),
+            Some(ConnectionType::Relay(url)) => (None, Some(url)),
+            Some(ConnectionType::Mixed(addr, url)) => (Some(addr), Some(url)),
+            Some(ConnectionType::None) | None => (None, None),
+        };
+        Ok(CallStats {
+            direct_addr,
+            relay_url,
+            rtt_ms: conn.rtt().as_secs_f64() * 1000.0,
+            ip_hidden,
+        })
+    }
+
+    /// Sends a control message to the peer of the ongoing call.
+    pub async fn send_control(&self, control: &CallControl) -> Result<(), FreeVoipError> {
+        let conn = self
+            .connection()
+            .await
+            .ok_or_else(|| FreeVoipError::invalid_state("Not in a call"))?;
+        let mut control_tx = conn.open_uni().await?;
+
+        let serialized_control = postcard::to_stdvec(control)?;
+        control_tx.write_all(&serialized_control).await?;
+        control_tx.finish()?;
+
+        Ok(())
+    }
+
+    async fn start_media_tasks(&self, conn: Connection, self_is_ringer: bool, call: PendingCall) {
+        // TODO: propagate errors to GUI
+
+        let stream = if self_is_ringer {
+            conn.open_bi().await
+        } else {
+            conn.accept_bi().await
+        };
+
+        if let Err(err) = stream {
+            _ = self
+                .history_tx
+                .send(call.finish(CallOutcome::Failed(err.to_string())));
+
+            if self_is_ringer {
+                warn!("Failed to open media stream: {err}");
+                conn.close(1u32.into(), b"Failed to open media stream");
+            } else {
+                warn!("Failed to accept media stream: {err}");
+                conn.closed().await;
+            }
+            return;
+        }
+        let (mut proto_tx, mut proto_rx) = stream.unwrap();
+
+        // Set connection state
+        let stable_id = conn.stable_id();
+        {
+            let mut conn_state = self.connection.lock().await;
+            *conn_state = Some(conn.clone());
+            *self.active_call.lock().await = Some((stable_id, call));
+        }
+
+        // Control messages from the peer
+        let control_conn = conn.clone();
+        let control_tx = self.control_tx.clone();
+        tokio::spawn(
+            async move {
+                while let Ok(mut control_rx) = control_conn.accept_uni().await {
+                    let control = match control_rx.read_to_end(MAX_CONTROL_SIZE).await {
+                        Ok(buf) => postcard::from_bytes::<CallControl>(&buf),
+                        Err(err) => {
+                            warn!("Failed to read call control message: {err}");
+                            continue;
+                        }
+                    };
+
+                    match control {
+                        Ok(control) => _ = control_tx.send(control),
+                        Err(err) => warn!("Received invalid call control message: {err}"),
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+
+        // Any further streams the peer opens (e.g. file transfers) are handed off
+        if let Ok(peer) = conn.remote_node_id() {
+            let extra_stream_tx = self.extra_stream_tx.clone();
+            tokio::spawn(
+                async move {
+                    while let Ok((stream_tx, stream_rx)) = conn.accept_bi().await {
+                        if extra_stream_tx.send((peer, stream_tx, stream_rx)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+
+        // Prime the lazy QUIC stream
+        if self_is_ringer {
+            proto_tx.write_u8(0).await.unwrap();
+        } else {
+            assert_eq!(proto_rx.read_u8().await.unwrap(), 0);
+        }
+
+        let in_media_tx = self.in_media_tx.clone();
+        let mut out_media_rx = self.out_media_rx.resubscribe();
+
+        // Incoming media
+        let hang_up_clone = self.hang_up_tx.clone();
+        let call_state = self.clone_call_state();
+        tokio::spawn(
+            async move {
+                while let Ok(num_bytes) = proto_rx.read_u32().await {
+                    let mut buf = vec![0u8; num_bytes as usize];
+
+                    if let Err(err) = proto_rx.read_exact(&mut buf).await {
+                        warn!("Encountered error reading media data from network: {err}");
+                        break;
+                    }
+
+                    let media = postcard::from_bytes::<CallMedia>(&buf).unwrap();
+                    if let Err(err) = in_media_tx.send(media) {
+                        warn!("Encountered error sending incoming media to GUI: {err}");
+                        break;
+                    }
+                }
+
+                debug!("Exited incoming media loop");
+                end_call(call_state, stable_id).await;
+                _ = hang_up_clone.send(());
+            }
+            .in_current_span(),
+        );
+
+        // Outgoing media
+        let hang_up_clone = self.hang_up_tx.clone();
+        let call_state = self.clone_call_state();
+        tokio::spawn(
+            async move {
+                loop {
+                    let media = match out_media_rx.recv().await {
+                        Ok(media) => media,
+                        // The network can't keep up, skip the frames that were dropped
+                        Err(RecvError::Lagged(n)) => {
+                            debug!("Dropped {n} outgoing media frames");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    let media_serialized = postcard::to_stdvec(&media).unwrap();
+
+                    if let Err(err) = proto_tx.write_u32(media_serialized.len() as u32).await {
+                        warn!("Encountered error writing media size to network: {err}");
+                        break;
+                    }
+
+                    if let Err(err) = proto_tx.write_all(&media_serialized).await {
+                        warn!("Encountered error writing media data to network: {err}");
+                        break;
+                    }
+                }
+
+                debug!("Exited outgoing media loop");
+                end_call(call_state, stable_id).await;
+                _ = hang_up_clone.send(());
+            }
+            .in_current_span(),
+        );
+    }
+
+    fn clone_call_state(&self) -> CallState {
+        (
+            self.connection.clone(),
+            self.active_call.clone(),
+            self.history_tx.clone(),
+        )
+    }
+
+    #[instrument(name = "call", skip_all, fields(direction = "outgoing", peer = field::Empty))]
+    pub async fn ring(
+        &self,
+        endpoint: &Endpoint,
+        recipient_addr: impl Into<NodeAddr>,
+        self_ticket: &ContactTicket,
+    ) -> Result<bool, FreeVoipError> {
+        let recipient_addr = recipient_addr.into();
+        Span::current().record("peer", field::display(recipient_addr.node_id));
+        let call = PendingCall::new(recipient_addr.node_id, CallDirection::Outgoing);
+        info!("Ringing");
+
+        // Hanging up while still ringing cancels the ring
+        let ring_cancelled = self.ring_cancel.notified();
+        let ring_result = tokio::select! {
+            ring_result = Self::send_ring(endpoint, recipient_addr, self_ticket) => ring_result,
+            _ = ring_cancelled => {
+                _ = self.history_tx.send(call.finish(CallOutcome::Cancelled));
+                return Ok(false);
+            }
+        };
+
+        let (conn, response) = match ring_result {
+            Ok(ring_result) => ring_result,
+            Err(e) => {
+                warn!("Failed to ring: {e}");
+                _ = self
+                    .history_tx
+                    .send(call.finish(CallOutcome::Failed(e.to_string())));
+                return Err(e);
+            }
+        };
+
+        if response == RESPONSE_ACCEPT {
+            info!("Call answered");
+            self.start_media_tasks(conn, true, call.answered()).await;
+            return Ok(true);
+        }
+
+        let outcome = match response {
+            RESPONSE_BUSY => CallOutcome::Busy,
+            RESPONSE_NO_ANSWER => CallOutcome::Missed,
+            _ => CallOutcome::Declined,
+        };
+        info!(?outcome, "Call not answered");
+        conn.close(0u32.into(), b"Ring request complete");
+        _ = self.history_tx.send(call.finish(outcome));
+
+        Ok(false)
+    }
+
+    async fn send_ring(
+        endpoint: &Endpoint,
+        recipient_addr: NodeAddr,
+        self_ticket: &ContactTicket,
+    ) -> Result<(Connection, u8), FreeVoipError> {
+        let conn = endpoint.connect(recipient_addr, ALPN).await?;
+        let (mut proto_tx, mut proto_rx) = conn.open_bi().await?;
+
+        // Identify ourself with recipient
+        proto_tx.write_all(&Ticket::to_bytes(self_ticket)).await?;
+
+        // Wait for ring response, with some slack for the recipient's own timeout
+        let response = match tokio::time::timeout(RING_TIMEOUT * 2, proto_rx.read_u8()).await {
+            Ok(response) => response.map_err(connection_lost)?,
+            Err(_) => RESPONSE_NO_ANSWER,
+        };
+
+        Ok((conn, response))
+    }
+
+    pub async fn disconnect(&self) -> bool {
+        self.ring_cancel.notify_waiters();
+
+        let conn = self.connection.lock().await.clone();
+        match conn {
+            Some(conn) => {
+                conn.close(0u32.into(), b"Hanging up");
+                conn.closed().await;
+                end_call(self.clone_call_state(), conn.stable_id()).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Shows the ring to the user unless already in a call, giving up after [`RING_TIMEOUT`].
+    async fn ask_user(
+        &self,
+        connection: &Connection,
+        ticket: ContactTicket,
+    ) -> Result<u8, AcceptError> {
+        if self.connection.lock().await.is_some() {
+            return Ok(RESPONSE_BUSY);
+        }
+
+        // Another ring is already waiting for the user
+        let Ok(mut response_rx) = self.response_rx.try_lock() else {
+            return Ok(RESPONSE_BUSY);
+        };
+
+        // Discard answers to earlier rings that arrived after those timed out
+        while let Ok(_) | Err(TryRecvError::Lagged(_)) = response_rx.try_recv() {}
+
+        self.ring_tx.send(ticket).map_err(AcceptError::from_err)?;
+        tokio::select! {
+            gui_response = response_rx.recv() => {
+                if gui_response.map_err(AcceptError::from_err)? {
+                    Ok(RESPONSE_ACCEPT)
+                } else {
+                    Ok(RESPONSE_DECLINE)
+                }
+            }
+            _ = tokio::time::sleep(RING_TIMEOUT) => Ok(RESPONSE_NO_ANSWER),
+            // Caller gave up
+            _ = connection.closed() => Ok(RESPONSE_NO_ANSWER),
+        }
+    }
+}
+
+type CallState = (
+    Arc<Mutex<Option<Connection>>>,
+    Arc<Mutex<Option<(usize, PendingCall)>>>,
+    broadcast::Sender<CallRecord>,
+);
+
+/// Clears the state of the call on connection `stable_id` and records it, unless already done.
+async fn end_call((connection, active_call, history_tx): CallState, stable_id: usize) {
+    let mut conn_state = connection.lock().await;
+    if conn_state
+        .as_ref()
+        .is_some_and(|c| c.stable_id() == stable_id)
+    {
+        *conn_state = None;
+    }
+
+    let mut active_call = active_call.lock().await;
+    if active_call.as_ref().is_some_and(|(id, _)| *id == stable_id) {
+        if let Some((_, call)) = active_call.take() {
+            _ = history_tx.send(call.finish(CallOutcome::Answered));
+        }
+    }
+}
+
+impl ProtocolHandler for CallProtocol {
+    #[instrument(
+        name = "call",
+        skip_all,
+        fields(direction = "incoming", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let (mut proto_tx, mut proto_rx) = connection.accept_bi().await?;
+
+        // Identify caller
+        let ticket = {
+            let mut buf = Vec::<u8>::new();
+            proto_rx.read_buf(&mut buf).await?;
+            <ContactTicket as Ticket>::from_bytes(&buf).map_err(AcceptError::from_err)?
+        };
+
+        let call = PendingCall::new(connection.remote_node_id()?, CallDirection::Incoming);
+
+        // Display call UI and get user's response
+        info!("Incoming call");
+        let response = self.ask_user(&connection, ticket).await?;
+
+        // Send response back to caller
+        debug!(response, "Answering ring");
+        let write_result = proto_tx.write_u8(response).await;
+
+        if response == RESPONSE_ACCEPT {
+            write_result?;
+            info!("Call answered");
+            self.start_media_tasks(connection, false, call.answered())
+                .await;
+        } else {
+            let outcome = match response {
+                RESPONSE_BUSY => CallOutcome::Busy,
+                RESPONSE_DECLINE => CallOutcome::Declined,
+                _ => CallOutcome::Missed,
+            };
+            info!(?outcome, "Call not answered");
+            _ = self.history_tx.send(call.finish(outcome));
+            connection.closed().await;
+        }
+
+        Ok(())
+    }
+}
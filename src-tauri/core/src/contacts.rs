@@ -0,0 +1,340 @@
+use iroh::{
+    endpoint::{ConnectError, Connection, ConnectionError, TransportErrorCode},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr, NodeId,
+};
+use iroh_base::ticket::{ParseError as TicketParseError, Ticket};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{
+        broadcast::{Receiver, Sender},
+        Mutex,
+    },
+};
+use tracing::{field, info, instrument, warn, Span};
+
+use crate::{
+    error::{connection_lost, FreeVoipError},
+    invite::{InviteBook, InviteTicket},
+    logging::remote_peer,
+};
+
+pub const ALPN: &[u8] = b"free-voip/contacts/1";
+/// The protocol before [`ContactsMessage`], which only carries serialized contact tickets.
+pub const LEGACY_ALPN: &[u8] = b"free-voip/contacts";
+
+const RESPONSE_ACCEPT: u8 = 1;
+const RESPONSE_DECLINE: u8 = 0;
+
+/// TLS alert peers reject connections with when they don't speak the ALPN.
+const NO_APPLICATION_PROTOCOL: u8 = 120;
+
+/// Upper bound on the size of a single contacts protocol message.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactTicket {
+    pub nickname: String,
+    pub node_id: NodeId,
+    /// Relay URL and direct addresses the node was last known at, so that it can be reached
+    /// without waiting for discovery.
+    #[serde(default)]
+    pub addr: Option<NodeAddr>,
+}
+
+/// A [`ContactTicket`] as serialized before it carried addresses.
+#[derive(Deserialize)]
+struct LegacyContactTicket {
+    nickname: String,
+    node_id: NodeId,
+}
+
+impl ContactTicket {
+    pub fn new(nickname: String, node_id: NodeId) -> Self {
+        Self {
+            nickname,
+            node_id,
+            addr: None,
+        }
+    }
+
+    /// Where to connect to the node: the known addresses if there are any, discovery fills in
+    /// the rest.
+    pub fn node_addr(&self) -> NodeAddr {
+        match &self.addr {
+            Some(addr) if addr.node_id == self.node_id => addr.clone(),
+            _ => NodeAddr::new(self.node_id),
+        }
+    }
+}
+
+impl Ticket for ContactTicket {
+    const KIND: &'static str = "node";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).expect("Postcard serializtion should be infallible")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, TicketParseError> {
+        // Older tickets end before the addresses
+        postcard::from_bytes(bytes).or_else(|_| {
+            let legacy = postcard::from_bytes::<LegacyContactTicket>(bytes)?;
+            Ok(Self::new(legacy.nickname, legacy.node_id))
+        })
+    }
+}
+
+/// A single message exchanged over the contacts protocol.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum ContactsMessage {
+    /// Ask the recipient to add the sender as a contact.
+    Request(ContactTicket),
+    /// Ask the recipient to add the sender as a contact, with an invite the recipient issued.
+    InvitedRequest {
+        contact: ContactTicket,
+        invite: InviteTicket,
+    },
+    /// Tell the recipient that the sender tried to call while they were offline.
+    MissedCall {
+        caller: ContactTicket,
+        timestamp: u64,
+    },
+    /// Tell the recipient that the sender's profile changed, so that they can update their
+    /// contact and fetch the avatar with the given hash if they don't have it yet.
+    ProfileUpdate {
+        contact: ContactTicket,
+        avatar: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub struct ContactsProtocol {
+    request_tx: Sender<ContactTicket>,
+    response_rx: Mutex<Receiver<bool>>,
+    missed_call_tx: Sender<(ContactTicket, u64)>,
+    profile_update_tx: Sender<(ContactTicket, Option<String>)>,
+    invites: InviteBook,
+    /// Contacts accepted because they came with a valid invite.
+    invited_tx: Sender<ContactTicket>,
+}
+
+impl ContactsProtocol {
+    pub fn new(
+        request_tx: Sender<ContactTicket>,
+        response_rx: Receiver<bool>,
+        missed_call_tx: Sender<(ContactTicket, u64)>,
+        profile_update_tx: Sender<(ContactTicket, Option<String>)>,
+        invites: InviteBook,
+        invited_tx: Sender<ContactTicket>,
+    ) -> Self {
+        Self {
+            request_tx,
+            response_rx: Mutex::new(response_rx),
+            missed_call_tx,
+            profile_update_tx,
+            invites,
+            invited_tx,
+        }
+    }
+
+    #[instrument(name = "contact_request", skip_all, fields(direction = "outgoing"))]
+    pub async fn send_request(
+        endpoint: &Endpoint,
+        recipient_addr: impl Into<NodeAddr>,
+        sender_ticket: &ContactTicket,
+    ) -> Result<bool, FreeVoipError> {
+        let message = ContactsMessage::Request(sender_ticket.clone());
+        Self::send_message(endpoint, recipient_addr, &message, || {}).await
+    }
+
+    /// Sends a contact request with an invite from the recipient, which they accept without
+    /// asking as long as the invite is valid.
+    #[instrument(name = "contact_request", skip_all, fields(direction = "outgoing"))]
+    pub async fn send_invited_request(
+        endpoint: &Endpoint,
+        sender_ticket: &ContactTicket,
+        invite: &InviteTicket,
+    ) -> Result<bool, FreeVoipError> {
+        let message = ContactsMessage::InvitedRequest {
+            contact: sender_ticket.clone(),
+            invite: invite.clone(),
+        };
+        Self::send_message(endpoint, invite.issuer.node_addr(), &message, || {}).await
+    }
+
+    /// Delivers `message` and waits for the recipient's response.
+    ///
+    /// `on_sent` is called once the message has been written to the recipient, before the
+    /// response arrives. For notices the response is an acknowledgement and is always `true`.
+    #[instrument(name = "connection", skip_all, fields(protocol = "contacts", peer = field::Empty))]
+    pub async fn send_message(
+        endpoint: &Endpoint,
+        recipient_addr: impl Into<NodeAddr>,
+        message: &ContactsMessage,
+        on_sent: impl FnOnce(),
+    ) -> Result<bool, FreeVoipError> {
+        let recipient_addr = recipient_addr.into();
+        Span::current().record("peer", field::display(recipient_addr.node_id));
+
+        let (connection, legacy) = Self::connect(endpoint, recipient_addr).await?;
+        let (mut proto_tx, mut proto_rx) = connection.open_bi().await?;
+
+        // Send the message
+        let serialized_message = if legacy {
+            let ticket = match message {
+                ContactsMessage::Request(contact)
+                | ContactsMessage::InvitedRequest { contact, .. } => contact,
+                // Notices mean nothing to older peers, so there is nothing to deliver
+                ContactsMessage::MissedCall { .. } | ContactsMessage::ProfileUpdate { .. } => {
+                    info!("Dropping notice for peer on the legacy contacts protocol");
+                    connection.close(0u32.into(), b"Unsupported");
+                    return Ok(false);
+                }
+            };
+            // Older peers ignore the trailing addresses, which they can't use anyway
+            let ticket = ContactTicket::new(ticket.nickname.clone(), ticket.node_id);
+            Ticket::serialize(&ticket).into_bytes()
+        } else {
+            postcard::to_stdvec(message)?
+        };
+        proto_tx.write_all(&serialized_message).await?;
+        proto_tx.finish()?;
+        on_sent();
+
+        // Listen for accept/decline response
+        let response = proto_rx.read_u8().await.map_err(connection_lost)?;
+        connection.close(0u32.into(), b"Contact request complete");
+        Ok(response == RESPONSE_ACCEPT)
+    }
+
+    /// Connects with the current protocol, or the legacy one if the peer only speaks that,
+    /// returning whether it is the legacy one.
+    async fn connect(
+        endpoint: &Endpoint,
+        addr: NodeAddr,
+    ) -> Result<(Connection, bool), FreeVoipError> {
+        match endpoint.connect(addr.clone(), ALPN).await {
+            Ok(connection) => Ok((connection, false)),
+            Err(ConnectError::Connection { source, .. })
+                if matches!(
+                    *source,
+                    ConnectionError::ConnectionClosed(ref close)
+                        if close.error_code == TransportErrorCode::crypto(NO_APPLICATION_PROTOCOL)
+                ) =>
+            {
+                info!("Peer only speaks the legacy contacts protocol");
+                Ok((endpoint.connect(addr, LEGACY_ALPN).await?, true))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(name = "contact_request", skip_all, fields(direction = "incoming"))]
+    async fn handle_request(&self, contact_ticket: ContactTicket) -> Result<u8, AcceptError> {
+        info!(nickname = ?contact_ticket.nickname, "Received contact request");
+
+        let mut response_rx = self.response_rx.lock().await;
+        self.request_tx
+            .send(contact_ticket)
+            .map_err(AcceptError::from_err)?;
+        let user_response = response_rx.recv().await.map_err(AcceptError::from_err)?;
+
+        if user_response {
+            Ok(RESPONSE_ACCEPT)
+        } else {
+            Ok(RESPONSE_DECLINE)
+        }
+    }
+}
+
+impl ProtocolHandler for ContactsProtocol {
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "contacts", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let (mut proto_tx, mut proto_rx) = connection.accept_bi().await?;
+
+        // Retrieve connecting side's message
+        let message = {
+            let buf = proto_rx
+                .read_to_end(MAX_MESSAGE_SIZE)
+                .await
+                .map_err(AcceptError::from_err)?;
+            if connection.alpn().as_deref() == Some(LEGACY_ALPN) {
+                let serialized_ticket = std::str::from_utf8(&buf).map_err(AcceptError::from_err)?;
+                let ticket = <ContactTicket as Ticket>::deserialize(serialized_ticket)
+                    .map_err(AcceptError::from_err)?;
+                ContactsMessage::Request(ticket)
+            } else {
+                postcard::from_bytes::<ContactsMessage>(&buf).map_err(AcceptError::from_err)?
+            }
+        };
+        let remote_node_id = connection.remote_node_id()?;
+
+        let response = match message {
+            ContactsMessage::Request(contact_ticket) => self.handle_request(contact_ticket).await?,
+            ContactsMessage::InvitedRequest { contact, invite } => {
+                if contact.node_id != remote_node_id {
+                    return Err(AcceptError::NotAllowed {});
+                }
+
+                match self.invites.redeem(&invite) {
+                    Ok(()) => {
+                        info!(nickname = ?contact.nickname, "Accepted invited contact request");
+                        self.invited_tx
+                            .send(contact)
+                            .map_err(AcceptError::from_err)?;
+                        RESPONSE_ACCEPT
+                    }
+                    // Without a valid invite it is up to the user, like any other request
+                    Err(e) => {
+                        warn!("Ignoring invite: {e}");
+                        self.handle_request(contact).await?
+                    }
+                }
+            }
+            ContactsMessage::MissedCall { caller, timestamp } => {
+                // Notices must come from the node they claim to be from
+                if caller.node_id != remote_node_id {
+                    return Err(AcceptError::NotAllowed {});
+                }
+
+                self.missed_call_tx
+                    .send((caller, timestamp))
+                    .map_err(AcceptError::from_err)?;
+                RESPONSE_ACCEPT
+            }
+            ContactsMessage::ProfileUpdate { contact, avatar } => {
+                let valid_avatar = avatar
+                    .as_ref()
+                    .is_none_or(|hash| blake3::Hash::from_hex(hash).is_ok());
+                if contact.node_id != remote_node_id || !valid_avatar {
+                    return Err(AcceptError::NotAllowed {});
+                }
+
+                info!(nickname = ?contact.nickname, "Received profile update");
+                self.profile_update_tx
+                    .send((contact, avatar))
+                    .map_err(AcceptError::from_err)?;
+                RESPONSE_ACCEPT
+            }
+        };
+
+        // Send user's response to requester
+        proto_tx
+            .write_u8(response)
+            .await
+            .map_err(AcceptError::from_err)?;
+
+        // Finished here
+        proto_tx.finish()?;
+        connection.closed().await;
+        Ok(())
+    }
+}
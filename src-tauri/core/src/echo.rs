@@ -0,0 +1,200 @@
+//! Echo service for testing calls, like a test call: the bot answers every ring and sends each media
+//! frame straight back, while the caller measures the round-trip latency and loss of the echoes.
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::Serialize;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{debug, info, warn};
+
+use crate::{call::CallMedia, Event, FreeVoipError, Node};
+
+/// Frames that have not come back after this long are counted as lost.
+pub const LOSS_TIMEOUT: Duration = Duration::from_secs(5);
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Round-trip statistics of an echo test. Latencies are `None` until the first echo arrives.
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EchoStats {
+    pub sent: u64,
+    pub echoed: u64,
+    pub lost: u64,
+    /// Share of the frames that are no longer in flight and were lost, from 0 to 1.
+    pub loss_ratio: f64,
+    pub last_rtt_ms: Option<f64>,
+    pub min_rtt_ms: Option<f64>,
+    pub avg_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+}
+
+/// Frames are matched with their echoes by kind and timestamp, which the echo keeps.
+fn frame_key(media: &CallMedia) -> (bool, u64) {
+    match media {
+        CallMedia::Video { timestamp, .. } => (true, *timestamp),
+        CallMedia::Audio { timestamp, .. } => (false, *timestamp),
+    }
+}
+
+/// Matches sent frames with their echoes.
+#[derive(Debug, Default)]
+pub struct EchoMonitor {
+    in_flight: HashMap<(bool, u64), Instant>,
+    sent: u64,
+    echoed: u64,
+    lost: u64,
+    rtt_sum: Duration,
+    rtt_last: Option<Duration>,
+    rtt_min: Option<Duration>,
+    rtt_max: Option<Duration>,
+}
+
+impl EchoMonitor {
+    pub fn on_sent(&mut self, media: &CallMedia) {
+        self.sent += 1;
+        self.in_flight.insert(frame_key(media), Instant::now());
+    }
+
+    /// Frames that were not sent by us, or already counted as lost, are ignored.
+    pub fn on_echoed(&mut self, media: &CallMedia) {
+        let Some(sent_at) = self.in_flight.remove(&frame_key(media)) else {
+            return;
+        };
+        let rtt = sent_at.elapsed();
+
+        self.echoed += 1;
+        self.rtt_sum += rtt;
+        self.rtt_last = Some(rtt);
+        self.rtt_min = Some(self.rtt_min.map_or(rtt, |min| min.min(rtt)));
+        self.rtt_max = Some(self.rtt_max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    pub fn stats(&mut self) -> EchoStats {
+        // Give up on frames that took too long
+        let before = self.in_flight.len();
+        self.in_flight
+            .retain(|_, sent_at| sent_at.elapsed() < LOSS_TIMEOUT);
+        self.lost += (before - self.in_flight.len()) as u64;
+
+        let to_ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let completed = self.echoed + self.lost;
+        EchoStats {
+            sent: self.sent,
+            echoed: self.echoed,
+            lost: self.lost,
+            loss_ratio: match completed {
+                0 => 0.0,
+                _ => self.lost as f64 / completed as f64,
+            },
+            last_rtt_ms: self.rtt_last.map(to_ms),
+            min_rtt_ms: self.rtt_min.map(to_ms),
+            avg_rtt_ms: (self.echoed > 0).then(|| to_ms(self.rtt_sum) / self.echoed as f64),
+            max_rtt_ms: self.rtt_max.map(to_ms),
+        }
+    }
+}
+
+/// Runs an [`EchoMonitor`] over a call's media, reporting its statistics every second.
+#[derive(Debug)]
+pub struct EchoTest {
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<EchoStats>,
+}
+
+impl EchoTest {
+    pub fn start(
+        mut outgoing_rx: broadcast::Receiver<CallMedia>,
+        mut incoming_rx: broadcast::Receiver<CallMedia>,
+        stats_tx: broadcast::Sender<EchoStats>,
+    ) -> Self {
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+
+        let task = tokio::spawn(async move {
+            let mut monitor = EchoMonitor::default();
+            let mut report = tokio::time::interval(REPORT_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    media = outgoing_rx.recv() => match media {
+                        Ok(media) => monitor.on_sent(&media),
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                    media = incoming_rx.recv() => match media {
+                        Ok(media) => monitor.on_echoed(&media),
+                        Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = report.tick() => {
+                        _ = stats_tx.send(monitor.stats());
+                    }
+                    _ = &mut stop_rx => break,
+                }
+            }
+            monitor.stats()
+        });
+
+        Self { stop_tx, task }
+    }
+
+    pub async fn stop(self) -> Result<EchoStats, FreeVoipError> {
+        _ = self.stop_tx.send(());
+        self.task.await.map_err(Into::into)
+    }
+}
+
+/// Serves as an echo bot until cancelled: accepts every contact request, answers every ring and
+/// sends each received media frame back to the caller.
+///
+/// Rings don't need the caller to be a contact, so requesters are not added to the bot's contacts,
+/// which would otherwise grow with everyone who ever tried it.
+pub async fn serve(node: &Node) -> Result<(), FreeVoipError> {
+    let mut events = node.events();
+    let mut echoing: Option<JoinHandle<()>> = None;
+
+    loop {
+        match events.recv().await {
+            Ok(Event::ContactRequest(ticket)) => {
+                info!(nickname = ?ticket.nickname, "Accepting contact request");
+                node.respond_to_contact_request(true).await?;
+            }
+            Ok(Event::RingRequest(ticket)) => {
+                info!(nickname = ?ticket.nickname, "Echoing call");
+
+                // Subscribe before answering so that no media is missed
+                let mut media_rx = node.subscribe_call_media().await?;
+                node.respond_to_ring(true).await?;
+
+                let node = node.clone();
+                echoing = Some(tokio::spawn(async move {
+                    loop {
+                        match media_rx.recv().await {
+                            Ok(media) => {
+                                if let Err(e) = node.send_call_media(media).await {
+                                    warn!("Failed to echo media: {e}");
+                                }
+                            }
+                            Err(RecvError::Lagged(n)) => debug!("Dropped {n} media frames"),
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                }));
+            }
+            Ok(Event::CallHangUp) => {
+                if let Some(echoing) = echoing.take() {
+                    echoing.abort();
+                }
+            }
+            Ok(Event::FileOffer { .. }) => node.respond_to_file_offer(false).await?,
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
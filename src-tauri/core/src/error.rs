@@ -0,0 +1,180 @@
+use std::fmt;
+
+use iroh::endpoint::{
+    BindError, ClosedStream, ConnectError, ConnectionError, ReadError, ReadExactError,
+    ReadToEndError, RemoteNodeIdError, WriteError,
+};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use tokio::{sync::broadcast, task::JoinError, time::error::Elapsed};
+
+/// Everything that can go wrong, with a stable [`FreeVoipError::code`] for frontends to match on.
+///
+/// Serializes to `{ "code": ..., "message": ... }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FreeVoipError {
+    /// There is no identity, or it is not online yet.
+    NotLoggedIn,
+    /// No connection to the peer could be established.
+    PeerUnreachable(String),
+    /// The peer said no.
+    Declined,
+    /// The peer took too long to respond.
+    Timeout,
+    /// A contact ticket could not be parsed.
+    InvalidTicket,
+    /// Persisted data could not be read or written.
+    StoreError(String),
+    /// The peer sent something it should not have.
+    ProtocolViolation(String),
+    /// An established connection to the peer broke down.
+    ConnectionLost(String),
+    /// The operation does not make sense right now, like hanging up outside of a call.
+    InvalidState(String),
+    /// Local files could not be read or written.
+    Io(String),
+    /// The stored identity is encrypted and has not been unlocked with its passphrase yet.
+    Locked,
+    /// The passphrase does not match the one the identity was encrypted with.
+    WrongPassphrase,
+    /// A recovery phrase or backup file could not be read.
+    InvalidBackup,
+}
+
+impl FreeVoipError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FreeVoipError::NotLoggedIn => "NotLoggedIn",
+            FreeVoipError::PeerUnreachable(_) => "PeerUnreachable",
+            FreeVoipError::Declined => "Declined",
+            FreeVoipError::Timeout => "Timeout",
+            FreeVoipError::InvalidTicket => "InvalidTicket",
+            FreeVoipError::StoreError(_) => "StoreError",
+            FreeVoipError::ProtocolViolation(_) => "ProtocolViolation",
+            FreeVoipError::ConnectionLost(_) => "ConnectionLost",
+            FreeVoipError::InvalidState(_) => "InvalidState",
+            FreeVoipError::Io(_) => "Io",
+            FreeVoipError::Locked => "Locked",
+            FreeVoipError::WrongPassphrase => "WrongPassphrase",
+            FreeVoipError::InvalidBackup => "InvalidBackup",
+        }
+    }
+
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        FreeVoipError::InvalidState(message.into())
+    }
+
+    pub fn protocol_violation(message: impl Into<String>) -> Self {
+        FreeVoipError::ProtocolViolation(message.into())
+    }
+}
+
+impl fmt::Display for FreeVoipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreeVoipError::NotLoggedIn => write!(f, "Not logged in"),
+            FreeVoipError::PeerUnreachable(e) => write!(f, "Peer is unreachable: {e}"),
+            FreeVoipError::Declined => write!(f, "Declined by the peer"),
+            FreeVoipError::Timeout => write!(f, "Peer took too long to respond"),
+            FreeVoipError::InvalidTicket => write!(f, "Invalid contact ticket"),
+            FreeVoipError::StoreError(e) => write!(f, "Failed to access storage: {e}"),
+            FreeVoipError::ProtocolViolation(e) => write!(f, "Peer violated the protocol: {e}"),
+            FreeVoipError::ConnectionLost(e) => write!(f, "Connection lost: {e}"),
+            FreeVoipError::InvalidState(e) | FreeVoipError::Io(e) => write!(f, "{e}"),
+            FreeVoipError::Locked => write!(f, "Locked, enter the passphrase to unlock"),
+            FreeVoipError::WrongPassphrase => write!(f, "Wrong passphrase"),
+            FreeVoipError::InvalidBackup => write!(f, "Invalid recovery phrase or backup file"),
+        }
+    }
+}
+
+impl std::error::Error for FreeVoipError {}
+
+impl Serialize for FreeVoipError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FreeVoipError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<ConnectError> for FreeVoipError {
+    fn from(e: ConnectError) -> Self {
+        FreeVoipError::PeerUnreachable(e.to_string())
+    }
+}
+
+impl From<BindError> for FreeVoipError {
+    fn from(e: BindError) -> Self {
+        FreeVoipError::Io(e.to_string())
+    }
+}
+
+macro_rules! connection_lost_from {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for FreeVoipError {
+                fn from(e: $error) -> Self {
+                    FreeVoipError::ConnectionLost(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+connection_lost_from!(
+    ConnectionError,
+    ReadError,
+    ReadExactError,
+    ReadToEndError,
+    RemoteNodeIdError,
+    WriteError,
+    ClosedStream
+);
+
+impl From<postcard::Error> for FreeVoipError {
+    fn from(e: postcard::Error) -> Self {
+        FreeVoipError::ProtocolViolation(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for FreeVoipError {
+    fn from(e: serde_json::Error) -> Self {
+        FreeVoipError::StoreError(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for FreeVoipError {
+    fn from(e: std::io::Error) -> Self {
+        FreeVoipError::Io(e.to_string())
+    }
+}
+
+impl From<Elapsed> for FreeVoipError {
+    fn from(_: Elapsed) -> Self {
+        FreeVoipError::Timeout
+    }
+}
+
+impl From<JoinError> for FreeVoipError {
+    fn from(e: JoinError) -> Self {
+        FreeVoipError::InvalidState(e.to_string())
+    }
+}
+
+impl From<broadcast::error::RecvError> for FreeVoipError {
+    fn from(_: broadcast::error::RecvError) -> Self {
+        FreeVoipError::invalid_state("Nobody is there to respond")
+    }
+}
+
+impl<T> From<broadcast::error::SendError<T>> for FreeVoipError {
+    fn from(_: broadcast::error::SendError<T>) -> Self {
+        FreeVoipError::invalid_state("Nobody is listening for the response")
+    }
+}
+
+/// For I/O errors of QUIC streams used through tokio's I/O traits, which are not local I/O.
+pub(crate) fn connection_lost(e: std::io::Error) -> FreeVoipError {
+    FreeVoipError::ConnectionLost(e.to_string())
+}
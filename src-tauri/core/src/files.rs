@@ -0,0 +1,535 @@
+use iroh::{
+    endpoint::{Connection, RecvStream, SendStream},
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr, NodeId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::{
+        broadcast::{error::TryRecvError, Receiver, Sender},
+        watch, Mutex,
+    },
+};
+use tracing::{field, info, instrument, warn, Span};
+
+use crate::{
+    error::{connection_lost, FreeVoipError},
+    logging::remote_peer,
+};
+
+pub const ALPN: &[u8] = b"free-voip/files";
+
+const RESPONSE_ACCEPT: u8 = 1;
+const RESPONSE_DECLINE: u8 = 0;
+
+/// Upper bound on the size of a serialized [`FileOffer`].
+const MAX_OFFER_SIZE: u32 = 4 * 1024;
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Minimum number of bytes between two progress events of the same transfer.
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+/// How often a transfer on its own connection is resumed after the connection breaks.
+const MAX_RESUMES: u32 = 3;
+/// Delay before resuming, multiplied by the number of the attempt.
+const RESUME_DELAY: Duration = Duration::from_secs(2);
+/// How long an offer waits for the user before it is declined.
+const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Describes a file the sender would like to transfer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOffer {
+    pub name: String,
+    pub size: u64,
+    /// Hex encoded BLAKE3 hash of the whole file, checked by the receiver once complete.
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferState {
+    InProgress,
+    Completed,
+    Failed(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferProgress {
+    pub peer: NodeId,
+    pub direction: TransferDirection,
+    pub offer: FileOffer,
+    pub transferred: u64,
+    pub state: TransferState,
+}
+
+/// Transfers files between contacts, either on a dedicated connection or on a call's connection.
+/// Offers on a dedicated connection are refused unless they come from a contact.
+///
+/// Incomplete downloads are kept as `<peer>-<hash>.part` in the download directory. When the
+/// connection breaks, the sender offers the file again and the receiver picks up where it stopped
+/// without asking the user a second time. The same file from anyone else is asked about again.
+#[derive(Debug, Clone)]
+pub struct FilesProtocol {
+    offer_tx: Sender<(NodeId, FileOffer)>,
+    response_rx: Arc<Mutex<Receiver<bool>>>,
+    progress_tx: Sender<TransferProgress>,
+    download_dir: PathBuf,
+    /// Who may send us files outside of a call.
+    contacts: watch::Receiver<HashSet<NodeId>>,
+}
+
+async fn hash_file(path: &Path) -> Result<blake3::Hash, FreeVoipError> {
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(path)?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(file)?;
+        Ok::<_, std::io::Error>(hasher.finalize())
+    })
+    .await?
+    .map_err(Into::into)
+}
+
+async fn file_offer(path: &Path) -> Result<FileOffer, FreeVoipError> {
+    Ok(FileOffer {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| FreeVoipError::Io("Path does not point to a file".to_owned()))?,
+        size: fs::metadata(path).await?.len(),
+        hash: hash_file(path).await?.to_hex().to_string(),
+    })
+}
+
+/// Picks a path in `dir` for `name` that does not overwrite an existing file.
+async fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let name = Path::new(name);
+    let stem = name
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "file".to_owned());
+    let extension = name.extension().map(|e| e.to_string_lossy().into_owned());
+
+    let mut path = dir.join(name);
+    let mut counter = 1;
+    while fs::try_exists(&path).await.unwrap_or(false) {
+        let file_name = match extension {
+            Some(ref extension) => format!("{stem} ({counter}).{extension}"),
+            None => format!("{stem} ({counter})"),
+        };
+        path = dir.join(file_name);
+        counter += 1;
+    }
+    path
+}
+
+impl FilesProtocol {
+    pub fn new(
+        offer_tx: Sender<(NodeId, FileOffer)>,
+        response_rx: Receiver<bool>,
+        progress_tx: Sender<TransferProgress>,
+        download_dir: PathBuf,
+        contacts: watch::Receiver<HashSet<NodeId>>,
+    ) -> Self {
+        Self {
+            offer_tx,
+            response_rx: Arc::new(Mutex::new(response_rx)),
+            progress_tx,
+            download_dir,
+            contacts,
+        }
+    }
+
+    fn part_path(&self, peer: NodeId, offer: &FileOffer) -> PathBuf {
+        self.download_dir
+            .join(format!("{peer}-{}.part", offer.hash))
+    }
+
+    fn report(
+        &self,
+        peer: NodeId,
+        direction: TransferDirection,
+        offer: &FileOffer,
+        transferred: u64,
+        state: TransferState,
+    ) {
+        _ = self.progress_tx.send(TransferProgress {
+            peer,
+            direction,
+            offer: offer.clone(),
+            transferred,
+            state,
+        });
+    }
+
+    /// Sends a file over a new connection, returning whether the recipient accepted it.
+    ///
+    /// If the connection breaks the transfer is resumed on a new one, up to [`MAX_RESUMES`]
+    /// times.
+    #[instrument(name = "connection", skip_all, fields(protocol = "files", peer = field::Empty))]
+    pub async fn send_file(
+        &self,
+        endpoint: &Endpoint,
+        recipient_addr: impl Into<NodeAddr>,
+        path: &Path,
+    ) -> Result<bool, FreeVoipError> {
+        let recipient_addr = recipient_addr.into();
+        Span::current().record("peer", field::display(recipient_addr.node_id));
+
+        let offer = file_offer(path).await?;
+        let mut accepted = false;
+        let mut resumes = 0;
+        loop {
+            let result = match endpoint.connect(recipient_addr.clone(), ALPN).await {
+                Ok(connection) => {
+                    let result = self
+                        .send_and_report(&connection, path, &offer, &mut accepted)
+                        .await;
+                    connection.close(0u32.into(), b"File transfer complete");
+                    result
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            // Only a file the recipient accepted is resumed, they aren't asked again
+            match result {
+                Err(
+                    FreeVoipError::ConnectionLost(ref e) | FreeVoipError::PeerUnreachable(ref e),
+                ) if accepted && resumes < MAX_RESUMES => {
+                    resumes += 1;
+                    info!(resumes, "Resuming file transfer after connection loss: {e}");
+                    tokio::time::sleep(RESUME_DELAY * resumes).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends a file on an already established connection, such as an ongoing call.
+    pub async fn send_file_on(
+        &self,
+        connection: &Connection,
+        path: &Path,
+    ) -> Result<bool, FreeVoipError> {
+        let offer = file_offer(path).await?;
+        self.send_and_report(connection, path, &offer, &mut false)
+            .await
+    }
+
+    /// Sends `offer` and reports how it went, setting `accepted` once the recipient accepts it.
+    async fn send_and_report(
+        &self,
+        connection: &Connection,
+        path: &Path,
+        offer: &FileOffer,
+        accepted: &mut bool,
+    ) -> Result<bool, FreeVoipError> {
+        let peer = connection.remote_node_id()?;
+        let result = self
+            .send_offered_file(connection, path, peer, offer, accepted)
+            .await;
+        match result {
+            Ok(true) => self.report(
+                peer,
+                TransferDirection::Outgoing,
+                offer,
+                offer.size,
+                TransferState::Completed,
+            ),
+            Ok(false) => {}
+            Err(ref e) => self.report(
+                peer,
+                TransferDirection::Outgoing,
+                offer,
+                0,
+                TransferState::Failed(e.to_string()),
+            ),
+        }
+        result
+    }
+
+    async fn send_offered_file(
+        &self,
+        connection: &Connection,
+        path: &Path,
+        peer: NodeId,
+        offer: &FileOffer,
+        accepted: &mut bool,
+    ) -> Result<bool, FreeVoipError> {
+        let (mut proto_tx, mut proto_rx) = connection.open_bi().await?;
+
+        // Send the offer
+        let serialized_offer = postcard::to_stdvec(offer)?;
+        proto_tx
+            .write_u32(serialized_offer.len() as u32)
+            .await
+            .map_err(connection_lost)?;
+        proto_tx.write_all(&serialized_offer).await?;
+
+        // Wait for the receiver's response and how much they already have
+        let response = proto_rx.read_u8().await.map_err(connection_lost)?;
+        if response != RESPONSE_ACCEPT {
+            return Ok(false);
+        }
+        *accepted = true;
+        let offset = proto_rx.read_u64().await.map_err(connection_lost)?;
+        if offset > offer.size {
+            return Err(FreeVoipError::protocol_violation(
+                "Receiver requested an invalid offset",
+            ));
+        }
+
+        // Stream the remainder of the file
+        let mut file = File::open(path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut transferred = offset;
+        let mut last_report = offset;
+        while transferred < offer.size {
+            let num_bytes = file.read(&mut buf).await?;
+            if num_bytes == 0 {
+                return Err(FreeVoipError::Io("File changed while sending".to_owned()));
+            }
+            proto_tx.write_all(&buf[..num_bytes]).await?;
+            transferred += num_bytes as u64;
+
+            if transferred - last_report >= PROGRESS_INTERVAL {
+                self.report(
+                    peer,
+                    TransferDirection::Outgoing,
+                    offer,
+                    transferred,
+                    TransferState::InProgress,
+                );
+                last_report = transferred;
+            }
+        }
+        proto_tx.finish()?;
+
+        // Receiver confirms once the hash checks out
+        let verified = proto_rx.read_u8().await.map_err(connection_lost)?;
+        if verified == RESPONSE_ACCEPT {
+            Ok(true)
+        } else {
+            Err(FreeVoipError::protocol_violation(
+                "Receiver could not verify the file",
+            ))
+        }
+    }
+
+    /// Handles a file offer arriving on `proto_rx`, asking the user whether to accept it.
+    pub async fn receive(
+        &self,
+        peer: NodeId,
+        mut proto_tx: SendStream,
+        mut proto_rx: RecvStream,
+    ) -> Result<(), FreeVoipError> {
+        // Retrieve the offer
+        let offer = {
+            let offer_len = proto_rx.read_u32().await.map_err(connection_lost)?;
+            if offer_len > MAX_OFFER_SIZE {
+                return Err(FreeVoipError::protocol_violation("File offer is too large"));
+            }
+            let mut buf = vec![0u8; offer_len as usize];
+            proto_rx.read_exact(&mut buf).await?;
+            postcard::from_bytes::<FileOffer>(&buf)?
+        };
+        let expected_hash = blake3::Hash::from_hex(&offer.hash)
+            .map_err(|e| FreeVoipError::protocol_violation(e.to_string()))?;
+        info!(%peer, name = ?offer.name, size = offer.size, "Received file offer");
+
+        // A partial download from this peer means the user accepted the file before, so pick up
+        // where it stopped
+        let resuming = fs::metadata(self.part_path(peer, &offer))
+            .await
+            .is_ok_and(|m| m.len() > 0 && m.len() <= offer.size);
+
+        // Get user's response
+        let accepted = if resuming {
+            info!("Resuming partial download");
+            true
+        } else {
+            self.ask_user(peer, &offer, &mut proto_tx).await?
+        };
+        if !accepted {
+            proto_tx
+                .write_u8(RESPONSE_DECLINE)
+                .await
+                .map_err(connection_lost)?;
+            proto_tx.finish()?;
+            return Ok(());
+        }
+
+        let result = self
+            .receive_accepted(peer, &offer, expected_hash, &mut proto_tx, &mut proto_rx)
+            .await;
+        if let Err(ref e) = result {
+            self.report(
+                peer,
+                TransferDirection::Incoming,
+                &offer,
+                0,
+                TransferState::Failed(e.to_string()),
+            );
+        }
+        result
+    }
+
+    /// Shows the offer to the user once earlier offers are answered, declining it after
+    /// [`OFFER_TIMEOUT`] or when the sender gives up.
+    async fn ask_user(
+        &self,
+        peer: NodeId,
+        offer: &FileOffer,
+        proto_tx: &mut SendStream,
+    ) -> Result<bool, FreeVoipError> {
+        let user_response = async {
+            let mut response_rx = self.response_rx.lock().await;
+            // Discard answers to earlier offers that arrived after those timed out
+            while let Ok(_) | Err(TryRecvError::Lagged(_)) = response_rx.try_recv() {}
+
+            self.offer_tx.send((peer, offer.clone()))?;
+            Ok::<_, FreeVoipError>(response_rx.recv().await?)
+        };
+        tokio::select! {
+            response = user_response => response,
+            _ = tokio::time::sleep(OFFER_TIMEOUT) => {
+                info!("File offer timed out");
+                Ok(false)
+            }
+            // Sender gave up
+            _ = proto_tx.stopped() => Ok(false),
+        }
+    }
+
+    async fn receive_accepted(
+        &self,
+        peer: NodeId,
+        offer: &FileOffer,
+        expected_hash: blake3::Hash,
+        proto_tx: &mut SendStream,
+        proto_rx: &mut RecvStream,
+    ) -> Result<(), FreeVoipError> {
+        fs::create_dir_all(&self.download_dir).await?;
+
+        // Continue a previous partial download of the same file
+        let part_path = self.part_path(peer, offer);
+        let mut part_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&part_path)
+            .await?;
+        let mut offset = part_file.metadata().await?.len();
+        if offset > offer.size {
+            part_file.set_len(0).await?;
+            offset = 0;
+        }
+
+        proto_tx
+            .write_u8(RESPONSE_ACCEPT)
+            .await
+            .map_err(connection_lost)?;
+        proto_tx.write_u64(offset).await.map_err(connection_lost)?;
+
+        // Receive the remainder of the file
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut transferred = offset;
+        let mut last_report = offset;
+        while transferred < offer.size {
+            let max_bytes = CHUNK_SIZE.min((offer.size - transferred) as usize);
+            let num_bytes = proto_rx.read(&mut buf[..max_bytes]).await?.ok_or_else(|| {
+                FreeVoipError::ConnectionLost(
+                    "Sender stopped before the file was complete".to_owned(),
+                )
+            })?;
+            part_file.write_all(&buf[..num_bytes]).await?;
+            transferred += num_bytes as u64;
+
+            if transferred - last_report >= PROGRESS_INTERVAL {
+                self.report(
+                    peer,
+                    TransferDirection::Incoming,
+                    offer,
+                    transferred,
+                    TransferState::InProgress,
+                );
+                last_report = transferred;
+            }
+        }
+        part_file.flush().await?;
+        drop(part_file);
+
+        // Verify the whole file before handing it to the user
+        let hash = hash_file(&part_path).await?;
+        if hash != expected_hash {
+            _ = fs::remove_file(&part_path).await;
+            _ = proto_tx.write_u8(RESPONSE_DECLINE).await;
+            return Err(FreeVoipError::protocol_violation(
+                "Received file does not match its hash",
+            ));
+        }
+
+        let file_name = Path::new(&offer.name)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| offer.hash.clone());
+        let path = unique_path(&self.download_dir, &file_name).await;
+        fs::rename(&part_path, &path).await?;
+        info!(?path, "Received file");
+
+        proto_tx
+            .write_u8(RESPONSE_ACCEPT)
+            .await
+            .map_err(connection_lost)?;
+        proto_tx.finish()?;
+        self.report(
+            peer,
+            TransferDirection::Incoming,
+            offer,
+            offer.size,
+            TransferState::Completed,
+        );
+
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for FilesProtocol {
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "files", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let peer = connection.remote_node_id()?;
+        // Files sent during a call arrive on the call's connection instead
+        if !self.contacts.borrow().contains(&peer) {
+            return Err(AcceptError::NotAllowed {});
+        }
+        let (proto_tx, proto_rx) = connection.accept_bi().await?;
+
+        if let Err(e) = self.receive(peer, proto_tx, proto_rx).await {
+            warn!("File transfer failed: {e}");
+        }
+
+        connection.closed().await;
+        Ok(())
+    }
+}
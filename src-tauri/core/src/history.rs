@@ -0,0 +1,79 @@
+use crate::unix_timestamp;
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CallDirection {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CallOutcome {
+    Answered,
+    Declined,
+    /// Nobody answered before the ring timed out or the caller gave up.
+    Missed,
+    /// The callee was already in a call.
+    Busy,
+    /// The caller hung up before the callee answered.
+    Cancelled,
+    Failed(String),
+}
+
+/// A finished call, as stored in the call history.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallRecord {
+    pub peer: NodeId,
+    pub direction: CallDirection,
+    pub started_at: u64,
+    pub answered_at: Option<u64>,
+    pub ended_at: u64,
+    /// Seconds spent connected, zero for unanswered calls.
+    pub duration: u64,
+    pub outcome: CallOutcome,
+}
+
+/// A call that is ringing or in progress.
+#[derive(Debug, Clone)]
+pub struct PendingCall {
+    pub peer: NodeId,
+    pub direction: CallDirection,
+    pub started_at: u64,
+    pub answered_at: Option<u64>,
+}
+
+impl PendingCall {
+    pub fn new(peer: NodeId, direction: CallDirection) -> Self {
+        Self {
+            peer,
+            direction,
+            started_at: unix_timestamp(),
+            answered_at: None,
+        }
+    }
+
+    pub fn answered(mut self) -> Self {
+        self.answered_at = Some(unix_timestamp());
+        self
+    }
+
+    pub fn finish(self, outcome: CallOutcome) -> CallRecord {
+        let ended_at = unix_timestamp();
+        CallRecord {
+            peer: self.peer,
+            direction: self.direction,
+            started_at: self.started_at,
+            answered_at: self.answered_at,
+            ended_at,
+            duration: self
+                .answered_at
+                .map(|answered_at| ended_at.saturating_sub(answered_at))
+                .unwrap_or_default(),
+            outcome,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Invite tickets: signed by the node that issued them, they expire and can only be used a
+//! limited number of times. Contact requests that come with one are accepted without asking.
+
+use std::fmt::Write;
+
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use iroh::{NodeId, SecretKey};
+use iroh_base::{
+    ticket::{ParseError as TicketParseError, Ticket},
+    Signature,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{contacts::ContactTicket, unix_timestamp, FreeVoipError};
+
+/// Keeps invite signatures from being valid for anything else.
+const SIGNATURE_CONTEXT: &[u8] = b"free-voip/invite";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteTicket {
+    /// Whom the invite is for. Only the node ID is signed, the nickname and addresses are hints.
+    pub issuer: ContactTicket,
+    pub nonce: [u8; 16],
+    /// Seconds since the Unix epoch.
+    pub expires_at: u64,
+    /// How many contacts can use the invite, just one if not given.
+    pub max_uses: Option<u32>,
+    pub signature: Signature,
+}
+
+fn signed_bytes(
+    node_id: &NodeId,
+    nonce: &[u8; 16],
+    expires_at: u64,
+    max_uses: Option<u32>,
+) -> Vec<u8> {
+    postcard::to_stdvec(&(SIGNATURE_CONTEXT, node_id, nonce, expires_at, max_uses))
+        .expect("Postcard serializtion should be infallible")
+}
+
+impl InviteTicket {
+    /// Issues an invite to `issuer`, signed with their `secret_key`.
+    pub fn issue(
+        secret_key: &SecretKey,
+        issuer: ContactTicket,
+        expires_at: u64,
+        max_uses: Option<u32>,
+    ) -> Self {
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+        let signature =
+            secret_key.sign(&signed_bytes(&issuer.node_id, &nonce, expires_at, max_uses));
+
+        Self {
+            issuer,
+            nonce,
+            expires_at,
+            max_uses,
+            signature,
+        }
+    }
+
+    /// The hex encoded nonce, which identifies the invite.
+    pub fn id(&self) -> String {
+        self.nonce
+            .iter()
+            .fold(String::with_capacity(32), |mut id, b| {
+                _ = write!(id, "{b:02x}");
+                id
+            })
+    }
+
+    /// Whether the invite was signed by the node it is for.
+    pub fn verify(&self) -> Result<(), FreeVoipError> {
+        let message = signed_bytes(
+            &self.issuer.node_id,
+            &self.nonce,
+            self.expires_at,
+            self.max_uses,
+        );
+        self.issuer
+            .node_id
+            .verify(&message, &self.signature)
+            .map_err(|_| FreeVoipError::InvalidTicket)
+    }
+}
+
+impl Ticket for InviteTicket {
+    const KIND: &'static str = "invite";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).expect("Postcard serializtion should be infallible")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, TicketParseError> {
+        postcard::from_bytes(bytes).map_err(Into::into)
+    }
+}
+
+/// An invite we issued, as we keep track of it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Invite {
+    pub id: String,
+    pub expires_at: u64,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+}
+
+impl Invite {
+    pub fn new(ticket: &InviteTicket) -> Self {
+        Self {
+            id: ticket.id(),
+            expires_at: ticket.expires_at,
+            max_uses: ticket.max_uses,
+            uses: 0,
+        }
+    }
+
+    /// Whether the invite has neither expired nor been used up.
+    pub fn is_valid(&self) -> bool {
+        self.expires_at > unix_timestamp() && self.uses < self.max_uses.unwrap_or(1)
+    }
+}
+
+/// The invites a node issued and has not revoked, shared between the node and its contacts
+/// protocol.
+#[derive(Debug, Clone)]
+pub struct InviteBook {
+    node_id: NodeId,
+    issued: watch::Sender<Vec<Invite>>,
+}
+
+impl InviteBook {
+    pub fn new(node_id: NodeId, issued: watch::Sender<Vec<Invite>>) -> Self {
+        Self { node_id, issued }
+    }
+
+    /// Uses `ticket` up once, if it is one of ours and still valid.
+    pub fn redeem(&self, ticket: &InviteTicket) -> Result<(), FreeVoipError> {
+        if ticket.issuer.node_id != self.node_id {
+            return Err(FreeVoipError::invalid_state("Invite is for someone else"));
+        }
+        ticket.verify()?;
+
+        let id = ticket.id();
+        let mut result = Err(FreeVoipError::invalid_state("Unknown or revoked invite"));
+        self.issued.send_if_modified(|invites| {
+            let Some(invite) = invites.iter_mut().find(|i| i.id == id) else {
+                return false;
+            };
+            if !invite.is_valid() {
+                result = Err(FreeVoipError::invalid_state("Invite expired or used up"));
+                return false;
+            }
+
+            invite.uses += 1;
+            result = Ok(());
+            true
+        });
+        result
+    }
+}
@@ -0,0 +1,79 @@
+//! Passphrase encryption of secrets at rest: a key is derived from the passphrase with Argon2id
+//! and the secret is sealed with ChaCha20-Poly1305.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::FreeVoipError;
+
+const SALT_LEN: usize = 16;
+
+/// A secret sealed with a passphrase, along with everything needed to open it again except the
+/// passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sealed {
+    /// Argon2id memory cost in KiB.
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Result<Key, FreeVoipError> {
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| FreeVoipError::invalid_state(e.to_string()))?;
+    Ok(key)
+}
+
+impl Sealed {
+    pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Self, FreeVoipError> {
+        if passphrase.is_empty() {
+            return Err(FreeVoipError::invalid_state(
+                "The passphrase must not be empty",
+            ));
+        }
+
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let params = Params::default();
+        let key = derive_key(passphrase, &salt, params.clone())?;
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&key)
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| FreeVoipError::invalid_state(e.to_string()))?;
+
+        Ok(Self {
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            salt,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Fails with [`FreeVoipError::WrongPassphrase`] if the passphrase does not match, or the
+    /// sealed secret was tampered with.
+    pub fn open(&self, passphrase: &str) -> Result<Vec<u8>, FreeVoipError> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, None)
+            .map_err(|e| FreeVoipError::StoreError(e.to_string()))?;
+        let nonce = <[u8; 12]>::try_from(self.nonce.as_slice())
+            .map(Nonce::from)
+            .map_err(|_| FreeVoipError::StoreError("Invalid nonce".to_owned()))?;
+
+        let key = derive_key(passphrase, &self.salt, params)?;
+        ChaCha20Poly1305::new(&key)
+            .decrypt(&nonce, self.ciphertext.as_slice())
+            .map_err(|_| FreeVoipError::WrongPassphrase)
+    }
+}
@@ -0,0 +1,40 @@
+//! Calling and contacts stack of Free VoIP, without any GUI dependencies.
+//!
+//! [`Node`] owns the iroh endpoint and protocols and exposes an async API, with everything the
+//! GUI needs to react to delivered as [`Event`]s.
+
+pub mod backup;
+pub mod call;
+pub mod contacts;
+pub mod echo;
+pub mod error;
+pub mod files;
+pub mod history;
+pub mod invite;
+pub mod keystore;
+pub mod link;
+pub mod logging;
+pub mod network;
+pub mod outbox;
+pub mod presence;
+pub mod profile;
+pub mod qr;
+pub mod recording;
+pub mod storage;
+pub mod voicemail;
+
+mod matroska;
+mod node;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use error::FreeVoipError;
+pub use iroh::{NodeId, RelayUrl, SecretKey};
+pub use node::{Event, Node, NodeConfig};
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
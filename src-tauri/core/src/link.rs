@@ -0,0 +1,86 @@
+//! `free-voip://` deep links, so that links shared in chats or emails open the app:
+//! `free-voip://add?ticket=<contact ticket>` and `free-voip://call?node=<node ID>`.
+
+use iroh::NodeId;
+use iroh_base::ticket::Ticket;
+use serde::Serialize;
+use url::Url;
+
+use crate::{contacts::ContactTicket, FreeVoipError};
+
+pub const SCHEME: &str = "free-voip";
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum DeepLink {
+    /// Send a contact request to the owner of the ticket.
+    Add {
+        ticket: ContactTicket,
+        /// The ticket as it was in the link, for [`crate::Node::send_contact_request`].
+        serialized_ticket: String,
+    },
+    /// Ring the node.
+    Call { node_id: NodeId },
+}
+
+fn invalid_link(message: &str) -> FreeVoipError {
+    FreeVoipError::invalid_state(format!("Invalid link: {message}"))
+}
+
+impl DeepLink {
+    pub fn parse(uri: &str) -> Result<Self, FreeVoipError> {
+        let url = Url::parse(uri.trim()).map_err(|_| invalid_link("not a URI"))?;
+        if url.scheme() != SCHEME {
+            return Err(invalid_link("not a Free VoIP link"));
+        }
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .ok_or_else(|| invalid_link(&format!("missing `{name}`")))
+        };
+
+        match url.host_str() {
+            Some("add") => {
+                let serialized_ticket = param("ticket")?;
+                let ticket = <ContactTicket as Ticket>::deserialize(&serialized_ticket)
+                    .map_err(|_e| FreeVoipError::InvalidTicket)?;
+                Ok(DeepLink::Add {
+                    ticket,
+                    serialized_ticket,
+                })
+            }
+            Some("call") => {
+                let node_id = param("node")?
+                    .parse()
+                    .map_err(|_| invalid_link("invalid node ID"))?;
+                Ok(DeepLink::Call { node_id })
+            }
+            _ => Err(invalid_link("unknown action")),
+        }
+    }
+
+    /// The link as a URI to share.
+    pub fn to_uri(&self) -> String {
+        let mut url = Url::parse(&format!("{SCHEME}://")).expect("Scheme is a valid URI");
+        match self {
+            DeepLink::Add {
+                serialized_ticket, ..
+            } => {
+                url.set_host(Some("add")).expect("Host is valid");
+                url.query_pairs_mut()
+                    .append_pair("ticket", serialized_ticket);
+            }
+            DeepLink::Call { node_id } => {
+                url.set_host(Some("call")).expect("Host is valid");
+                url.query_pairs_mut()
+                    .append_pair("node", &node_id.to_string());
+            }
+        }
+        url.into()
+    }
+}
@@ -0,0 +1,159 @@
+//! Diagnostics through `tracing`: daily rotating log files in the data directory, optionally
+//! mirrored to stderr, and redacted log bundles that users can attach to bug reports.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use iroh::endpoint::Connection;
+use regex::{Captures, Regex};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::{unix_timestamp, FreeVoipError};
+
+const LOG_FILE_PREFIX: &str = "free-voip";
+const LOG_FILE_SUFFIX: &str = "log";
+/// Days of logs to keep.
+const MAX_LOG_FILES: usize = 7;
+
+/// Our own crates in detail and everything else only when it goes wrong, unless `RUST_LOG` says
+/// otherwise.
+const FILE_FILTER: &str = "warn,free_voip_core=debug,free_voip_lib=debug,free_voip_cli=debug";
+const STDERR_FILTER: &str = "warn,free_voip_core=info,free_voip_lib=info";
+
+fn filter(default: &str) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default))
+}
+
+/// Installs the global subscriber, logging to files in `log_dir` and, if `stderr` is set, to
+/// stderr. Log files are written in the background until the returned guard is dropped.
+pub fn init(log_dir: &Path, stderr: bool) -> Result<WorkerGuard, FreeVoipError> {
+    std::fs::create_dir_all(log_dir)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)
+        .map_err(|e| FreeVoipError::Io(e.to_string()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let file_layer = fmt::layer()
+        .with_ansi(false)
+        .with_writer(writer)
+        .with_filter(filter(FILE_FILTER));
+    let stderr_layer = stderr.then(|| {
+        fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_filter(filter(STDERR_FILTER))
+    });
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(stderr_layer)
+        .try_init()
+        .map_err(|e| FreeVoipError::invalid_state(e.to_string()))?;
+    Ok(guard)
+}
+
+/// Strips identifying data from log lines. Node IDs are replaced with pseudonyms that stay the
+/// same throughout a bundle, so that a peer can still be followed across lines.
+pub struct Redactor {
+    /// Nicknames, file names and paths, both as span fields and in `Debug` output.
+    fields: Regex,
+    /// Serialized contact and invite tickets.
+    tickets: Regex,
+    /// Relay URLs, which can point at a self-hosted relay.
+    urls: Regex,
+    /// Node IDs, and secret keys in case one ever slips into a log.
+    keys: Regex,
+    ip_addrs: Regex,
+    home_dirs: Regex,
+    pseudonyms: HashMap<String, String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self {
+            fields: Regex::new(
+                r#"\b(secret_key|nickname|name|path|status_text)(=|: )("(?:[^"\\]|\\.)*"|[^\s,}]+)"#,
+            )
+            .unwrap(),
+            tickets: Regex::new(r"\b(?:node|invite)[a-z2-7]{50,}\b").unwrap(),
+            urls: Regex::new(r#"\b(?:https?|wss?)://[^\s"',)}]+"#).unwrap(),
+            keys: Regex::new(r"\b[0-9a-f]{64}\b").unwrap(),
+            ip_addrs: Regex::new(r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b|\[[0-9a-fA-F:.%]+\](?::\d+)?")
+                .unwrap(),
+            home_dirs: Regex::new(r"(/home/|/Users/|[A-Z]:\\Users\\)[^/\\\s]+").unwrap(),
+            pseudonyms: HashMap::new(),
+        }
+    }
+}
+
+impl Redactor {
+    pub fn redact(&mut self, line: &str) -> String {
+        let line = self.fields.replace_all(line, "$1$2<redacted>");
+        let line = self.tickets.replace_all(&line, "<ticket>");
+        let line = self.urls.replace_all(&line, "<url>");
+        let line = self.ip_addrs.replace_all(&line, "<ip>");
+        let line = self.home_dirs.replace_all(&line, "$1<user>");
+
+        let pseudonyms = &mut self.pseudonyms;
+        self.keys
+            .replace_all(&line, |caps: &Captures| {
+                let next = pseudonyms.len() + 1;
+                pseudonyms
+                    .entry(caps[0].to_owned())
+                    .or_insert_with(|| format!("<node-{next}>"))
+                    .clone()
+            })
+            .into_owned()
+    }
+}
+
+/// Writes the redacted contents of all log files in `log_dir`, oldest first, to a new file in
+/// `destination_dir` and returns its path.
+pub async fn export_bundle(
+    log_dir: &Path,
+    destination_dir: &Path,
+) -> Result<PathBuf, FreeVoipError> {
+    let mut log_files = vec![];
+    let mut entries = tokio::fs::read_dir(log_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(LOG_FILE_PREFIX) {
+            log_files.push((name, entry.path()));
+        }
+    }
+    // Daily files are suffixed with their date
+    log_files.sort();
+
+    let mut redactor = Redactor::default();
+    let mut bundle = String::new();
+    for (name, path) in log_files {
+        let contents = tokio::fs::read(&path).await?;
+        bundle.push_str(&format!("===== {name} =====\n"));
+        for line in String::from_utf8_lossy(&contents).lines() {
+            bundle.push_str(&redactor.redact(line));
+            bundle.push('\n');
+        }
+    }
+
+    tokio::fs::create_dir_all(destination_dir).await?;
+    let path = destination_dir.join(format!("free-voip-logs-{}.txt", unix_timestamp()));
+    tokio::fs::write(&path, bundle).await?;
+    Ok(path)
+}
+
+/// The peer of `connection`, for the span of an accepted connection.
+pub(crate) fn remote_peer(connection: &Connection) -> String {
+    connection
+        .remote_node_id()
+        .map(|id| id.to_string())
+        .unwrap_or_default()
+}
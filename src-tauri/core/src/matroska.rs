@@ -0,0 +1,350 @@
+//! Just enough EBML to write and read back the Matroska files of call recordings and voicemails.
+
+use crate::{call::AudioConfig, FreeVoipError};
+
+// Matroska element IDs
+pub(crate) const EBML: u32 = 0x1A45DFA3;
+pub(crate) const EBML_VERSION: u32 = 0x4286;
+pub(crate) const EBML_READ_VERSION: u32 = 0x42F7;
+pub(crate) const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+pub(crate) const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+pub(crate) const DOC_TYPE: u32 = 0x4282;
+pub(crate) const DOC_TYPE_VERSION: u32 = 0x4287;
+pub(crate) const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+pub(crate) const SEGMENT: u32 = 0x18538067;
+pub(crate) const INFO: u32 = 0x1549A966;
+pub(crate) const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+pub(crate) const DURATION: u32 = 0x4489;
+pub(crate) const MUXING_APP: u32 = 0x4D80;
+pub(crate) const WRITING_APP: u32 = 0x5741;
+pub(crate) const TRACKS: u32 = 0x1654AE6B;
+pub(crate) const TRACK_ENTRY: u32 = 0xAE;
+pub(crate) const TRACK_NUMBER: u32 = 0xD7;
+pub(crate) const TRACK_UID: u32 = 0x73C5;
+pub(crate) const TRACK_TYPE: u32 = 0x83;
+pub(crate) const FLAG_LACING: u32 = 0x9C;
+pub(crate) const NAME: u32 = 0x536E;
+pub(crate) const CODEC_ID: u32 = 0x86;
+pub(crate) const CODEC_PRIVATE: u32 = 0x63A2;
+pub(crate) const VIDEO: u32 = 0xE0;
+pub(crate) const PIXEL_WIDTH: u32 = 0xB0;
+pub(crate) const PIXEL_HEIGHT: u32 = 0xBA;
+pub(crate) const AUDIO: u32 = 0xE1;
+pub(crate) const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub(crate) const CHANNELS: u32 = 0x9F;
+pub(crate) const CLUSTER: u32 = 0x1F43B675;
+pub(crate) const CLUSTER_TIMESTAMP: u32 = 0xE7;
+pub(crate) const SIMPLE_BLOCK: u32 = 0xA3;
+
+pub(crate) const TRACK_TYPE_VIDEO: u64 = 1;
+pub(crate) const TRACK_TYPE_AUDIO: u64 = 2;
+
+/// Block timestamps are in milliseconds.
+pub(crate) const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+/// Clusters are closed once they span this many milliseconds.
+pub(crate) const MAX_CLUSTER_DURATION: u64 = 1000;
+
+/// Sampling frequencies with an index of their own in an AAC AudioSpecificConfig.
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// AudioSpecificConfig of AAC-LC frames encoded with `config`, the CodecPrivate of `A_AAC`
+/// tracks.
+pub(crate) fn aac_codec_private(config: AudioConfig) -> Vec<u8> {
+    const AAC_LC: u64 = 2;
+
+    // Object type (5 bits), frequency index (4 bits), channels (4 bits), then 3 flag bits
+    let (bits, len) = match AAC_SAMPLE_RATES
+        .iter()
+        .position(|rate| *rate == config.sample_rate)
+    {
+        Some(index) => (AAC_LC << 11 | (index as u64) << 7, 16),
+        // Other rates follow the escape index as 24 bits of their own
+        None => (
+            AAC_LC << 35 | 0xF << 31 | (config.sample_rate as u64 & 0xFF_FFFF) << 7,
+            40,
+        ),
+    };
+    let bits = bits | (config.channels as u64 & 0xF) << 3;
+    bits.to_be_bytes()[8 - len / 8..].to_vec()
+}
+
+pub(crate) fn write_id(buf: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    buf.extend_from_slice(&bytes[skip..]);
+}
+
+pub(crate) fn write_size(buf: &mut Vec<u8>, size: u64) {
+    // Sizes with all value bits set are reserved for "unknown"
+    let len = (1..=8).find(|len| size < (1 << (7 * len)) - 1).unwrap_or(8);
+    let marked = size | (1 << (7 * len));
+    buf.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+pub(crate) fn write_element(buf: &mut Vec<u8>, id: u32, data: &[u8]) {
+    write_id(buf, id);
+    write_size(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+pub(crate) fn write_uint(buf: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    write_element(buf, id, &bytes[skip..]);
+}
+
+/// Writes a placeholder unsigned integer that can be patched later, returning its data offset.
+pub(crate) fn write_fixed_uint(buf: &mut Vec<u8>, id: u32) -> u64 {
+    write_id(buf, id);
+    write_size(buf, 4);
+    let offset = buf.len() as u64;
+    buf.extend_from_slice(&[0; 4]);
+    offset
+}
+
+pub(crate) fn write_float(buf: &mut Vec<u8>, id: u32, value: f64) -> u64 {
+    write_id(buf, id);
+    write_size(buf, 8);
+    let offset = buf.len() as u64;
+    buf.extend_from_slice(&value.to_be_bytes());
+    offset
+}
+
+/// The EBML header of a Matroska file.
+pub(crate) fn write_header(buf: &mut Vec<u8>) {
+    let mut ebml_header = vec![];
+    write_uint(&mut ebml_header, EBML_VERSION, 1);
+    write_uint(&mut ebml_header, EBML_READ_VERSION, 1);
+    write_uint(&mut ebml_header, EBML_MAX_ID_LENGTH, 4);
+    write_uint(&mut ebml_header, EBML_MAX_SIZE_LENGTH, 8);
+    write_element(&mut ebml_header, DOC_TYPE, b"matroska");
+    write_uint(&mut ebml_header, DOC_TYPE_VERSION, 4);
+    write_uint(&mut ebml_header, DOC_TYPE_READ_VERSION, 2);
+    write_element(buf, EBML, &ebml_header);
+}
+
+/// Writes the entry of an AAC audio track.
+pub(crate) fn write_aac_track(buf: &mut Vec<u8>, number: u64, name: &str, config: AudioConfig) {
+    let mut entry = vec![];
+    write_uint(&mut entry, TRACK_NUMBER, number);
+    write_uint(&mut entry, TRACK_UID, number);
+    write_uint(&mut entry, FLAG_LACING, 0);
+    write_element(&mut entry, NAME, name.as_bytes());
+    write_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_AUDIO);
+    write_element(&mut entry, CODEC_ID, b"A_AAC");
+    write_element(&mut entry, CODEC_PRIVATE, &aac_codec_private(config));
+
+    let mut audio = vec![];
+    write_float(&mut audio, SAMPLING_FREQUENCY, config.sample_rate as f64);
+    write_uint(&mut audio, CHANNELS, config.channels as u64);
+    write_element(&mut entry, AUDIO, &audio);
+
+    write_element(buf, TRACK_ENTRY, &entry);
+}
+
+/// Appends a SimpleBlock of `track` at `relative` milliseconds into its cluster.
+pub(crate) fn write_simple_block(
+    buf: &mut Vec<u8>,
+    track: u64,
+    relative: i16,
+    is_key: bool,
+    data: &[u8],
+) {
+    let mut block = vec![];
+    write_size(&mut block, track);
+    block.extend_from_slice(&relative.to_be_bytes());
+    block.push(if is_key { 0x80 } else { 0x00 });
+    block.extend_from_slice(data);
+    write_element(buf, SIMPLE_BLOCK, &block);
+}
+
+fn malformed() -> FreeVoipError {
+    FreeVoipError::protocol_violation("Malformed Matroska data")
+}
+
+/// Reads a variable length integer, returning its value without the length marker and its
+/// length.
+fn read_vint(data: &[u8]) -> Result<(u64, usize), FreeVoipError> {
+    let first = *data.first().ok_or_else(malformed)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 || data.len() < len {
+        return Err(malformed());
+    }
+    let value = data[..len]
+        .iter()
+        .fold(0u64, |value, b| value << 8 | *b as u64);
+    Ok((value & !(1 << (7 * len)), len))
+}
+
+/// Splits EBML `data` into its elements, failing if any size runs past the end.
+pub(crate) fn read_elements(mut data: &[u8]) -> Result<Vec<(u32, &[u8])>, FreeVoipError> {
+    let mut elements = vec![];
+    while !data.is_empty() {
+        // IDs keep their length marker
+        let id_len = data[0].leading_zeros() as usize + 1;
+        if id_len > 4 || data.len() < id_len {
+            return Err(malformed());
+        }
+        let id = data[..id_len]
+            .iter()
+            .fold(0u32, |id, b| id << 8 | *b as u32);
+
+        let (size, size_len) = read_vint(&data[id_len..])?;
+        let start = id_len + size_len;
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .filter(|end| *end <= data.len())
+            .ok_or_else(malformed)?;
+
+        elements.push((id, &data[start..end]));
+        data = &data[end..];
+    }
+    Ok(elements)
+}
+
+/// The data of the first child of `data` with the given ID.
+pub(crate) fn find_child(data: &[u8], id: u32) -> Result<Option<&[u8]>, FreeVoipError> {
+    Ok(read_elements(data)?
+        .into_iter()
+        .find(|(child_id, _)| *child_id == id)
+        .map(|(_, data)| data))
+}
+
+pub(crate) fn read_uint(data: &[u8]) -> Result<u64, FreeVoipError> {
+    if data.len() > 8 {
+        return Err(malformed());
+    }
+    Ok(data.iter().fold(0, |value, b| value << 8 | *b as u64))
+}
+
+pub(crate) fn read_float(data: &[u8]) -> Result<f64, FreeVoipError> {
+    match data.len() {
+        0 => Ok(0.0),
+        4 => Ok(f32::from_be_bytes(data.try_into().unwrap()) as f64),
+        8 => Ok(f64::from_be_bytes(data.try_into().unwrap())),
+        _ => Err(malformed()),
+    }
+}
+
+/// A frame read back from a SimpleBlock.
+#[derive(Debug)]
+pub(crate) struct Block<'a> {
+    pub track: u64,
+    /// Microseconds since the start of the segment.
+    pub timestamp_us: u64,
+    pub is_key: bool,
+    pub data: &'a [u8],
+}
+
+/// Reads the segment of a Matroska file, returning its children.
+pub(crate) fn read_segment(data: &[u8]) -> Result<Vec<(u32, &[u8])>, FreeVoipError> {
+    let top = read_elements(data)?;
+    let header = top
+        .iter()
+        .find(|(id, _)| *id == EBML)
+        .ok_or_else(malformed)?
+        .1;
+    let doc_type = find_child(header, DOC_TYPE)?;
+    if !matches!(doc_type, Some(b"matroska" | b"webm")) {
+        return Err(FreeVoipError::protocol_violation("Not a Matroska file"));
+    }
+
+    let segment = top
+        .iter()
+        .find(|(id, _)| *id == SEGMENT)
+        .ok_or_else(malformed)?
+        .1;
+    read_elements(segment)
+}
+
+/// Reads every SimpleBlock in the clusters of `segment`, in file order.
+pub(crate) fn read_blocks<'a>(
+    segment: &[(u32, &'a [u8])],
+) -> Result<Vec<Block<'a>>, FreeVoipError> {
+    let timestamp_scale = match segment.iter().find(|(id, _)| *id == INFO) {
+        Some((_, info)) => find_child(info, TIMESTAMP_SCALE)?
+            .map(read_uint)
+            .transpose()?
+            .unwrap_or(DEFAULT_TIMESTAMP_SCALE),
+        None => DEFAULT_TIMESTAMP_SCALE,
+    };
+
+    let mut blocks = vec![];
+    for (_, cluster) in segment.iter().filter(|(id, _)| *id == CLUSTER) {
+        let mut cluster_timestamp = 0;
+        for (id, data) in read_elements(cluster)? {
+            match id {
+                CLUSTER_TIMESTAMP => cluster_timestamp = read_uint(data)?,
+                SIMPLE_BLOCK => {
+                    let (track, track_len) = read_vint(data)?;
+                    let header = data.get(track_len..track_len + 3).ok_or_else(malformed)?;
+                    let relative = i16::from_be_bytes([header[0], header[1]]);
+                    let flags = header[2];
+                    // Laced blocks hold several frames, which we never write
+                    if flags & 0x06 != 0 {
+                        return Err(FreeVoipError::protocol_violation(
+                            "Laced Matroska blocks are not supported",
+                        ));
+                    }
+
+                    let timestamp = cluster_timestamp
+                        .checked_add_signed(relative as i64)
+                        .ok_or_else(malformed)?;
+                    blocks.push(Block {
+                        track,
+                        timestamp_us: timestamp.saturating_mul(timestamp_scale) / 1000,
+                        is_key: flags & 0x80 != 0,
+                        data: &data[track_len + 3..],
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+/// Entry of the first track of `segment` with the given codec.
+pub(crate) fn find_track<'a>(
+    segment: &[(u32, &'a [u8])],
+    codec_id: &[u8],
+) -> Result<Option<&'a [u8]>, FreeVoipError> {
+    let Some((_, tracks)) = segment.iter().find(|(id, _)| *id == TRACKS) else {
+        return Ok(None);
+    };
+    for (id, entry) in read_elements(tracks)? {
+        if id == TRACK_ENTRY && find_child(entry, CODEC_ID)? == Some(codec_id) {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
+
+/// Number of the track with the given entry.
+pub(crate) fn track_number(entry: &[u8]) -> Result<u64, FreeVoipError> {
+    find_child(entry, TRACK_NUMBER)?
+        .map(read_uint)
+        .transpose()?
+        .ok_or_else(malformed)
+}
+
+/// Sampling frequency and channels of the audio track with the given entry.
+pub(crate) fn audio_config(entry: &[u8]) -> Result<AudioConfig, FreeVoipError> {
+    let audio = find_child(entry, AUDIO)?.ok_or_else(malformed)?;
+    // Both default to 8 kHz mono in the spec
+    let sample_rate = match find_child(audio, SAMPLING_FREQUENCY)? {
+        Some(data) => read_float(data)? as u32,
+        None => 8000,
+    };
+    let channels = find_child(audio, CHANNELS)?
+        .map(read_uint)
+        .transpose()?
+        .unwrap_or(1);
+    Ok(AudioConfig {
+        sample_rate,
+        channels: u32::try_from(channels).map_err(|_| malformed())?,
+    })
+}
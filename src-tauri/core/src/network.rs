@@ -0,0 +1,127 @@
+//! How the endpoint finds peers and is found by them: the relays it uses, whether it publishes its
+//! addresses, whether it looks for peers on the local network that can be reached without the
+//! internet, and whether it hides our IP address behind a relay.
+
+use std::time::Duration;
+
+use iroh::{
+    discovery::{pkarr::PkarrPublisher, Discovery, NodeData},
+    Endpoint, NodeAddr, NodeId, RelayMap, RelayUrl,
+};
+use serde::{Deserialize, Serialize};
+
+/// How long to wait between checks whether local network discovery should stop.
+pub(crate) const NEARBY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Which relays connections fall back to when peers can't reach each other directly.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RelayMode {
+    /// The public relays run by n0.
+    #[default]
+    Default,
+    /// The relays in [`NetworkSettings::relay_urls`], like one run by the company.
+    Custom,
+    /// No relays, for trusted networks where peers can always reach each other directly.
+    Disabled,
+}
+
+/// How peers find our current addresses by node ID, and we theirs.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiscoveryMode {
+    /// Publish and look up addresses on n0's DNS servers.
+    #[default]
+    Default,
+    /// Rely on the addresses in tickets and on the local network only.
+    Disabled,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkSettings {
+    #[serde(default)]
+    pub relay_mode: RelayMode,
+    /// The relays to use with [`RelayMode::Custom`].
+    #[serde(default)]
+    pub relay_urls: Vec<RelayUrl>,
+    #[serde(default)]
+    pub discovery: DiscoveryMode,
+    /// Advertise ourselves and find peers on the local network with mDNS, so that calls work
+    /// without internet access. Off while [`NetworkSettings::hide_ip`] is on.
+    #[serde(default = "enabled")]
+    pub local_discovery: bool,
+    /// Keep our IP addresses from peers: all traffic goes through a relay, and no direct
+    /// addresses are put in tickets or published. Peers we connect to may still learn of a port
+    /// mapping our router set up with UPnP.
+    #[serde(default)]
+    pub hide_ip: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            relay_mode: RelayMode::default(),
+            relay_urls: vec![],
+            discovery: DiscoveryMode::default(),
+            local_discovery: enabled(),
+            hide_ip: false,
+        }
+    }
+}
+
+impl NetworkSettings {
+    /// The relays for the endpoint to use.
+    pub(crate) fn iroh_relay_mode(&self) -> iroh::RelayMode {
+        match self.relay_mode {
+            RelayMode::Default => iroh::RelayMode::Default,
+            RelayMode::Custom => {
+                iroh::RelayMode::Custom(RelayMap::from_iter(self.relay_urls.iter().cloned()))
+            }
+            RelayMode::Disabled => iroh::RelayMode::Disabled,
+        }
+    }
+
+    /// Whether to advertise ourselves on the local network, which gives away our addresses.
+    pub(crate) fn use_local_discovery(&self) -> bool {
+        self.local_discovery && !self.hide_ip
+    }
+}
+
+/// Publishes our relay URL to n0's DNS servers like [`Endpoint::builder`]'s `discovery_n0`
+/// does, but never our direct addresses, not even before we are connected to a relay.
+#[derive(Debug)]
+pub(crate) struct RelayUrlPublisher(PkarrPublisher);
+
+impl RelayUrlPublisher {
+    pub(crate) fn n0_dns(endpoint: &Endpoint) -> Self {
+        Self(
+            PkarrPublisher::n0_dns()
+                .dns_resolver(endpoint.dns_resolver().clone())
+                .build(endpoint.secret_key().clone()),
+        )
+    }
+}
+
+impl Discovery for RelayUrlPublisher {
+    fn publish(&self, data: &NodeData) {
+        let mut data = data.clone();
+        data.clear_direct_addresses();
+        self.0.publish(&data);
+    }
+}
+
+/// A device running Free VoIP found on the local network.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NearbyDevice {
+    pub node_id: NodeId,
+    /// The addresses it advertised.
+    pub addr: NodeAddr,
+    /// Its nickname if it is one of our contacts.
+    pub nickname: Option<String>,
+}
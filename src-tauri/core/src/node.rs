@@ -0,0 +1,2168 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use iroh::{
+    discovery::{
+        dns::DnsDiscovery,
+        mdns::{DiscoveryEvent, MdnsDiscovery},
+    },
+    endpoint::{ConnectionType, PathSelection},
+    protocol::Router,
+    Endpoint, NodeAddr, NodeId, SecretKey, Watcher,
+};
+use iroh_base::ticket::Ticket;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        broadcast::{channel, Receiver, Sender},
+        mpsc, watch, RwLock,
+    },
+    task::JoinSet,
+};
+use tokio_stream::StreamExt;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    backup::{self, Backup, BackupSource},
+    call::{self, AudioConfig, CallControl, CallMedia, CallProtocol, CallStats},
+    contacts::{self, ContactTicket, ContactsProtocol},
+    echo::{EchoStats, EchoTest},
+    error::FreeVoipError,
+    files::{self, FileOffer, FilesProtocol, TransferProgress},
+    history::{CallDirection, CallOutcome, CallRecord},
+    invite::{Invite, InviteBook, InviteTicket},
+    keystore::Sealed,
+    logging,
+    network::{self, DiscoveryMode, NearbyDevice, NetworkSettings, RelayMode},
+    outbox::{DeliveryState, Outbox, OutboxItem, OutboxPayload},
+    presence::{
+        self, Presence, PresenceProtocol, PresenceSettings, PresenceState, PresenceVisibility,
+    },
+    profile::{self, AvatarStore, ProfileProtocol},
+    qr::{self, QrFormat},
+    recording::CallRecorder,
+    storage::{
+        get_json, set_json, Storage, CALL_HISTORY_STORE, CONTACTS_STORE, CREDENTIALS_STORE,
+        OUTBOX_STORE, SETTINGS_STORE, VOICEMAIL_STORE,
+    },
+    unix_timestamp,
+    voicemail::{self, VoicemailInfo, VoicemailProtocol, VoicemailRecorder},
+};
+
+/// Everything the GUI (or any other frontend) may want to react to.
+///
+/// Serializes to just the payload, so [`Event::name`] and the event itself can be forwarded to a
+/// frontend as-is.
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+#[serde(rename_all_fields = "camelCase")]
+pub enum Event {
+    ContactRequest(ContactTicket),
+    ContactsUpdated(Vec<ContactTicket>),
+    RingRequest(ContactTicket),
+    CallHangUp,
+    MissedCall(CallRecord),
+    CallHistoryUpdated(Vec<CallRecord>),
+    PeerRecording(bool),
+    CallRecordingSaved(PathBuf),
+    EchoStats(EchoStats),
+    FileOffer {
+        peer: NodeId,
+        offer: FileOffer,
+    },
+    FileTransferProgress(TransferProgress),
+    VoicemailReceived(VoicemailInfo),
+    OutboxUpdated(Vec<OutboxItem>),
+    PresenceChanged {
+        node_id: NodeId,
+        presence: Presence,
+    },
+    /// The contact has a new avatar, fetch it again with [`Node::avatar`].
+    AvatarChanged(NodeId),
+    /// Someone used one of our invites and was added as a contact.
+    InviteRedeemed(ContactTicket),
+    /// Devices found on the local network came or went.
+    NearbyDevicesChanged(Vec<NearbyDevice>),
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ContactRequest(_) => "contact-request",
+            Event::ContactsUpdated(_) => "contacts-updated",
+            Event::RingRequest(_) => "ring-request",
+            Event::CallHangUp => "call-hang-up",
+            Event::MissedCall(_) => "missed-call",
+            Event::CallHistoryUpdated(_) => "call-history-updated",
+            Event::PeerRecording(_) => "peer-recording",
+            Event::CallRecordingSaved(_) => "call-recording-saved",
+            Event::EchoStats(_) => "echo-stats",
+            Event::FileOffer { .. } => "file-offer",
+            Event::FileTransferProgress(_) => "file-transfer-progress",
+            Event::VoicemailReceived(_) => "voicemail-received",
+            Event::OutboxUpdated(_) => "outbox-updated",
+            Event::PresenceChanged { .. } => "presence-changed",
+            Event::AvatarChanged(_) => "avatar-changed",
+            Event::InviteRedeemed(_) => "invite-redeemed",
+            Event::NearbyDevicesChanged(_) => "nearby-devices-changed",
+        }
+    }
+}
+
+/// Where the node keeps files that do not belong in [`Storage`].
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// Received files are saved here.
+    pub download_dir: PathBuf,
+    /// Voicemails are kept in `voicemail/` below this directory.
+    pub data_dir: PathBuf,
+    /// Call recordings are saved here.
+    pub recordings_dir: PathBuf,
+}
+
+impl NodeConfig {
+    /// Where [`logging::init`] should put the log files.
+    pub fn log_dir(&self) -> PathBuf {
+        self.data_dir.join("logs")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointCredentials {
+    self_ticket: ContactTicket,
+    secret_key: SecretKey,
+}
+
+/// The parts of our self ticket that can change after login. Kept outside of the encrypted
+/// credentials so that changing them doesn't need the passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    nickname: String,
+    /// Hex encoded BLAKE3 hash of our avatar in the [`AvatarStore`].
+    #[serde(default)]
+    avatar: Option<String>,
+}
+
+#[derive(Default)]
+struct NodeState {
+    endpoint_credentials: Option<EndpointCredentials>,
+    router: Option<Router>,
+    call_protocol: Option<CallProtocol>,
+    files_protocol: Option<FilesProtocol>,
+    contact_response_tx: Option<Sender<bool>>,
+    file_response_tx: Option<Sender<bool>>,
+    ring_response_tx: Option<Sender<bool>>,
+    media_tx: Option<Sender<CallMedia>>,
+    media_rx: Option<Receiver<CallMedia>>,
+    voicemail_recorder: Option<VoicemailRecorder>,
+    call_recorder: Option<CallRecorder>,
+    echo_test: Option<EchoTest>,
+    /// Whether the endpoint keeps our IP addresses from peers.
+    hide_ip: bool,
+}
+
+impl NodeState {
+    /// Our contact ticket, with the addresses we can currently be reached at.
+    fn self_ticket(&self) -> Result<ContactTicket, FreeVoipError> {
+        let credentials = self
+            .endpoint_credentials
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        let addr = self
+            .router
+            .as_ref()
+            .map(|r| relay_only(r.endpoint().node_addr(), self.hide_ip));
+        Ok(ContactTicket {
+            addr,
+            ..credentials.self_ticket.clone()
+        })
+    }
+}
+
+/// `addr` without its direct addresses if `hide_ip` is on. Used for our own tickets, and for
+/// peers before dialing them so that we only reach them through their relay.
+pub(crate) fn relay_only(mut addr: NodeAddr, hide_ip: bool) -> NodeAddr {
+    if hide_ip {
+        addr.direct_addresses.clear();
+    }
+    addr
+}
+
+/// The parts of a node that background tasks need, without the router so that they don't keep it
+/// alive.
+struct Shared {
+    storage: Arc<dyn Storage>,
+    config: NodeConfig,
+    events_tx: Sender<Event>,
+    outbox: Outbox,
+    avatars: AvatarStore,
+    own_avatar_tx: watch::Sender<Option<String>>,
+    own_presence_tx: watch::Sender<Presence>,
+    /// Who may probe our presence.
+    presence_audience_tx: watch::Sender<HashSet<NodeId>>,
+    /// Node IDs of our contacts.
+    contact_ids_tx: watch::Sender<HashSet<NodeId>>,
+    /// Stored addresses of our contacts.
+    contact_addrs_tx: watch::Sender<HashMap<NodeId, NodeAddr>>,
+    /// Last known presence of each contact.
+    presences: Mutex<HashMap<NodeId, Presence>>,
+    /// Invites we issued and have not revoked.
+    invites_tx: watch::Sender<Vec<Invite>>,
+    /// Devices found on the local network, with the addresses they advertised.
+    nearby: Mutex<HashMap<NodeId, NodeAddr>>,
+    /// How the GUI's audio encoder is configured.
+    audio_config_tx: watch::Sender<AudioConfig>,
+    /// How the peer of the ongoing call encodes its audio.
+    remote_audio_config_tx: watch::Sender<AudioConfig>,
+}
+
+impl Shared {
+    fn emit(&self, event: Event) {
+        // Nobody listening is fine
+        _ = self.events_tx.send(event);
+    }
+
+    fn voicemail_dir(&self) -> PathBuf {
+        self.config.data_dir.join("voicemail")
+    }
+
+    /// Credentials stored in plain text, from before they were encrypted with a passphrase.
+    fn plain_credentials(&self) -> Result<Option<EndpointCredentials>, FreeVoipError> {
+        get_json(self.storage.as_ref(), CREDENTIALS_STORE, "endpoint")
+    }
+
+    fn sealed_credentials(&self) -> Result<Option<Sealed>, FreeVoipError> {
+        get_json(
+            self.storage.as_ref(),
+            CREDENTIALS_STORE,
+            "encryptedEndpoint",
+        )
+    }
+
+    /// Stores `credentials` encrypted with `passphrase`, replacing any plain text copy.
+    fn store_credentials(
+        &self,
+        credentials: &EndpointCredentials,
+        passphrase: &str,
+    ) -> Result<(), FreeVoipError> {
+        let sealed = Sealed::seal(&serde_json::to_vec(credentials)?, passphrase)?;
+        set_json(
+            self.storage.as_ref(),
+            CREDENTIALS_STORE,
+            "encryptedEndpoint",
+            &sealed,
+        )?;
+        self.storage.delete(CREDENTIALS_STORE, "endpoint")
+    }
+
+    fn profile(&self) -> Result<Option<Profile>, FreeVoipError> {
+        get_json(self.storage.as_ref(), CREDENTIALS_STORE, "profile")
+    }
+
+    fn set_profile(&self, profile: &Profile) -> Result<(), FreeVoipError> {
+        set_json(self.storage.as_ref(), CREDENTIALS_STORE, "profile", profile)?;
+        self.own_avatar_tx.send_replace(profile.avatar.clone());
+        Ok(())
+    }
+
+    /// Lets every contact know about our profile, now or once they are reachable.
+    fn announce_profile(&self) -> Result<(), FreeVoipError> {
+        // Updates are sent with the profile at delivery time, so one pending update is enough
+        let pending = self
+            .outbox
+            .items()
+            .into_iter()
+            .filter(|i| {
+                matches!(i.payload, OutboxPayload::ProfileUpdate)
+                    && i.state != DeliveryState::Delivered
+            })
+            .map(|i| i.recipient)
+            .collect::<Vec<_>>();
+        for contact in self.contacts()? {
+            if !pending.contains(&contact.node_id) {
+                self.outbox
+                    .enqueue(contact.node_id, OutboxPayload::ProfileUpdate);
+            }
+        }
+        self.save_outbox()
+    }
+
+    /// Hashes of our contacts' avatars.
+    fn contact_avatars(&self) -> Result<HashMap<NodeId, String>, FreeVoipError> {
+        Ok(get_json(self.storage.as_ref(), CONTACTS_STORE, "avatars")?.unwrap_or_default())
+    }
+
+    fn contacts(&self) -> Result<Vec<ContactTicket>, FreeVoipError> {
+        Ok(get_json(self.storage.as_ref(), CONTACTS_STORE, "contacts")?.unwrap_or_default())
+    }
+
+    fn save_invites(&self) -> Result<(), FreeVoipError> {
+        let invites = self.invites_tx.borrow().clone();
+        set_json(self.storage.as_ref(), CONTACTS_STORE, "invites", &invites)
+    }
+
+    /// Where to connect to `node_id`: the stored addresses of the contact if there are any.
+    fn contact_addr(&self, node_id: NodeId) -> Result<NodeAddr, FreeVoipError> {
+        Ok(self
+            .contacts()?
+            .iter()
+            .find(|c| c.node_id == node_id)
+            .map_or_else(|| NodeAddr::new(node_id), ContactTicket::node_addr))
+    }
+
+    /// Stores the addresses of a contact after connecting to them, if they changed.
+    fn remember_addr(&self, addr: NodeAddr) -> Result<(), FreeVoipError> {
+        let mut contacts = self.contacts()?;
+        let Some(contact) = contacts.iter_mut().find(|c| c.node_id == addr.node_id) else {
+            return Ok(());
+        };
+        if contact.addr.as_ref() == Some(&addr) {
+            return Ok(());
+        }
+
+        contact.addr = Some(addr);
+        self.set_contacts(contacts)
+    }
+
+    fn add_contact(&self, contact_ticket: ContactTicket) -> Result<(), FreeVoipError> {
+        let mut contacts = self.contacts()?;
+
+        // Check for duplicates
+        let duplicate_contact = contacts
+            .iter()
+            .find(|c| c.node_id == contact_ticket.node_id);
+        if let Some(duplicate_contact) = duplicate_contact {
+            return Err(FreeVoipError::InvalidState(format!(
+                "This contact is already in your list as {}.",
+                duplicate_contact.nickname
+            )));
+        }
+
+        // Update contacts store
+        contacts.push(contact_ticket);
+        self.set_contacts(contacts)
+    }
+
+    fn set_contacts(&self, contacts: Vec<ContactTicket>) -> Result<(), FreeVoipError> {
+        set_json(self.storage.as_ref(), CONTACTS_STORE, "contacts", &contacts)?;
+        self.publish_contacts(&contacts);
+        self.refresh_presence_audience()?;
+        self.emit(Event::ContactsUpdated(contacts));
+        Ok(())
+    }
+
+    /// Lets background tasks know who our contacts are and where to reach them.
+    fn publish_contacts(&self, contacts: &[ContactTicket]) {
+        self.contact_ids_tx
+            .send_replace(contacts.iter().map(|c| c.node_id).collect());
+        self.contact_addrs_tx.send_replace(
+            contacts
+                .iter()
+                .map(|c| (c.node_id, c.node_addr()))
+                .collect(),
+        );
+    }
+
+    fn presence_settings(&self) -> Result<PresenceSettings, FreeVoipError> {
+        Ok(
+            get_json(self.storage.as_ref(), SETTINGS_STORE, "presenceSettings")?
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Recomputes who may see our presence after contacts or presence settings changed.
+    fn refresh_presence_audience(&self) -> Result<(), FreeVoipError> {
+        let settings = self.presence_settings()?;
+        let audience = match settings.visibility {
+            PresenceVisibility::Contacts => self
+                .contacts()?
+                .into_iter()
+                .map(|c| c.node_id)
+                .filter(|id| !settings.hidden_from.contains(id))
+                .collect(),
+            PresenceVisibility::Nobody => HashSet::new(),
+        };
+        self.presence_audience_tx.send_replace(audience);
+        Ok(())
+    }
+
+    /// Records the presence of a contact, emitting an event if it changed.
+    fn set_contact_presence(&self, node_id: NodeId, presence: Presence) {
+        let previous = self
+            .presences
+            .lock()
+            .unwrap()
+            .insert(node_id, presence.clone());
+        if previous.as_ref() != Some(&presence) {
+            self.emit(Event::PresenceChanged { node_id, presence });
+        }
+    }
+
+    /// Probes every contact for their presence until `endpoint` is closed.
+    fn probe_presences(self: Arc<Self>, endpoint: Endpoint, hide_ip: bool) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(presence::PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if endpoint.is_closed() {
+                    break;
+                }
+                // Probing would give us away while invisible
+                if self.own_presence_tx.borrow().state == PresenceState::Offline {
+                    continue;
+                }
+                let contacts = match self.contacts() {
+                    Ok(contacts) => contacts,
+                    Err(e) => {
+                        error!("Failed to load contacts for presence: {e}");
+                        continue;
+                    }
+                };
+
+                let mut probes = JoinSet::new();
+                for contact in contacts {
+                    let endpoint = endpoint.clone();
+                    probes.spawn(async move {
+                        let addr = relay_only(contact.node_addr(), hide_ip);
+                        let probe = PresenceProtocol::probe(&endpoint, addr);
+                        let presence =
+                            match tokio::time::timeout(presence::PROBE_TIMEOUT, probe).await {
+                                Ok(Ok(presence)) => presence,
+                                _ => Presence::OFFLINE,
+                            };
+                        (contact.node_id, presence)
+                    });
+                }
+                while let Some(result) = probes.join_next().await {
+                    if let Ok((node_id, presence)) = result {
+                        self.set_contact_presence(node_id, presence);
+                    }
+                }
+            }
+        });
+    }
+
+    fn network_settings(&self) -> Result<NetworkSettings, FreeVoipError> {
+        Ok(get_json(self.storage.as_ref(), SETTINGS_STORE, "networkSettings")?.unwrap_or_default())
+    }
+
+    /// Devices found on the local network, named after the contact if they are one.
+    fn nearby_devices(&self) -> Result<Vec<NearbyDevice>, FreeVoipError> {
+        let contacts = self.contacts()?;
+        let nearby = self.nearby.lock().unwrap().clone();
+        Ok(nearby
+            .into_values()
+            .map(|addr| NearbyDevice {
+                node_id: addr.node_id,
+                nickname: contacts
+                    .iter()
+                    .find(|c| c.node_id == addr.node_id)
+                    .map(|c| c.nickname.clone()),
+                addr,
+            })
+            .collect())
+    }
+
+    /// Keeps track of the devices `mdns` finds on the local network until `endpoint` is closed.
+    fn watch_nearby(self: Arc<Self>, endpoint: Endpoint, mdns: MdnsDiscovery) {
+        self.nearby.lock().unwrap().clear();
+        tokio::spawn(async move {
+            let mut discovered = mdns.subscribe().await;
+            drop(mdns);
+            let mut interval = tokio::time::interval(network::NEARBY_CHECK_INTERVAL);
+            loop {
+                let changed = tokio::select! {
+                    event = discovered.next() => match event {
+                        Some(DiscoveryEvent::Discovered { node_info, .. }) => {
+                            let addr = node_info.into_node_addr();
+                            let mut nearby = self.nearby.lock().unwrap();
+                            nearby.insert(addr.node_id, addr.clone()) != Some(addr)
+                        }
+                        Some(DiscoveryEvent::Expired { node_id }) => {
+                            self.nearby.lock().unwrap().remove(&node_id).is_some()
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        if endpoint.is_closed() {
+                            break;
+                        }
+                        false
+                    }
+                };
+
+                if changed {
+                    match self.nearby_devices() {
+                        Ok(devices) => self.emit(Event::NearbyDevicesChanged(devices)),
+                        Err(e) => error!("Failed to list nearby devices: {e}"),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Takes on the new nickname and avatar of a contact, ignoring anyone who isn't one.
+    fn update_contact(
+        &self,
+        contact_ticket: ContactTicket,
+        avatar: Option<String>,
+    ) -> Result<(), FreeVoipError> {
+        let mut contacts = self.contacts()?;
+        let Some(contact) = contacts
+            .iter_mut()
+            .find(|c| c.node_id == contact_ticket.node_id)
+        else {
+            return Ok(());
+        };
+
+        contact.nickname = contact_ticket.nickname;
+        if contact_ticket.addr.is_some() {
+            contact.addr = contact_ticket.addr;
+        }
+        self.set_contacts(contacts)?;
+
+        // The new avatar is fetched once it is asked for
+        let mut avatars = self.contact_avatars()?;
+        let node_id = contact_ticket.node_id;
+        let changed = match avatar {
+            Some(avatar) => avatars.insert(node_id, avatar.clone()) != Some(avatar),
+            None => avatars.remove(&node_id).is_some(),
+        };
+        if changed {
+            set_json(self.storage.as_ref(), CONTACTS_STORE, "avatars", &avatars)?;
+            self.emit(Event::AvatarChanged(node_id));
+        }
+
+        Ok(())
+    }
+
+    fn call_history(&self) -> Result<Vec<CallRecord>, FreeVoipError> {
+        Ok(get_json(self.storage.as_ref(), CALL_HISTORY_STORE, "calls")?.unwrap_or_default())
+    }
+
+    /// Appends a call to the call history.
+    fn record_call(&self, record: CallRecord) -> Result<(), FreeVoipError> {
+        let mut history = self.call_history()?;
+        history.push(record.clone());
+        set_json(self.storage.as_ref(), CALL_HISTORY_STORE, "calls", &history)?;
+        self.emit(Event::CallHistoryUpdated(history));
+
+        if record.direction == CallDirection::Incoming && record.outcome == CallOutcome::Missed {
+            self.emit(Event::MissedCall(record));
+        }
+
+        Ok(())
+    }
+
+    fn voicemails(&self) -> Result<Vec<VoicemailInfo>, FreeVoipError> {
+        Ok(get_json(self.storage.as_ref(), VOICEMAIL_STORE, "voicemails")?.unwrap_or_default())
+    }
+
+    /// Adds a received voicemail to the inbox.
+    fn store_voicemail(&self, info: VoicemailInfo) -> Result<(), FreeVoipError> {
+        let mut voicemails = self.voicemails()?;
+
+        // Redeliveries of the same voicemail have the same ID
+        if voicemails.iter().any(|v| v.id == info.id) {
+            return Ok(());
+        }
+        voicemails.push(info.clone());
+        set_json(
+            self.storage.as_ref(),
+            VOICEMAIL_STORE,
+            "voicemails",
+            &voicemails,
+        )?;
+        self.emit(Event::VoicemailReceived(info));
+
+        Ok(())
+    }
+
+    /// Loads `owner`'s queued items into the outbox, unless they are there already.
+    fn load_outbox(&self, owner: NodeId) -> Result<(), FreeVoipError> {
+        if self.outbox.owner() == Some(owner) {
+            return Ok(());
+        }
+
+        let prefix = format!("{owner}/");
+        let mut items = vec![];
+        for (key, value) in self.storage.entries(OUTBOX_STORE)? {
+            if key.starts_with(&prefix) {
+                let contact_items = serde_json::from_value::<Vec<OutboxItem>>(value)?;
+                items.extend(contact_items);
+            }
+        }
+        self.outbox.load(owner, items);
+        self.emit(Event::OutboxUpdated(self.outbox.items()));
+
+        Ok(())
+    }
+
+    /// Stops delivering queued items and forgets them, also removing them from storage with
+    /// `wipe`. They are loaded again when their identity comes back online.
+    fn unload_outbox(&self, wipe: bool) -> Result<(), FreeVoipError> {
+        // Changes are saved in the background, which may not have caught up yet
+        if !wipe {
+            self.save_outbox()?;
+        }
+        if let Some(owner) = self.outbox.unload() {
+            if wipe {
+                self.delete_outbox_entries(owner)?;
+            }
+        }
+        self.emit(Event::OutboxUpdated(vec![]));
+
+        Ok(())
+    }
+
+    fn delete_outbox_entries(&self, owner: NodeId) -> Result<(), FreeVoipError> {
+        let prefix = format!("{owner}/");
+        for (key, _) in self.storage.entries(OUTBOX_STORE)? {
+            if key.starts_with(&prefix) {
+                self.storage.delete(OUTBOX_STORE, &key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the outbox to storage, one entry per identity and recipient.
+    fn save_outbox(&self) -> Result<(), FreeVoipError> {
+        let Some((owner, items)) = self.outbox.owned_items() else {
+            return Ok(());
+        };
+
+        let mut contact_items = HashMap::<NodeId, Vec<&OutboxItem>>::new();
+        for item in items.iter() {
+            contact_items.entry(item.recipient).or_default().push(item);
+        }
+
+        self.delete_outbox_entries(owner)?;
+        for (recipient, items) in contact_items {
+            set_json(
+                self.storage.as_ref(),
+                OUTBOX_STORE,
+                &format!("{owner}/{recipient}"),
+                &items,
+            )?;
+        }
+        self.emit(Event::OutboxUpdated(items));
+
+        Ok(())
+    }
+
+    fn handle_outbox_updates(self: Arc<Self>, mut update_rx: Receiver<OutboxItem>) {
+        tokio::spawn(async move {
+            while let Ok(item) = update_rx.recv().await {
+                // Queued contact requests add the contact once they are accepted
+                if let (
+                    OutboxPayload::ContactRequest { ref contact, .. },
+                    DeliveryState::Delivered,
+                ) = (&item.payload, item.state)
+                {
+                    if item.accepted == Some(true) {
+                        if let Err(e) = self.add_contact(contact.clone()) {
+                            warn!("Failed to add contact from outbox: {e}");
+                        }
+                    }
+                }
+
+                if let Err(e) = self.save_outbox() {
+                    error!("Failed to save outbox: {e}");
+                }
+            }
+        });
+    }
+}
+
+/// A Free VoIP node: identity, contacts, calls and everything around them.
+///
+/// Cheap to clone, all clones share the same node.
+#[derive(Clone)]
+pub struct Node {
+    shared: Arc<Shared>,
+    state: Arc<RwLock<NodeState>>,
+}
+
+/// Binds an endpoint configured with `settings`, returning the local network discovery it uses
+/// if it is enabled and available.
+async fn build_endpoint(
+    secret_key: Option<SecretKey>,
+    settings: &NetworkSettings,
+) -> Result<(Endpoint, Option<MdnsDiscovery>), iroh::endpoint::BindError> {
+    let builder = Endpoint::builder().relay_mode(settings.iroh_relay_mode());
+    let builder = match settings.discovery {
+        // Only look up peers here, we publish ourselves once bound
+        DiscoveryMode::Default if settings.hide_ip => builder.add_discovery(DnsDiscovery::n0_dns()),
+        DiscoveryMode::Default => builder.discovery_n0(),
+        DiscoveryMode::Disabled => builder,
+    };
+    let builder = if settings.hide_ip {
+        // Relay-only path selection keeps iroh from sending to or hole punching towards direct
+        // addresses. iroh has no switch for port mapping and address discovery through the
+        // relays, but with only loopback sockets a port mapping leads nowhere and the discovery
+        // probes can't leave the machine
+        builder
+            .bind_addr_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .bind_addr_v6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0))
+            .path_selection(PathSelection::RelayOnly)
+    } else {
+        builder
+    };
+
+    let builder = if let Some(key) = secret_key {
+        builder.secret_key(key)
+    } else {
+        builder
+    };
+
+    let endpoint = builder.bind().await?;
+    info!(node_id = %endpoint.node_id(), "Endpoint created");
+
+    if settings.hide_ip && settings.discovery == DiscoveryMode::Default {
+        endpoint
+            .discovery()
+            .add(network::RelayUrlPublisher::n0_dns(&endpoint));
+    }
+
+    // Added after binding, as the endpoint only gets its node ID then
+    let mdns = if settings.use_local_discovery() {
+        match MdnsDiscovery::builder().build(endpoint.node_id()) {
+            Ok(mdns) => {
+                endpoint.discovery().add(mdns.clone());
+                Some(mdns)
+            }
+            Err(e) => {
+                warn!("Local network discovery unavailable: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Ok((endpoint, mdns))
+}
+
+/// `known` updated with the path `endpoint` is using to reach the node, after connecting to it.
+fn connected_addr(endpoint: &Endpoint, known: NodeAddr) -> NodeAddr {
+    let mut addr = known;
+    match endpoint.conn_type(addr.node_id).map(|mut c| c.get()) {
+        Some(ConnectionType::Direct(direct)) => {
+            addr.direct_addresses = [direct].into();
+        }
+        Some(ConnectionType::Relay(relay_url)) => addr.relay_url = Some(relay_url),
+        Some(ConnectionType::Mixed(direct, relay_url)) => {
+            addr.direct_addresses = [direct].into();
+            addr.relay_url = Some(relay_url);
+        }
+        Some(ConnectionType::None) | None => {}
+    }
+    addr
+}
+
+/// Parses a serialized contact or invite ticket, returning the contact it is for and the invite.
+fn parse_ticket(
+    serialized_ticket: &str,
+) -> Result<(ContactTicket, Option<InviteTicket>), FreeVoipError> {
+    if let Ok(contact_ticket) = <ContactTicket as Ticket>::deserialize(serialized_ticket) {
+        return Ok((contact_ticket, None));
+    }
+
+    let invite = <InviteTicket as Ticket>::deserialize(serialized_ticket)
+        .map_err(|_e| FreeVoipError::InvalidTicket)?;
+    invite.verify()?;
+    if invite.expires_at <= unix_timestamp() {
+        return Err(FreeVoipError::invalid_state("This invite has expired"));
+    }
+    Ok((invite.issuer.clone(), Some(invite)))
+}
+
+impl Node {
+    /// Creates a node from what is in `storage`. Must be called within a Tokio runtime.
+    pub async fn new(storage: Arc<dyn Storage>, config: NodeConfig) -> Result<Self, FreeVoipError> {
+        // Populate with stored credentials
+        let state = NodeState {
+            endpoint_credentials: get_json(storage.as_ref(), CREDENTIALS_STORE, "endpoint")?,
+            ..Default::default()
+        };
+
+        // Queued outgoing items are loaded and delivered once logged in
+        let (outbox_update_tx, outbox_update_rx) = channel::<OutboxItem>(32);
+        let own_avatar = get_json::<Profile>(storage.as_ref(), CREDENTIALS_STORE, "profile")?
+            .and_then(|p| p.avatar);
+        let (own_avatar_tx, own_avatar_rx) = watch::channel(own_avatar);
+        let (contact_addrs_tx, contact_addrs_rx) = watch::channel(HashMap::new());
+        let outbox = Outbox::new(outbox_update_tx, own_avatar_rx, contact_addrs_rx);
+
+        let own_presence = get_json(storage.as_ref(), SETTINGS_STORE, "presence")?;
+        let (own_presence_tx, _) = watch::channel(own_presence.unwrap_or_default());
+
+        let invites = get_json(storage.as_ref(), CONTACTS_STORE, "invites")?;
+        let (invites_tx, _) = watch::channel(invites.unwrap_or_default());
+
+        let (events_tx, _) = channel::<Event>(64);
+        let shared = Arc::new(Shared {
+            avatars: AvatarStore::new(config.data_dir.join("avatars")),
+            storage,
+            config,
+            events_tx,
+            outbox,
+            own_avatar_tx,
+            own_presence_tx,
+            presence_audience_tx: watch::Sender::default(),
+            contact_ids_tx: watch::Sender::default(),
+            contact_addrs_tx,
+            presences: Mutex::default(),
+            invites_tx,
+            nearby: Mutex::default(),
+            audio_config_tx: watch::Sender::default(),
+            remote_audio_config_tx: watch::Sender::default(),
+        });
+        shared.publish_contacts(&shared.contacts()?);
+        shared.refresh_presence_audience()?;
+        shared.clone().handle_outbox_updates(outbox_update_rx);
+
+        Ok(Self {
+            shared,
+            state: Arc::new(RwLock::new(state)),
+        })
+    }
+
+    /// Subscribes to the node's events.
+    pub fn events(&self) -> Receiver<Event> {
+        self.shared.events_tx.subscribe()
+    }
+
+    fn build_router(
+        &self,
+        state: &mut NodeState,
+        endpoint: Endpoint,
+        mdns: Option<MdnsDiscovery>,
+    ) -> Router {
+        let contacts = {
+            // Create and set protocol communication channels
+            let (request_tx, mut request_rx) = channel::<ContactTicket>(8);
+            let (response_tx, response_rx) = channel::<bool>(8);
+            state.contact_response_tx = Some(response_tx);
+
+            // Listen to contact requests
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(ticket) = request_rx.recv().await {
+                    shared.emit(Event::ContactRequest(ticket));
+                }
+            });
+
+            // Record calls we missed while offline
+            let (missed_call_tx, mut missed_call_rx) = channel::<(ContactTicket, u64)>(8);
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok((caller, timestamp)) = missed_call_rx.recv().await {
+                    let record = CallRecord {
+                        peer: caller.node_id,
+                        direction: CallDirection::Incoming,
+                        started_at: timestamp,
+                        answered_at: None,
+                        ended_at: timestamp,
+                        duration: 0,
+                        outcome: CallOutcome::Missed,
+                    };
+                    if let Err(e) = shared.record_call(record) {
+                        error!("Failed to record missed call: {e}");
+                    }
+                }
+            });
+
+            // Keep contacts' nicknames up to date
+            let (profile_update_tx, mut profile_update_rx) =
+                channel::<(ContactTicket, Option<String>)>(8);
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok((contact_ticket, avatar)) = profile_update_rx.recv().await {
+                    if let Err(e) = shared.update_contact(contact_ticket, avatar) {
+                        error!("Failed to update contact: {e}");
+                    }
+                }
+            });
+
+            // Contacts with a valid invite are added without asking
+            let (invited_tx, mut invited_rx) = channel::<ContactTicket>(8);
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(contact_ticket) = invited_rx.recv().await {
+                    if let Err(e) = shared.save_invites() {
+                        error!("Failed to save invites: {e}");
+                    }
+                    match shared.add_contact(contact_ticket.clone()) {
+                        Ok(()) => shared.emit(Event::InviteRedeemed(contact_ticket)),
+                        Err(e) => warn!("Failed to add invited contact: {e}"),
+                    }
+                }
+            });
+
+            Arc::new(ContactsProtocol::new(
+                request_tx,
+                response_rx,
+                missed_call_tx,
+                profile_update_tx,
+                InviteBook::new(endpoint.node_id(), self.shared.invites_tx.clone()),
+                invited_tx,
+            ))
+        };
+
+        let files = {
+            let (offer_tx, mut offer_rx) = channel::<(NodeId, FileOffer)>(8);
+            let (response_tx, response_rx) = channel::<bool>(8);
+            let (progress_tx, mut progress_rx) = channel::<TransferProgress>(32);
+            state.file_response_tx = Some(response_tx);
+
+            // Listen to file offers
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok((peer, offer)) = offer_rx.recv().await {
+                    shared.emit(Event::FileOffer { peer, offer });
+                }
+            });
+
+            // Listen to transfer progress
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(progress) = progress_rx.recv().await {
+                    shared.emit(Event::FileTransferProgress(progress));
+                }
+            });
+
+            FilesProtocol::new(
+                offer_tx,
+                response_rx,
+                progress_tx,
+                self.shared.config.download_dir.clone(),
+                self.shared.contact_ids_tx.subscribe(),
+            )
+        };
+        state.files_protocol = Some(files.clone());
+
+        let call = {
+            let (ring_tx, mut ring_rx) = channel::<ContactTicket>(2);
+            let (response_tx, response_rx) = channel::<bool>(2);
+            state.ring_response_tx = Some(response_tx);
+
+            // Listen to ring requests
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(ticket) = ring_rx.recv().await {
+                    shared.emit(Event::RingRequest(ticket));
+                }
+            });
+
+            let (in_media_tx, in_media_rx) = channel::<CallMedia>(32);
+            let (out_media_tx, out_media_rx) = channel::<CallMedia>(32);
+            let (hang_up_tx, mut hang_up_rx) = channel::<()>(1);
+            state.media_tx = Some(out_media_tx);
+            state.media_rx = Some(in_media_rx);
+
+            let shared = self.shared.clone();
+            let weak_state = Arc::downgrade(&self.state);
+            tokio::spawn(async move {
+                while let Ok(()) = hang_up_rx.recv().await {
+                    // Finish any recording or echo test of the call that ended
+                    let (recorder, echo_test) = match Weak::upgrade(&weak_state) {
+                        Some(state) => {
+                            let mut state = state.write().await;
+                            (state.call_recorder.take(), state.echo_test.take())
+                        }
+                        None => (None, None),
+                    };
+                    if let Some(recorder) = recorder {
+                        match recorder.stop().await {
+                            Ok(path) => shared.emit(Event::CallRecordingSaved(path)),
+                            Err(err) => error!("Failed to finish call recording: {err}"),
+                        }
+                    }
+                    if let Some(echo_test) = echo_test {
+                        match echo_test.stop().await {
+                            Ok(stats) => shared.emit(Event::EchoStats(stats)),
+                            Err(err) => warn!("Failed to finish echo test: {err}"),
+                        }
+                    }
+
+                    // The next peer may not tell us, so don't keep assuming this one's config
+                    shared
+                        .remote_audio_config_tx
+                        .send_replace(AudioConfig::default());
+                    shared.emit(Event::CallHangUp);
+                }
+            });
+
+            // Listen to control messages from the peer
+            let (control_tx, mut control_rx) = channel::<CallControl>(8);
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(control) = control_rx.recv().await {
+                    match control {
+                        CallControl::Recording { active } => {
+                            shared.emit(Event::PeerRecording(active))
+                        }
+                        CallControl::AudioConfig(config) => {
+                            shared.remote_audio_config_tx.send_replace(config);
+                        }
+                    }
+                }
+            });
+
+            // Record finished calls
+            let (history_tx, mut history_rx) = channel::<CallRecord>(8);
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(record) = history_rx.recv().await {
+                    if let Err(e) = shared.record_call(record) {
+                        error!("Failed to record call: {e}");
+                    }
+                }
+            });
+
+            // Files sent during a call arrive as extra streams on the call's connection
+            let (extra_stream_tx, mut extra_stream_rx) = mpsc::unbounded_channel();
+            let files_clone = files.clone();
+            tokio::spawn(async move {
+                while let Some((peer, stream_tx, stream_rx)) = extra_stream_rx.recv().await {
+                    let files = files_clone.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = files.receive(peer, stream_tx, stream_rx).await {
+                            warn!(%peer, "In-call file transfer failed: {e}");
+                        }
+                    });
+                }
+            });
+
+            CallProtocol::new(
+                ring_tx,
+                response_rx,
+                in_media_tx,
+                out_media_rx,
+                hang_up_tx,
+                extra_stream_tx,
+                history_tx,
+                control_tx,
+            )
+        };
+
+        let voicemail = {
+            let (received_tx, mut received_rx) = channel::<VoicemailInfo>(8);
+
+            // Listen to received voicemails
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                while let Ok(info) = received_rx.recv().await {
+                    if let Err(e) = shared.store_voicemail(info) {
+                        error!("Failed to store voicemail: {e}");
+                    }
+                }
+            });
+
+            VoicemailProtocol::new(
+                received_tx,
+                self.shared.voicemail_dir().join("inbox"),
+                self.shared.contact_ids_tx.subscribe(),
+            )
+        };
+
+        let profile = ProfileProtocol::new(
+            self.shared.avatars.clone(),
+            self.shared.own_avatar_tx.subscribe(),
+        );
+
+        let presence = PresenceProtocol::new(
+            self.shared.own_presence_tx.subscribe(),
+            self.shared.presence_audience_tx.subscribe(),
+        );
+        self.shared
+            .clone()
+            .probe_presences(endpoint.clone(), state.hide_ip);
+        if let Some(mdns) = mdns {
+            self.shared.clone().watch_nearby(endpoint.clone(), mdns);
+        }
+
+        // HACK: only used to call `ring` because it requires GUI-Iroh bridging channels
+        state.call_protocol = Some(call.clone());
+
+        // Resume delivering queued items from the new endpoint
+        if let Some(ref credentials) = state.endpoint_credentials {
+            self.shared.outbox.start(
+                endpoint.clone(),
+                credentials.self_ticket.clone(),
+                state.hide_ip,
+            );
+        }
+
+        Router::builder(endpoint)
+            .accept(contacts::ALPN, contacts.clone())
+            .accept(contacts::LEGACY_ALPN, contacts)
+            .accept(call::ALPN, call)
+            .accept(files::ALPN, files)
+            .accept(voicemail::ALPN, voicemail)
+            .accept(profile::ALPN, profile)
+            .accept(presence::ALPN, presence)
+            .spawn()
+    }
+
+    /// Brings the stored identity online, returning whether there was one.
+    ///
+    /// Fails with [`FreeVoipError::Locked`] if the identity is encrypted, until [`Node::unlock`]
+    /// is called.
+    pub async fn restore_login(&self) -> Result<bool, FreeVoipError> {
+        let mut state = self.state.write().await;
+
+        if state.router.is_some() {
+            // Endpoint and Router are already active, nothing to restore.
+            return Ok(true);
+        }
+
+        // Credentials are forgotten on logout but may still be stored
+        if state.endpoint_credentials.is_none() {
+            state.endpoint_credentials = self.shared.plain_credentials()?;
+        }
+        if state.endpoint_credentials.is_none() && self.shared.sealed_credentials()?.is_some() {
+            return Err(FreeVoipError::Locked);
+        }
+
+        self.go_online(&mut state).await
+    }
+
+    /// Decrypts the stored identity with `passphrase` and brings it online.
+    pub async fn unlock(&self, passphrase: &str) -> Result<(), FreeVoipError> {
+        let mut state = self.state.write().await;
+
+        if state.router.is_some() {
+            return Ok(());
+        }
+
+        let sealed = self
+            .shared
+            .sealed_credentials()?
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        state.endpoint_credentials = Some(serde_json::from_slice(&sealed.open(passphrase)?)?);
+        info!("Unlocked identity");
+
+        self.go_online(&mut state).await?;
+        Ok(())
+    }
+
+    /// Re-encrypts the stored identity with `new_passphrase`. An identity stored before
+    /// passphrases were introduced has no `current_passphrase`, so it is not checked.
+    pub async fn change_passphrase(
+        &self,
+        current_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<(), FreeVoipError> {
+        // Hold the lock so that a concurrent login can't be overwritten
+        let _state = self.state.write().await;
+
+        let credentials = match self.shared.sealed_credentials()? {
+            Some(sealed) => serde_json::from_slice(&sealed.open(current_passphrase)?)?,
+            None => self
+                .shared
+                .plain_credentials()?
+                .ok_or(FreeVoipError::NotLoggedIn)?,
+        };
+        self.shared
+            .store_credentials(&credentials, new_passphrase)?;
+        info!("Changed passphrase");
+
+        Ok(())
+    }
+
+    /// Builds the endpoint and router for the credentials in `state`, returning whether there
+    /// were any.
+    async fn go_online(&self, state: &mut NodeState) -> Result<bool, FreeVoipError> {
+        if let Some(ref mut credentials) = state.endpoint_credentials {
+            if let Some(profile) = self.shared.profile()? {
+                credentials.self_ticket.nickname = profile.nickname;
+            }
+
+            let settings = self.shared.network_settings()?;
+            let (endpoint, mdns) =
+                build_endpoint(Some(credentials.secret_key.clone()), &settings).await?;
+            self.shared.load_outbox(endpoint.node_id())?;
+            state.hide_ip = settings.hide_ip;
+            state.router = Some(self.build_router(state, endpoint, mdns));
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Creates an identity with the given nickname, encrypts it with `passphrase` and brings it
+    /// online. The identity is new unless a restored `secret_key` is given.
+    pub async fn login(
+        &self,
+        nickname: String,
+        passphrase: &str,
+        secret_key: Option<SecretKey>,
+    ) -> Result<(), FreeVoipError> {
+        if passphrase.is_empty() {
+            return Err(FreeVoipError::invalid_state(
+                "The passphrase must not be empty",
+            ));
+        }
+
+        info!(
+            ?nickname,
+            restored = secret_key.is_some(),
+            "Creating identity"
+        );
+        let mut state = self.state.write().await;
+
+        // Create new endpoint and router
+        let settings = self.shared.network_settings()?;
+        let (endpoint, mdns) = build_endpoint(secret_key, &settings).await?;
+        let credentials = EndpointCredentials {
+            self_ticket: ContactTicket::new(nickname, endpoint.node_id()),
+            secret_key: endpoint.secret_key().clone(),
+        };
+
+        // Store credentials
+        self.shared.store_credentials(&credentials, passphrase)?;
+        self.shared.set_profile(&Profile {
+            nickname: credentials.self_ticket.nickname.clone(),
+            avatar: None,
+        })?;
+        state.endpoint_credentials = Some(credentials);
+        state.hide_ip = settings.hide_ip;
+        // Keep the items of an identity that is still logged in for when it comes back
+        if self.shared.outbox.owner() != Some(endpoint.node_id()) {
+            self.shared.unload_outbox(false)?;
+        }
+        self.shared.load_outbox(endpoint.node_id())?;
+        let router = self.build_router(&mut state, endpoint, mdns);
+
+        // Close existing endpoint if it exists
+        if let Some(ref existing_router) = state.router {
+            existing_router.shutdown().await?;
+        }
+        state.router = Some(router);
+
+        Ok(())
+    }
+
+    /// Changes our nickname and lets every contact know, now or once they are reachable.
+    pub async fn update_profile(&self, nickname: String) -> Result<(), FreeVoipError> {
+        let nickname = nickname.trim().to_owned();
+        if nickname.is_empty() {
+            return Err(FreeVoipError::invalid_state(
+                "The nickname must not be empty",
+            ));
+        }
+
+        let mut state = self.state.write().await;
+        let router = state.router.clone().ok_or(FreeVoipError::NotLoggedIn)?;
+        let hide_ip = state.hide_ip;
+        let credentials = state
+            .endpoint_credentials
+            .as_mut()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+
+        info!(?nickname, "Updating profile");
+        credentials.self_ticket.nickname = nickname.clone();
+        let avatar = self.shared.own_avatar_tx.borrow().clone();
+        self.shared.set_profile(&Profile { nickname, avatar })?;
+
+        // Queued items go out with the new ticket
+        self.shared.outbox.start(
+            router.endpoint().clone(),
+            credentials.self_ticket.clone(),
+            hide_ip,
+        );
+        self.shared.announce_profile()
+    }
+
+    /// Replaces our avatar, or removes it if `image` is `None`, and lets every contact know.
+    pub async fn set_avatar(&self, image: Option<Vec<u8>>) -> Result<(), FreeVoipError> {
+        let state = self.state.read().await;
+        let credentials = state
+            .endpoint_credentials
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+
+        let avatar = match image {
+            Some(image) => Some(self.shared.avatars.put(&image).await?),
+            None => None,
+        };
+        info!(has_avatar = avatar.is_some(), "Updating avatar");
+        self.shared.set_profile(&Profile {
+            nickname: credentials.self_ticket.nickname.clone(),
+            avatar,
+        })?;
+        self.shared.announce_profile()
+    }
+
+    /// The avatar image of a contact, or ours if `node_id` is `None`. A contact's avatar is
+    /// fetched from them if it isn't cached yet.
+    pub async fn avatar(&self, node_id: Option<NodeId>) -> Result<Option<Vec<u8>>, FreeVoipError> {
+        let avatars = &self.shared.avatars;
+        let Some(node_id) = node_id else {
+            let own_avatar = self.shared.own_avatar_tx.borrow().clone();
+            return match own_avatar {
+                Some(hash) => avatars.get(&hash).await,
+                None => Ok(None),
+            };
+        };
+
+        let Some(hash) = self.shared.contact_avatars()?.remove(&node_id) else {
+            return Ok(None);
+        };
+        if let Some(image) = avatars.get(&hash).await? {
+            return Ok(Some(image));
+        }
+
+        let state = self.state.read().await;
+        let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+        let addr = relay_only(self.shared.contact_addr(node_id)?, state.hide_ip);
+        ProfileProtocol::fetch_avatar(router.endpoint(), addr, &hash, avatars)
+            .await
+            .map(Some)
+    }
+
+    pub fn presence(&self) -> Presence {
+        self.shared.own_presence_tx.borrow().clone()
+    }
+
+    /// Sets our presence as contacts see it. [`PresenceState::Offline`] hides us from everyone.
+    pub fn set_presence(&self, mut presence: Presence) -> Result<(), FreeVoipError> {
+        presence.status_text = presence
+            .status_text
+            .map(|t| t.trim().to_owned())
+            .filter(|t| !t.is_empty());
+        if presence
+            .status_text
+            .as_ref()
+            .is_some_and(|t| t.chars().count() > presence::MAX_STATUS_TEXT_LEN)
+        {
+            return Err(FreeVoipError::InvalidState(format!(
+                "The status can be at most {} characters long",
+                presence::MAX_STATUS_TEXT_LEN
+            )));
+        }
+
+        info!(state = ?presence.state, "Setting presence");
+        set_json(
+            self.shared.storage.as_ref(),
+            SETTINGS_STORE,
+            "presence",
+            &presence,
+        )?;
+        self.shared.own_presence_tx.send_replace(presence);
+        Ok(())
+    }
+
+    pub fn presence_settings(&self) -> Result<PresenceSettings, FreeVoipError> {
+        self.shared.presence_settings()
+    }
+
+    pub fn set_presence_settings(&self, settings: PresenceSettings) -> Result<(), FreeVoipError> {
+        set_json(
+            self.shared.storage.as_ref(),
+            SETTINGS_STORE,
+            "presenceSettings",
+            &settings,
+        )?;
+        self.shared.refresh_presence_audience()
+    }
+
+    /// Last known presence of each contact that has been probed so far.
+    pub fn contact_presences(&self) -> HashMap<NodeId, Presence> {
+        self.shared.presences.lock().unwrap().clone()
+    }
+
+    pub fn network_settings(&self) -> Result<NetworkSettings, FreeVoipError> {
+        self.shared.network_settings()
+    }
+
+    /// Changes how the endpoint finds peers. An identity that is online gets a new endpoint with
+    /// the settings right away, so this is refused during a call: the new endpoint comes with new
+    /// media channels, leaving the ones the GUI subscribed to silent. The settings are only stored
+    /// once that endpoint is bound.
+    pub async fn set_network_settings(
+        &self,
+        settings: NetworkSettings,
+    ) -> Result<(), FreeVoipError> {
+        if settings.relay_mode == RelayMode::Custom && settings.relay_urls.is_empty() {
+            return Err(FreeVoipError::invalid_state(
+                "Custom relays need at least one relay URL",
+            ));
+        }
+        if settings.hide_ip && settings.relay_mode == RelayMode::Disabled {
+            return Err(FreeVoipError::invalid_state(
+                "Hiding your IP address needs relays",
+            ));
+        }
+
+        info!(?settings, "Setting network settings");
+        let mut state = self.state.write().await;
+        if let Some(call_proto) = &state.call_protocol {
+            if call_proto.connection().await.is_some() {
+                return Err(FreeVoipError::invalid_state(
+                    "Network settings can't be changed during a call",
+                ));
+            }
+        }
+
+        // Bind the new endpoint before taking down the current one, so that the identity stays
+        // online with the previous settings if it fails
+        let replacement = match (&state.router, &state.endpoint_credentials) {
+            (Some(_), Some(credentials)) => {
+                Some(build_endpoint(Some(credentials.secret_key.clone()), &settings).await?)
+            }
+            _ => None,
+        };
+        if let Err(e) = set_json(
+            self.shared.storage.as_ref(),
+            SETTINGS_STORE,
+            "networkSettings",
+            &settings,
+        ) {
+            if let Some((endpoint, _)) = replacement {
+                endpoint.close().await;
+            }
+            return Err(e);
+        }
+
+        if let Some((endpoint, mdns)) = replacement {
+            if let Err(e) = self.shut_down(&mut state).await {
+                warn!("Failed to shut down previous endpoint: {e}");
+            }
+            *state = NodeState {
+                endpoint_credentials: state.endpoint_credentials.take(),
+                hide_ip: settings.hide_ip,
+                ..Default::default()
+            };
+            let router = self.build_router(&mut state, endpoint, mdns);
+            state.router = Some(router);
+        }
+
+        Ok(())
+    }
+
+    /// Devices running Free VoIP found on the local network so far. Always empty with local
+    /// discovery turned off.
+    pub fn nearby_devices(&self) -> Result<Vec<NearbyDevice>, FreeVoipError> {
+        self.shared.nearby_devices()
+    }
+
+    /// The recovery phrase of the identity's secret key.
+    pub async fn export_mnemonic(&self) -> Result<String, FreeVoipError> {
+        let state = self.state.read().await;
+        let credentials = state
+            .endpoint_credentials
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        Ok(backup::to_mnemonic(&credentials.secret_key))
+    }
+
+    /// Saves the identity and contacts, encrypted with `passphrase`, to the download directory,
+    /// returning the path of the backup file.
+    pub async fn export_identity_file(&self, passphrase: &str) -> Result<PathBuf, FreeVoipError> {
+        let contents = {
+            let state = self.state.read().await;
+            let credentials = state
+                .endpoint_credentials
+                .as_ref()
+                .ok_or(FreeVoipError::NotLoggedIn)?;
+            Backup {
+                self_ticket: credentials.self_ticket.clone(),
+                secret_key: credentials.secret_key.clone(),
+                contacts: self.shared.contacts()?,
+            }
+            .encrypt(passphrase)?
+        };
+
+        let download_dir = &self.shared.config.download_dir;
+        tokio::fs::create_dir_all(download_dir).await?;
+        let path = download_dir.join(format!("free-voip-identity-{}.json", unix_timestamp()));
+        tokio::fs::write(&path, contents).await?;
+        info!("Exported identity");
+        Ok(path)
+    }
+
+    /// Restores an identity from a backup, encrypting it with `passphrase` on this device. Contacts
+    /// from a backup file are added to the ones already here.
+    pub async fn import_identity(
+        &self,
+        source: BackupSource,
+        passphrase: &str,
+    ) -> Result<(), FreeVoipError> {
+        match source {
+            BackupSource::Mnemonic { phrase, nickname } => {
+                let secret_key = backup::from_mnemonic(&phrase)?;
+                self.login(nickname, passphrase, Some(secret_key)).await
+            }
+            BackupSource::File {
+                contents,
+                passphrase: file_passphrase,
+            } => {
+                let backup = Backup::decrypt(&contents, &file_passphrase)?;
+                self.login(
+                    backup.self_ticket.nickname,
+                    passphrase,
+                    Some(backup.secret_key),
+                )
+                .await?;
+
+                let mut contacts = self.shared.contacts()?;
+                for contact in backup.contacts {
+                    if !contacts.iter().any(|c| c.node_id == contact.node_id) {
+                        contacts.push(contact);
+                    }
+                }
+                self.shared.set_contacts(contacts)
+            }
+        }
+    }
+
+    /// Ends any call along with its recording or echo test, discards a voicemail being recorded,
+    /// stops delivering queued items and shuts down the router.
+    async fn shut_down(&self, state: &mut NodeState) -> Result<(), FreeVoipError> {
+        if let Some(ref call_protocol) = state.call_protocol {
+            call_protocol.disconnect().await;
+        }
+        if let Some(recorder) = state.call_recorder.take() {
+            match recorder.stop().await {
+                Ok(path) => self.shared.emit(Event::CallRecordingSaved(path)),
+                Err(err) => error!("Failed to finish call recording: {err}"),
+            }
+        }
+        if let Some(echo_test) = state.echo_test.take() {
+            match echo_test.stop().await {
+                Ok(stats) => self.shared.emit(Event::EchoStats(stats)),
+                Err(err) => warn!("Failed to finish echo test: {err}"),
+            }
+        }
+        if let Some(recorder) = state.voicemail_recorder.take() {
+            info!("Discarding voicemail being recorded");
+            _ = recorder.stop().await;
+        }
+        self.shared.outbox.stop();
+        if let Some(router) = state.router.take() {
+            router.shutdown().await?;
+        }
+        self.shared.nearby.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Takes the identity offline: ends any call, shuts down the router and forgets the session.
+    ///
+    /// With `wipe`, the identity, contacts and queued items are also removed from storage.
+    /// Otherwise the stored identity can be brought back with [`Node::restore_login`].
+    pub async fn logout(&self, wipe: bool) -> Result<(), FreeVoipError> {
+        info!(wipe, "Logging out");
+        let mut state = self.state.write().await;
+
+        self.shut_down(&mut state).await?;
+        *state = NodeState::default();
+        self.shared.presences.lock().unwrap().clear();
+        self.shared.unload_outbox(wipe)?;
+
+        if wipe {
+            let storage = self.shared.storage.as_ref();
+            storage.clear(CREDENTIALS_STORE)?;
+            storage.clear(CONTACTS_STORE)?;
+            self.shared.invites_tx.send_replace(vec![]);
+            self.shared.own_avatar_tx.send_replace(None);
+            self.shared.publish_contacts(&[]);
+            self.shared.refresh_presence_audience()?;
+            self.shared.emit(Event::ContactsUpdated(vec![]));
+        }
+
+        Ok(())
+    }
+
+    /// Logs out and removes everything belonging to the identity: its secret key, contacts, call
+    /// history, voicemails, avatars, presence settings and queued items. The node ID can never be
+    /// recovered afterwards, so contacts will have to add the new one.
+    pub async fn delete_identity(&self) -> Result<(), FreeVoipError> {
+        self.logout(true).await?;
+        warn!("Deleting identity");
+
+        self.clear_call_history()?;
+        self.shared.storage.clear(VOICEMAIL_STORE)?;
+        for key in ["presence", "presenceSettings"] {
+            self.shared.storage.delete(SETTINGS_STORE, key)?;
+        }
+        self.shared
+            .own_presence_tx
+            .send_replace(Presence::default());
+
+        for dir in [
+            self.shared.voicemail_dir(),
+            self.shared.avatars.dir().to_owned(),
+        ] {
+            match tokio::fs::remove_dir_all(dir).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn self_ticket(&self) -> Result<ContactTicket, FreeVoipError> {
+        self.state.read().await.self_ticket()
+    }
+
+    /// Our contact ticket, serialized for sharing.
+    pub async fn serialized_self_ticket(&self) -> Result<String, FreeVoipError> {
+        Ok(Ticket::serialize(&self.self_ticket().await?))
+    }
+
+    /// Our contact ticket as a QR code image.
+    pub async fn self_ticket_qr(&self, format: QrFormat) -> Result<Vec<u8>, FreeVoipError> {
+        qr::encode(&self.serialized_self_ticket().await?, format)
+    }
+
+    /// Reads the contact or invite ticket in a QR code image, returning it serialized for
+    /// [`Node::send_contact_request`].
+    pub fn decode_ticket_qr(&self, image: &[u8]) -> Result<String, FreeVoipError> {
+        let serialized_ticket = qr::decode(image)?.trim().to_owned();
+        parse_ticket(&serialized_ticket)?;
+        Ok(serialized_ticket)
+    }
+
+    pub fn contacts(&self) -> Result<Vec<ContactTicket>, FreeVoipError> {
+        self.shared.contacts()
+    }
+
+    pub fn add_contact(&self, contact_ticket: ContactTicket) -> Result<(), FreeVoipError> {
+        self.shared.add_contact(contact_ticket)
+    }
+
+    /// Issues an invite that lets up to `max_uses` people (one if not given) add us within
+    /// `valid_for`, returning it serialized for sharing.
+    pub async fn create_invite(
+        &self,
+        valid_for: Duration,
+        max_uses: Option<u32>,
+    ) -> Result<String, FreeVoipError> {
+        if max_uses == Some(0) {
+            return Err(FreeVoipError::invalid_state(
+                "An invite must be usable at least once",
+            ));
+        }
+
+        let state = self.state.read().await;
+        let secret_key = state
+            .endpoint_credentials
+            .as_ref()
+            .map(|c| &c.secret_key)
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        let expires_at = unix_timestamp() + valid_for.as_secs();
+        let ticket = InviteTicket::issue(secret_key, state.self_ticket()?, expires_at, max_uses);
+
+        // Forget the invites that can't be used anymore while at it
+        self.shared.invites_tx.send_modify(|invites| {
+            invites.retain(Invite::is_valid);
+            invites.push(Invite::new(&ticket));
+        });
+        self.shared.save_invites()?;
+        info!(id = ticket.id(), expires_at, ?max_uses, "Issued invite");
+
+        Ok(Ticket::serialize(&ticket))
+    }
+
+    /// The invites we issued that can still be used.
+    pub fn invites(&self) -> Vec<Invite> {
+        let mut invites = self.shared.invites_tx.borrow().clone();
+        invites.retain(Invite::is_valid);
+        invites
+    }
+
+    /// Revokes an invite, returning whether there was one with the given ID.
+    pub fn revoke_invite(&self, id: &str) -> Result<bool, FreeVoipError> {
+        let revoked = self.shared.invites_tx.send_if_modified(|invites| {
+            let count = invites.len();
+            invites.retain(|i| i.id != id);
+            invites.len() != count
+        });
+        if revoked {
+            self.shared.save_invites()?;
+        }
+        Ok(revoked)
+    }
+
+    /// Sends a contact request to the owner of a contact or invite ticket, returning the
+    /// recipient's response or `None` if the recipient could not be reached and the request was
+    /// queued in the outbox.
+    pub async fn send_contact_request(
+        &self,
+        serialized_ticket: &str,
+    ) -> Result<(ContactTicket, Option<bool>), FreeVoipError> {
+        let (mut contact_ticket, mut invite) = parse_ticket(serialized_ticket)?;
+        let state = self.state.read().await;
+        let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+        let self_ticket = state.self_ticket()?;
+
+        // Discovery kicks in if the addresses in the ticket are stale
+        let addr = relay_only(contact_ticket.node_addr(), state.hide_ip);
+        if let Some(ref mut invite) = invite {
+            // Only the issuer's node ID is signed
+            invite.issuer.addr = invite
+                .issuer
+                .addr
+                .take()
+                .map(|a| relay_only(a, state.hide_ip));
+        }
+        let result = match invite {
+            Some(ref invite) => {
+                ContactsProtocol::send_invited_request(router.endpoint(), &self_ticket, invite)
+                    .await
+            }
+            None => {
+                ContactsProtocol::send_request(router.endpoint(), addr.clone(), &self_ticket).await
+            }
+        };
+        match result {
+            Ok(accepted) => {
+                contact_ticket.addr = Some(connected_addr(router.endpoint(), addr));
+                Ok((contact_ticket, Some(accepted)))
+            }
+            Err(e) => {
+                info!("Failed to send contact request, queueing it: {e}");
+                self.shared.outbox.enqueue(
+                    contact_ticket.node_id,
+                    OutboxPayload::ContactRequest {
+                        contact: contact_ticket.clone(),
+                        invite: invite.map(Box::new),
+                    },
+                );
+                Ok((contact_ticket, None))
+            }
+        }
+    }
+
+    pub async fn respond_to_contact_request(&self, accept: bool) -> Result<(), FreeVoipError> {
+        let state = self.state.read().await;
+
+        if let Some(ref response_tx) = state.contact_response_tx {
+            // Send the response to the contact request
+            response_tx.send(accept)?;
+            Ok(())
+        } else {
+            Err(FreeVoipError::NotLoggedIn)
+        }
+    }
+
+    /// Rings a contact, returning whether they answered.
+    pub async fn ring(&self, node_addr: NodeId) -> Result<bool, FreeVoipError> {
+        // Don't hold the state lock while ringing, hanging up to cancel the ring needs it
+        let (endpoint, call_protocol, self_ticket, hide_ip) = {
+            let state = self.state.read().await;
+            let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+            let call_protocol = state
+                .call_protocol
+                .clone()
+                .ok_or(FreeVoipError::NotLoggedIn)?;
+            (
+                router.endpoint().clone(),
+                call_protocol,
+                state.self_ticket()?,
+                state.hide_ip,
+            )
+        };
+
+        let addr = relay_only(self.shared.contact_addr(node_addr)?, hide_ip);
+        let result = call_protocol
+            .ring(&endpoint, addr.clone(), &self_ticket)
+            .await;
+
+        if result.is_ok() {
+            let addr = connected_addr(&endpoint, addr);
+            if let Err(e) = self.shared.remember_addr(addr) {
+                warn!("Failed to store contact addresses: {e}");
+            }
+        } else {
+            // Let the contact know they missed our call once they are back online
+            self.shared.outbox.enqueue(
+                node_addr,
+                OutboxPayload::MissedCall {
+                    timestamp: unix_timestamp(),
+                },
+            );
+        }
+        result
+    }
+
+    pub async fn respond_to_ring(&self, accept: bool) -> Result<(), FreeVoipError> {
+        let state = self.state.read().await;
+
+        if let Some(ref response_tx) = state.ring_response_tx {
+            response_tx.send(accept)?;
+            Ok(())
+        } else {
+            Err(FreeVoipError::NotLoggedIn)
+        }
+    }
+
+    pub async fn send_call_media(&self, media: CallMedia) -> Result<(), FreeVoipError> {
+        let state = self.state.read().await;
+
+        if let Some(ref media_tx) = state.media_tx {
+            media_tx.send(media)?;
+            Ok(())
+        } else {
+            Err(FreeVoipError::NotLoggedIn)
+        }
+    }
+
+    /// Sets the configuration of the encoder whose audio is passed to
+    /// [`Node::send_call_media`], which voicemails and recordings need to be played back. The peer
+    /// of an ongoing call is told as well, for its recordings.
+    pub async fn set_audio_config(&self, config: AudioConfig) -> Result<(), FreeVoipError> {
+        self.shared.audio_config_tx.send_replace(config);
+
+        let state = self.state.read().await;
+        if let Some(ref call_proto) = state.call_protocol {
+            // Voicemails are recorded outside of calls, with nobody to tell
+            if let Err(e) = call_proto
+                .send_control(&CallControl::AudioConfig(config))
+                .await
+            {
+                debug!("Not sending audio config to peer: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes to media received from the peer of the ongoing call.
+    pub async fn subscribe_call_media(&self) -> Result<Receiver<CallMedia>, FreeVoipError> {
+        let state = self.state.read().await;
+        let media_rx = state.media_rx.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+        Ok(media_rx.resubscribe())
+    }
+
+    /// Hangs up the ongoing call or cancels a ring, returning whether a call was disconnected.
+    pub async fn hang_up(&self) -> Result<bool, FreeVoipError> {
+        let state = self.state.read().await;
+        let call_proto = state
+            .call_protocol
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        let disconnected = call_proto.disconnect().await;
+        Ok(disconnected)
+    }
+
+    pub fn call_history(&self) -> Result<Vec<CallRecord>, FreeVoipError> {
+        self.shared.call_history()
+    }
+
+    pub fn clear_call_history(&self) -> Result<(), FreeVoipError> {
+        self.shared.storage.delete(CALL_HISTORY_STORE, "calls")?;
+        self.shared.emit(Event::CallHistoryUpdated(vec![]));
+        Ok(())
+    }
+
+    /// Sends a file to a contact, returning whether they accepted it.
+    pub async fn send_file(&self, node_id: NodeId, path: PathBuf) -> Result<bool, FreeVoipError> {
+        // Don't hold the state lock for the duration of the transfer
+        let (endpoint, files_protocol, addr) = {
+            let state = self.state.read().await;
+            let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+            let files_protocol = state
+                .files_protocol
+                .clone()
+                .ok_or(FreeVoipError::NotLoggedIn)?;
+            let addr = relay_only(self.shared.contact_addr(node_id)?, state.hide_ip);
+            (router.endpoint().clone(), files_protocol, addr)
+        };
+
+        files_protocol.send_file(&endpoint, addr, &path).await
+    }
+
+    /// How the ongoing call reaches the peer.
+    pub async fn call_stats(&self) -> Result<CallStats, FreeVoipError> {
+        let state = self.state.read().await;
+        let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+        let call_proto = state
+            .call_protocol
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        call_proto.stats(router.endpoint(), state.hide_ip).await
+    }
+
+    /// Sends a file to the peer of the ongoing call, on the call's connection.
+    pub async fn send_file_in_call(&self, path: PathBuf) -> Result<bool, FreeVoipError> {
+        let (connection, files_protocol) = {
+            let state = self.state.read().await;
+            let call_proto = state
+                .call_protocol
+                .as_ref()
+                .ok_or(FreeVoipError::NotLoggedIn)?;
+            let connection = call_proto
+                .connection()
+                .await
+                .ok_or_else(|| FreeVoipError::invalid_state("Not in a call"))?;
+            let files_protocol = state
+                .files_protocol
+                .clone()
+                .ok_or(FreeVoipError::NotLoggedIn)?;
+            (connection, files_protocol)
+        };
+
+        files_protocol.send_file_on(&connection, &path).await
+    }
+
+    pub async fn respond_to_file_offer(&self, accept: bool) -> Result<(), FreeVoipError> {
+        let state = self.state.read().await;
+
+        if let Some(ref response_tx) = state.file_response_tx {
+            response_tx.send(accept)?;
+            Ok(())
+        } else {
+            Err(FreeVoipError::NotLoggedIn)
+        }
+    }
+
+    /// Starts recording the audio sent with [`Node::send_call_media`] as a voicemail.
+    pub async fn start_voicemail(&self, node_id: NodeId) -> Result<(), FreeVoipError> {
+        let mut state = self.state.write().await;
+        if state.voicemail_recorder.is_some() {
+            return Err(FreeVoipError::invalid_state(
+                "Already recording a voicemail",
+            ));
+        }
+
+        let media_rx = state
+            .media_tx
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?
+            .subscribe();
+        state.voicemail_recorder = Some(VoicemailRecorder::start(node_id, media_rx));
+
+        Ok(())
+    }
+
+    /// Stops recording a voicemail and sends it unless discarded.
+    ///
+    /// Returns whether it was delivered right away, or `None` if it was discarded. Voicemails for
+    /// contacts that are offline are queued in the outbox.
+    pub async fn stop_voicemail(&self, send: bool) -> Result<Option<bool>, FreeVoipError> {
+        // Don't hold the state lock while sending, connecting can take until it times out
+        let (recorder, endpoint, self_ticket, hide_ip) = {
+            let mut state = self.state.write().await;
+            let recorder = state
+                .voicemail_recorder
+                .take()
+                .ok_or_else(|| FreeVoipError::invalid_state("Not recording a voicemail"))?;
+            let router = state.router.as_ref().ok_or(FreeVoipError::NotLoggedIn)?;
+            let endpoint = router.endpoint().clone();
+            (recorder, endpoint, state.self_ticket()?, state.hide_ip)
+        };
+        let recipient = recorder.recipient;
+        let frames = recorder.stop().await?;
+
+        if !send || frames.is_empty() {
+            return Ok(None);
+        }
+
+        // Keep the recording on disk until it is delivered
+        let outgoing_dir = self.shared.voicemail_dir().join("outgoing");
+        let path = outgoing_dir.join(format!("{}-{}.mka", recipient, unix_timestamp()));
+        let bytes = voicemail::encode_container(&frames, *self.shared.audio_config_tx.borrow());
+        tokio::fs::create_dir_all(&outgoing_dir).await?;
+        tokio::fs::write(&path, bytes).await?;
+
+        let addr = relay_only(self.shared.contact_addr(recipient)?, hide_ip);
+        match VoicemailProtocol::send(&endpoint, addr, &self_ticket, &path).await {
+            Ok(()) => {
+                _ = tokio::fs::remove_file(&path).await;
+                Ok(Some(true))
+            }
+            Err(e) => {
+                info!("Failed to send voicemail, queueing it: {e}");
+                self.shared
+                    .outbox
+                    .enqueue(recipient, OutboxPayload::Voicemail { path });
+                Ok(Some(false))
+            }
+        }
+    }
+
+    pub fn voicemails(&self) -> Result<Vec<VoicemailInfo>, FreeVoipError> {
+        self.shared.voicemails()
+    }
+
+    /// Plays a voicemail by handing its audio frames to `on_frame`, paced in real time.
+    pub async fn play_voicemail(
+        &self,
+        id: &str,
+        on_frame: impl FnMut(CallMedia),
+    ) -> Result<(), FreeVoipError> {
+        let voicemail = self
+            .voicemails()?
+            .into_iter()
+            .find(|v| v.id == id)
+            .ok_or_else(|| FreeVoipError::invalid_state("Voicemail not found"))?;
+
+        let path = voicemail::inbox_path(
+            &self.shared.voicemail_dir().join("inbox"),
+            voicemail.sender.node_id,
+            id,
+        );
+        voicemail::play(&path, on_frame).await
+    }
+
+    pub async fn delete_voicemail(&self, id: &str) -> Result<(), FreeVoipError> {
+        let mut voicemails = self.voicemails()?;
+        let Some(index) = voicemails.iter().position(|v| v.id == id) else {
+            return Ok(());
+        };
+        let voicemail = voicemails.remove(index);
+        set_json(
+            self.shared.storage.as_ref(),
+            VOICEMAIL_STORE,
+            "voicemails",
+            &voicemails,
+        )?;
+
+        let path = voicemail::inbox_path(
+            &self.shared.voicemail_dir().join("inbox"),
+            voicemail.sender.node_id,
+            id,
+        );
+        _ = tokio::fs::remove_file(path).await;
+
+        Ok(())
+    }
+
+    /// Starts recording the ongoing call and lets the peer know, returning the recording's path.
+    pub async fn start_recording(&self) -> Result<PathBuf, FreeVoipError> {
+        let mut state = self.state.write().await;
+        if state.call_recorder.is_some() {
+            return Err(FreeVoipError::invalid_state("Already recording the call"));
+        }
+
+        let call_proto = state
+            .call_protocol
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?;
+        call_proto
+            .send_control(&CallControl::Recording { active: true })
+            .await?;
+
+        let local_media_rx = state
+            .media_tx
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?
+            .subscribe();
+        let remote_media_rx = state
+            .media_rx
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?
+            .resubscribe();
+
+        let recordings_dir = &self.shared.config.recordings_dir;
+        tokio::fs::create_dir_all(recordings_dir).await?;
+        let path = recordings_dir.join(format!("free-voip-call-{}.mkv", unix_timestamp()));
+
+        let recorder = CallRecorder::start(
+            path.clone(),
+            local_media_rx,
+            remote_media_rx,
+            *self.shared.audio_config_tx.borrow(),
+            *self.shared.remote_audio_config_tx.borrow(),
+        )
+        .await?;
+        state.call_recorder = Some(recorder);
+
+        Ok(path)
+    }
+
+    /// Stops recording the ongoing call, returning the recording's path.
+    pub async fn stop_recording(&self) -> Result<PathBuf, FreeVoipError> {
+        let mut state = self.state.write().await;
+        let recorder = state
+            .call_recorder
+            .take()
+            .ok_or_else(|| FreeVoipError::invalid_state("Not recording the call"))?;
+
+        if let Some(ref call_proto) = state.call_protocol {
+            if let Err(e) = call_proto
+                .send_control(&CallControl::Recording { active: false })
+                .await
+            {
+                warn!("Failed to notify peer that recording stopped: {e}");
+            }
+        }
+
+        recorder.stop().await
+    }
+
+    /// Starts measuring round-trip latency and loss of the ongoing call, which should be with an echo
+    /// bot. Statistics are reported every second as [`Event::EchoStats`] until the call ends.
+    pub async fn start_echo_test(&self) -> Result<(), FreeVoipError> {
+        let mut state = self.state.write().await;
+        if state.echo_test.is_some() {
+            return Err(FreeVoipError::invalid_state("Echo test already running"));
+        }
+
+        let outgoing_rx = state
+            .media_tx
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?
+            .subscribe();
+        let incoming_rx = state
+            .media_rx
+            .as_ref()
+            .ok_or(FreeVoipError::NotLoggedIn)?
+            .resubscribe();
+
+        let (stats_tx, mut stats_rx) = channel::<EchoStats>(8);
+        let shared = self.shared.clone();
+        tokio::spawn(async move {
+            while let Ok(stats) = stats_rx.recv().await {
+                shared.emit(Event::EchoStats(stats));
+            }
+        });
+
+        state.echo_test = Some(EchoTest::start(outgoing_rx, incoming_rx, stats_tx));
+        Ok(())
+    }
+
+    /// Stops the echo test, returning its final statistics.
+    pub async fn stop_echo_test(&self) -> Result<EchoStats, FreeVoipError> {
+        let mut state = self.state.write().await;
+        let echo_test = state
+            .echo_test
+            .take()
+            .ok_or_else(|| FreeVoipError::invalid_state("No echo test running"))?;
+        echo_test.stop().await
+    }
+
+    /// Queued outgoing items, optionally only those for one contact.
+    pub fn outbox(&self, node_id: Option<NodeId>) -> Vec<OutboxItem> {
+        self.shared
+            .outbox
+            .items()
+            .into_iter()
+            .filter(|i| node_id.is_none_or(|node_id| i.recipient == node_id))
+            .collect()
+    }
+
+    pub fn remove_outbox_item(&self, id: u64) -> Result<bool, FreeVoipError> {
+        let removed = self.shared.outbox.remove(id);
+        self.shared.save_outbox()?;
+        Ok(removed)
+    }
+
+    /// Saves the logs with identifying data stripped to the download directory, returning the
+    /// path of the bundle.
+    pub async fn export_log_bundle(&self) -> Result<PathBuf, FreeVoipError> {
+        let config = &self.shared.config;
+        logging::export_bundle(&config.log_dir(), &config.download_dir).await
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    contacts::{ContactTicket, ContactsMessage, ContactsProtocol},
+    invite::InviteTicket,
+    node::relay_only,
+    unix_timestamp,
+    voicemail::VoicemailProtocol,
+};
+use iroh::{Endpoint, NodeAddr, NodeId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, watch},
+    task::AbortHandle,
+};
+use tracing::{info, warn};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// How many delivered items to keep around so that the user can see what went out.
+const MAX_DELIVERED: usize = 50;
+
+/// What an outbox item delivers once the recipient is reachable.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum OutboxPayload {
+    /// A contact request to `contact`, sent with our self ticket at delivery time and the
+    /// invite `contact` gave us, if any.
+    ContactRequest {
+        contact: ContactTicket,
+        #[serde(default)]
+        invite: Option<Box<InviteTicket>>,
+    },
+    /// Tells the recipient we tried to call them at `timestamp` (seconds since the Unix epoch).
+    MissedCall { timestamp: u64 },
+    /// A recorded voicemail stored at `path`.
+    Voicemail { path: PathBuf },
+    /// Tells the recipient about our profile, sent with our self ticket and avatar at delivery
+    /// time.
+    ProfileUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryState {
+    /// Waiting for the recipient to come online.
+    Pending,
+    /// Written to the recipient, waiting for their response.
+    Sent,
+    /// The recipient has responded.
+    Delivered,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxItem {
+    pub id: u64,
+    pub recipient: NodeId,
+    pub payload: OutboxPayload,
+    pub state: DeliveryState,
+    pub created_at: u64,
+    pub attempts: u32,
+    /// The recipient's answer to a contact request, once delivered.
+    pub accepted: Option<bool>,
+}
+
+#[derive(Debug, Default)]
+struct OutboxInner {
+    /// The identity the items are sent from.
+    owner: Option<NodeId>,
+    items: Vec<OutboxItem>,
+    next_id: u64,
+    workers: HashMap<NodeId, AbortHandle>,
+    delivery: Option<Delivery>,
+}
+
+/// What the workers need to deliver items.
+#[derive(Debug, Clone)]
+struct Delivery {
+    endpoint: Endpoint,
+    self_ticket: ContactTicket,
+    /// Whether to reach recipients only through their relay.
+    hide_ip: bool,
+}
+
+/// Queue of outgoing items for contacts that could not be reached.
+///
+/// Holds the items of one identity at a time, loaded when it comes online.
+///
+/// Every change to an item is published on the update channel so that it can be persisted and
+/// shown to the user.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    inner: Arc<Mutex<OutboxInner>>,
+    update_tx: broadcast::Sender<OutboxItem>,
+    /// Hash of our current avatar, for profile updates.
+    own_avatar: watch::Receiver<Option<String>>,
+    /// Stored addresses of our contacts, to dial them without discovery.
+    contact_addrs: watch::Receiver<HashMap<NodeId, NodeAddr>>,
+}
+
+impl Outbox {
+    pub fn new(
+        update_tx: broadcast::Sender<OutboxItem>,
+        own_avatar: watch::Receiver<Option<String>>,
+        contact_addrs: watch::Receiver<HashMap<NodeId, NodeAddr>>,
+    ) -> Self {
+        Self {
+            inner: Arc::default(),
+            update_tx,
+            own_avatar,
+            contact_addrs,
+        }
+    }
+
+    /// Replaces the queued items with `owner`'s. Delivery stops until started again.
+    pub fn load(&self, owner: NodeId, items: Vec<OutboxItem>) {
+        let next_id = items.iter().map(|i| i.id + 1).max().unwrap_or_default();
+        let mut items = items
+            .into_iter()
+            .map(|mut item| {
+                // Anything interrupted mid-delivery is sent again
+                if item.state == DeliveryState::Sent {
+                    item.state = DeliveryState::Pending;
+                }
+                item
+            })
+            .collect();
+        prune_delivered(&mut items);
+
+        let mut inner = self.inner.lock().unwrap();
+        for (_, worker) in inner.workers.drain() {
+            worker.abort();
+        }
+        *inner = OutboxInner {
+            owner: Some(owner),
+            items,
+            next_id,
+            ..Default::default()
+        };
+    }
+
+    /// Stops delivering and forgets the queued items, returning whose they were.
+    pub fn unload(&self) -> Option<NodeId> {
+        let mut inner = self.inner.lock().unwrap();
+        for (_, worker) in inner.workers.drain() {
+            worker.abort();
+        }
+        std::mem::take(&mut *inner).owner
+    }
+
+    /// The identity whose items are queued, if any.
+    pub fn owner(&self) -> Option<NodeId> {
+        self.inner.lock().unwrap().owner
+    }
+
+    pub fn items(&self) -> Vec<OutboxItem> {
+        self.inner.lock().unwrap().items.clone()
+    }
+
+    /// The queued items together with their owner, if any are loaded.
+    pub fn owned_items(&self) -> Option<(NodeId, Vec<OutboxItem>)> {
+        let inner = self.inner.lock().unwrap();
+        inner.owner.map(|owner| (owner, inner.items.clone()))
+    }
+
+    /// Starts delivering pending items through `endpoint`, replacing any previous endpoint.
+    /// With `hide_ip` recipients are only dialed through their relay.
+    pub fn start(&self, endpoint: Endpoint, self_ticket: ContactTicket, hide_ip: bool) {
+        let recipients = {
+            let mut inner = self.inner.lock().unwrap();
+            for (_, worker) in inner.workers.drain() {
+                worker.abort();
+            }
+            inner.delivery = Some(Delivery {
+                endpoint,
+                self_ticket,
+                hide_ip,
+            });
+
+            inner
+                .items
+                .iter()
+                .filter(|i| i.state != DeliveryState::Delivered)
+                .map(|i| i.recipient)
+                .collect::<Vec<_>>()
+        };
+
+        for recipient in recipients {
+            self.ensure_worker(recipient);
+        }
+    }
+
+    /// Stops delivering until started again. Items stay queued.
+    pub fn stop(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        for (_, worker) in inner.workers.drain() {
+            worker.abort();
+        }
+        inner.delivery = None;
+    }
+
+    pub fn enqueue(&self, recipient: NodeId, payload: OutboxPayload) -> OutboxItem {
+        let item = {
+            let mut inner = self.inner.lock().unwrap();
+            let item = OutboxItem {
+                id: inner.next_id,
+                recipient,
+                payload,
+                state: DeliveryState::Pending,
+                created_at: unix_timestamp(),
+                attempts: 0,
+                accepted: None,
+            };
+            inner.next_id += 1;
+            inner.items.push(item.clone());
+            item
+        };
+
+        _ = self.update_tx.send(item.clone());
+        self.ensure_worker(recipient);
+        item
+    }
+
+    /// Removes an item from the outbox, returning whether it existed.
+    pub fn remove(&self, id: u64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.items.len();
+        inner.items.retain(|i| i.id != id);
+        len != inner.items.len()
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut OutboxItem)) {
+        let item = {
+            let mut inner = self.inner.lock().unwrap();
+            let Some(item) = inner.items.iter_mut().find(|i| i.id == id) else {
+                return;
+            };
+            f(item);
+            item.clone()
+        };
+        _ = self.update_tx.send(item);
+    }
+
+    fn next_pending(&self, recipient: NodeId) -> Option<OutboxItem> {
+        let mut inner = self.inner.lock().unwrap();
+        let item = inner
+            .items
+            .iter()
+            .find(|i| i.recipient == recipient && i.state != DeliveryState::Delivered)
+            .cloned();
+
+        // Unregister while still holding the lock so that a concurrent enqueue spawns a new worker
+        if item.is_none() {
+            inner.workers.remove(&recipient);
+        }
+        item
+    }
+
+    fn ensure_worker(&self, recipient: NodeId) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(delivery) = inner.delivery.clone() else {
+            // Not logged in yet, `start` picks this up later
+            return;
+        };
+        if inner.workers.contains_key(&recipient) {
+            return;
+        }
+
+        let outbox = self.clone();
+        let worker = tokio::spawn(async move {
+            outbox.run_worker(delivery, recipient).await;
+        });
+        inner.workers.insert(recipient, worker.abort_handle());
+    }
+
+    /// Takes an item out of the outbox for good, letting the update channel know.
+    fn discard(&self, item: OutboxItem) {
+        if self.remove(item.id) {
+            _ = self.update_tx.send(item);
+        }
+    }
+
+    /// Where to dial `recipient` for `item`: the address in a contact request, else the stored
+    /// address of the contact.
+    fn recipient_addr(&self, item: &OutboxItem, hide_ip: bool) -> NodeAddr {
+        let addr = match item.payload {
+            OutboxPayload::ContactRequest { ref contact, .. } => contact.node_addr(),
+            _ => self
+                .contact_addrs
+                .borrow()
+                .get(&item.recipient)
+                .cloned()
+                .unwrap_or_else(|| NodeAddr::new(item.recipient)),
+        };
+        relay_only(addr, hide_ip)
+    }
+
+    async fn run_worker(&self, delivery: Delivery, recipient: NodeId) {
+        let Delivery {
+            endpoint,
+            self_ticket,
+            hide_ip,
+        } = delivery;
+        let mut backoff = INITIAL_BACKOFF;
+
+        while let Some(item) = self.next_pending(recipient) {
+            if endpoint.is_closed() {
+                break;
+            }
+            // The recording may have been deleted by hand, retrying can't bring it back
+            if let OutboxPayload::Voicemail { ref path } = item.payload {
+                if !tokio::fs::try_exists(path).await.unwrap_or(false) {
+                    warn!(%recipient, "Dropping voicemail whose recording is gone");
+                    self.discard(item);
+                    continue;
+                }
+            }
+            self.update(item.id, |i| i.attempts += 1);
+
+            let addr = self.recipient_addr(&item, hide_ip);
+            let on_sent = || self.update(item.id, |i| i.state = DeliveryState::Sent);
+            let result = match item.payload {
+                OutboxPayload::ContactRequest { ref invite, .. } => {
+                    let message = match invite {
+                        Some(invite) => ContactsMessage::InvitedRequest {
+                            contact: self_ticket.clone(),
+                            invite: invite.as_ref().clone(),
+                        },
+                        None => ContactsMessage::Request(self_ticket.clone()),
+                    };
+                    ContactsProtocol::send_message(&endpoint, addr, &message, on_sent).await
+                }
+                OutboxPayload::MissedCall { timestamp } => {
+                    let message = ContactsMessage::MissedCall {
+                        caller: self_ticket.clone(),
+                        timestamp,
+                    };
+                    ContactsProtocol::send_message(&endpoint, addr, &message, on_sent).await
+                }
+                OutboxPayload::ProfileUpdate => {
+                    let message = ContactsMessage::ProfileUpdate {
+                        contact: self_ticket.clone(),
+                        avatar: self.own_avatar.borrow().clone(),
+                    };
+                    ContactsProtocol::send_message(&endpoint, addr, &message, on_sent).await
+                }
+                OutboxPayload::Voicemail { ref path } => {
+                    let result = VoicemailProtocol::send(&endpoint, addr, &self_ticket, path).await;
+                    if result.is_ok() {
+                        _ = tokio::fs::remove_file(path).await;
+                    }
+                    result.map(|()| true)
+                }
+            };
+
+            match result {
+                Ok(accepted) => {
+                    self.update(item.id, |i| {
+                        i.state = DeliveryState::Delivered;
+                        if matches!(i.payload, OutboxPayload::ContactRequest { .. }) {
+                            i.accepted = Some(accepted);
+                        }
+                    });
+                    prune_delivered(&mut self.inner.lock().unwrap().items);
+                    backoff = INITIAL_BACKOFF;
+                }
+                Err(e) => {
+                    info!(%recipient, "Failed to deliver outbox item: {e}");
+                    self.update(item.id, |i| i.state = DeliveryState::Pending);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+/// Drops the oldest delivered items beyond [`MAX_DELIVERED`].
+fn prune_delivered(items: &mut Vec<OutboxItem>) {
+    let delivered = items
+        .iter()
+        .filter(|i| i.state == DeliveryState::Delivered)
+        .count();
+    let mut excess = delivered.saturating_sub(MAX_DELIVERED);
+    // Items are in the order they were queued
+    items.retain(|i| {
+        let drop = excess > 0 && i.state == DeliveryState::Delivered;
+        if drop {
+            excess -= 1;
+        }
+        !drop
+    });
+}
@@ -0,0 +1,161 @@
+//! Presence: whether contacts are online, away or busy, found out by probing them periodically.
+
+use std::{collections::HashSet, time::Duration};
+
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr, NodeId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::watch};
+use tracing::{debug, field, instrument, Span};
+
+use crate::{
+    error::{connection_lost, FreeVoipError},
+    logging::remote_peer,
+};
+
+pub const ALPN: &[u8] = b"free-voip/presence";
+
+const PROBE_VERSION: u8 = 1;
+
+/// How often contacts are probed.
+pub(crate) const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+/// How long to wait for a contact before considering them offline.
+pub(crate) const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper bound on the size of a serialized [`Presence`].
+const MAX_PRESENCE_SIZE: usize = 1024;
+pub const MAX_STATUS_TEXT_LEN: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PresenceState {
+    #[default]
+    Online,
+    Away,
+    Busy,
+    /// Unreachable, or hiding from us. Setting our own presence to this makes us invisible.
+    Offline,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub state: PresenceState,
+    pub status_text: Option<String>,
+}
+
+impl Presence {
+    pub const OFFLINE: Presence = Presence {
+        state: PresenceState::Offline,
+        status_text: None,
+    };
+}
+
+/// Who may see our presence.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PresenceVisibility {
+    #[default]
+    Contacts,
+    Nobody,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceSettings {
+    pub visibility: PresenceVisibility,
+    /// Contacts who never see our presence, whatever the visibility.
+    pub hidden_from: Vec<NodeId>,
+}
+
+/// Answers presence probes from the peers in the audience. Everyone else is refused, which looks
+/// the same as being offline to them.
+#[derive(Debug, Clone)]
+pub struct PresenceProtocol {
+    own_presence: watch::Receiver<Presence>,
+    audience: watch::Receiver<HashSet<NodeId>>,
+}
+
+impl PresenceProtocol {
+    pub fn new(
+        own_presence: watch::Receiver<Presence>,
+        audience: watch::Receiver<HashSet<NodeId>>,
+    ) -> Self {
+        Self {
+            own_presence,
+            audience,
+        }
+    }
+
+    /// Asks a peer for their presence. Fails if they are unreachable or hiding from us.
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "presence", peer = field::Empty)
+    )]
+    pub async fn probe(
+        endpoint: &Endpoint,
+        peer_addr: impl Into<NodeAddr>,
+    ) -> Result<Presence, FreeVoipError> {
+        let peer_addr = peer_addr.into();
+        Span::current().record("peer", field::display(peer_addr.node_id));
+
+        let connection = endpoint.connect(peer_addr, ALPN).await?;
+        let (mut proto_tx, mut proto_rx) = connection.open_bi().await?;
+        proto_tx
+            .write_u8(PROBE_VERSION)
+            .await
+            .map_err(connection_lost)?;
+        proto_tx.finish()?;
+
+        let buf = proto_rx.read_to_end(MAX_PRESENCE_SIZE).await?;
+        connection.close(0u32.into(), b"Presence received");
+        let presence = postcard::from_bytes::<Presence>(&buf)?;
+        if presence
+            .status_text
+            .as_ref()
+            .is_some_and(|t| t.chars().count() > MAX_STATUS_TEXT_LEN)
+        {
+            return Err(FreeVoipError::protocol_violation("Status text is too long"));
+        }
+
+        debug!(state = ?presence.state, "Probed presence");
+        Ok(presence)
+    }
+}
+
+impl ProtocolHandler for PresenceProtocol {
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "presence", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let remote_node_id = connection.remote_node_id()?;
+        let presence = self.own_presence.borrow().clone();
+        if presence.state == PresenceState::Offline
+            || !self.audience.borrow().contains(&remote_node_id)
+        {
+            return Err(AcceptError::NotAllowed {});
+        }
+
+        let (mut proto_tx, mut proto_rx) = connection.accept_bi().await?;
+        let mut version = [0u8; 1];
+        proto_rx
+            .read_exact(&mut version)
+            .await
+            .map_err(AcceptError::from_err)?;
+
+        let serialized_presence = postcard::to_stdvec(&presence).map_err(AcceptError::from_err)?;
+        proto_tx
+            .write_all(&serialized_presence)
+            .await
+            .map_err(AcceptError::from_err)?;
+        proto_tx.finish()?;
+        connection.closed().await;
+        Ok(())
+    }
+}
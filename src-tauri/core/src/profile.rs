@@ -0,0 +1,183 @@
+//! Avatars, kept content-addressed by their BLAKE3 hash and fetched on demand from the contact
+//! they belong to.
+
+use std::path::{Path, PathBuf};
+
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::watch,
+};
+use tracing::{debug, field, info, instrument, Span};
+
+use crate::{
+    error::{connection_lost, FreeVoipError},
+    logging::remote_peer,
+};
+
+pub const ALPN: &[u8] = b"free-voip/profile";
+
+const RESPONSE_FOUND: u8 = 1;
+const RESPONSE_NOT_FOUND: u8 = 0;
+
+pub const MAX_AVATAR_SIZE: usize = 256 * 1024;
+
+fn parse_hash(hash: &str) -> Result<blake3::Hash, FreeVoipError> {
+    blake3::Hash::from_hex(hash).map_err(|_| FreeVoipError::invalid_state("Invalid avatar hash"))
+}
+
+/// Avatar images on disk, named by the hex encoded BLAKE3 hash of their contents.
+#[derive(Debug, Clone)]
+pub struct AvatarStore {
+    dir: PathBuf,
+}
+
+impl AvatarStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, hash: &blake3::Hash) -> PathBuf {
+        self.dir.join(hash.to_hex().as_str())
+    }
+
+    /// Stores `image`, returning its hash.
+    pub async fn put(&self, image: &[u8]) -> Result<String, FreeVoipError> {
+        if image.len() > MAX_AVATAR_SIZE {
+            return Err(FreeVoipError::InvalidState(format!(
+                "Avatars can be at most {} KiB",
+                MAX_AVATAR_SIZE / 1024
+            )));
+        }
+
+        let hash = blake3::hash(image);
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(self.path(&hash), image).await?;
+        Ok(hash.to_hex().to_string())
+    }
+
+    /// The avatar with the given hash, if it is stored and intact.
+    pub async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, FreeVoipError> {
+        let hash = parse_hash(hash)?;
+        match fs::read(self.path(&hash)).await {
+            Ok(image) if blake3::hash(&image) == hash => Ok(Some(image)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Hands out our own avatar to peers who ask for it by hash.
+#[derive(Debug, Clone)]
+pub struct ProfileProtocol {
+    avatars: AvatarStore,
+    /// Hash of our current avatar. Only that one is handed out, not the ones of our contacts.
+    own_avatar: watch::Receiver<Option<String>>,
+}
+
+impl ProfileProtocol {
+    pub fn new(avatars: AvatarStore, own_avatar: watch::Receiver<Option<String>>) -> Self {
+        Self {
+            avatars,
+            own_avatar,
+        }
+    }
+
+    /// Fetches the avatar with the given hash from its owner and stores it in `avatars`.
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "profile", peer = field::Empty)
+    )]
+    pub async fn fetch_avatar(
+        endpoint: &Endpoint,
+        owner_addr: impl Into<NodeAddr>,
+        hash: &str,
+        avatars: &AvatarStore,
+    ) -> Result<Vec<u8>, FreeVoipError> {
+        let hash = parse_hash(hash)?;
+        let owner_addr = owner_addr.into();
+        Span::current().record("peer", field::display(owner_addr.node_id));
+
+        let connection = endpoint.connect(owner_addr, ALPN).await?;
+        let (mut proto_tx, mut proto_rx) = connection.open_bi().await?;
+        proto_tx.write_all(hash.as_bytes()).await?;
+        proto_tx.finish()?;
+
+        if proto_rx.read_u8().await.map_err(connection_lost)? != RESPONSE_FOUND {
+            connection.close(0u32.into(), b"Avatar not found");
+            return Err(FreeVoipError::invalid_state(
+                "The contact no longer has this avatar",
+            ));
+        }
+        let len = proto_rx.read_u32().await.map_err(connection_lost)? as usize;
+        if len > MAX_AVATAR_SIZE {
+            return Err(FreeVoipError::protocol_violation("Avatar is too large"));
+        }
+        let mut image = vec![0u8; len];
+        proto_rx.read_exact(&mut image).await?;
+        connection.close(0u32.into(), b"Avatar received");
+
+        if blake3::hash(&image) != hash {
+            return Err(FreeVoipError::protocol_violation("Avatar hash mismatch"));
+        }
+        avatars.put(&image).await?;
+        debug!(size = image.len(), "Fetched avatar");
+        Ok(image)
+    }
+}
+
+impl ProtocolHandler for ProfileProtocol {
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "profile", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let (mut proto_tx, mut proto_rx) = connection.accept_bi().await?;
+
+        let mut requested = [0u8; 32];
+        proto_rx
+            .read_exact(&mut requested)
+            .await
+            .map_err(AcceptError::from_err)?;
+        let requested = blake3::Hash::from_bytes(requested).to_hex().to_string();
+
+        let own_avatar = self.own_avatar.borrow().clone();
+        let image = match own_avatar {
+            Some(hash) if hash == requested => self
+                .avatars
+                .get(&hash)
+                .await
+                .map_err(AcceptError::from_err)?,
+            _ => None,
+        };
+
+        match image {
+            Some(image) => {
+                info!("Sending avatar");
+                proto_tx.write_u8(RESPONSE_FOUND).await?;
+                proto_tx.write_u32(image.len() as u32).await?;
+                proto_tx
+                    .write_all(&image)
+                    .await
+                    .map_err(AcceptError::from_err)?;
+            }
+            None => proto_tx.write_u8(RESPONSE_NOT_FOUND).await?,
+        }
+
+        proto_tx.finish()?;
+        connection.closed().await;
+        Ok(())
+    }
+}
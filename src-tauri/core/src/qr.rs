@@ -0,0 +1,57 @@
+//! QR codes of serialized tickets, so that they can be scanned instead of copied around.
+
+use std::io::Cursor;
+
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+
+use crate::FreeVoipError;
+
+/// Smallest width and height of rendered codes, in pixels.
+const MIN_SIZE: u32 = 256;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum QrFormat {
+    Svg,
+    Png,
+}
+
+/// Renders `data` as a QR code image in the given format.
+pub fn encode(data: &str, format: QrFormat) -> Result<Vec<u8>, FreeVoipError> {
+    let code = QrCode::new(data).map_err(|e| FreeVoipError::invalid_state(e.to_string()))?;
+
+    match format {
+        QrFormat::Svg => Ok(code
+            .render::<svg::Color>()
+            .min_dimensions(MIN_SIZE, MIN_SIZE)
+            .build()
+            .into_bytes()),
+        QrFormat::Png => {
+            let image = code
+                .render::<Luma<u8>>()
+                .min_dimensions(MIN_SIZE, MIN_SIZE)
+                .build();
+            let mut png = Cursor::new(vec![]);
+            image
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(|e| FreeVoipError::invalid_state(e.to_string()))?;
+            Ok(png.into_inner())
+        }
+    }
+}
+
+/// The text of the first readable QR code in a PNG or JPEG image.
+pub fn decode(image: &[u8]) -> Result<String, FreeVoipError> {
+    let image = image::load_from_memory(image)
+        .map_err(|_| FreeVoipError::invalid_state("Not a PNG or JPEG image"))?;
+
+    let mut prepared = rqrr::PreparedImage::prepare(image.to_luma8());
+    prepared
+        .detect_grids()
+        .into_iter()
+        .find_map(|grid| grid.decode().ok())
+        .map(|(_, text)| text)
+        .ok_or_else(|| FreeVoipError::invalid_state("No QR code found in the image"))
+}
@@ -0,0 +1,407 @@
+use crate::{
+    call::{AudioConfig, CallMedia},
+    matroska::{
+        write_aac_track, write_element, write_fixed_uint, write_float, write_header, write_id,
+        write_simple_block, write_size, write_uint, CLUSTER, CLUSTER_TIMESTAMP, CODEC_ID,
+        DEFAULT_TIMESTAMP_SCALE, DURATION, FLAG_LACING, INFO, MAX_CLUSTER_DURATION, MUXING_APP,
+        NAME, PIXEL_HEIGHT, PIXEL_WIDTH, SEGMENT, TIMESTAMP_SCALE, TRACKS, TRACK_ENTRY,
+        TRACK_NUMBER, TRACK_TYPE, TRACK_TYPE_VIDEO, TRACK_UID, VIDEO, WRITING_APP,
+    },
+    FreeVoipError,
+};
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
+    sync::{broadcast, oneshot},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::debug;
+
+/// How long frames are held back, in milliseconds, so that frames of other tracks that arrive
+/// later can still be written before them.
+const REORDER_WINDOW: u64 = 500;
+
+/// Tracks in the recording, numbered from 1 in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Track {
+    LocalVideo = 1,
+    RemoteVideo = 2,
+    LocalAudio = 3,
+    RemoteAudio = 4,
+}
+
+impl Track {
+    const ALL: [Track; 4] = [
+        Track::LocalVideo,
+        Track::RemoteVideo,
+        Track::LocalAudio,
+        Track::RemoteAudio,
+    ];
+
+    fn for_media(media: &CallMedia, local: bool) -> Self {
+        match (media, local) {
+            (CallMedia::Video { .. }, true) => Track::LocalVideo,
+            (CallMedia::Video { .. }, false) => Track::RemoteVideo,
+            (CallMedia::Audio { .. }, true) => Track::LocalAudio,
+            (CallMedia::Audio { .. }, false) => Track::RemoteAudio,
+        }
+    }
+
+    fn number(self) -> u64 {
+        self as u64
+    }
+
+    fn index(self) -> usize {
+        self as usize - 1
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Track::LocalVideo => "Local video",
+            Track::RemoteVideo => "Remote video",
+            Track::LocalAudio => "Local audio",
+            Track::RemoteAudio => "Remote audio",
+        }
+    }
+}
+
+/// Reads the frame size from a VP8 key frame header.
+fn vp8_dimensions(frame: &[u8]) -> Option<(u32, u32)> {
+    if frame.len() < 10 || frame[3..6] != [0x9D, 0x01, 0x2A] {
+        return None;
+    }
+    let width = u16::from_le_bytes([frame[6], frame[7]]) & 0x3FFF;
+    let height = u16::from_le_bytes([frame[8], frame[9]]) & 0x3FFF;
+    Some((width as u32, height as u32))
+}
+
+/// Maps a track's own timestamps onto the recording's clock.
+///
+/// Each side's encoder has its own clock, so the first frame of a track is placed at its arrival
+/// time and later frames keep their spacing relative to it.
+#[derive(Debug, Default, Clone, Copy)]
+struct TrackClock {
+    base: Option<(u64, u64)>,
+    last: u64,
+}
+
+impl TrackClock {
+    fn map(&mut self, frame_timestamp_us: u64, arrival_ms: u64) -> u64 {
+        let (base_frame, base_arrival) = *self.base.get_or_insert((frame_timestamp_us, arrival_ms));
+        let timestamp = base_arrival + frame_timestamp_us.saturating_sub(base_frame) / 1000;
+
+        self.last = self.last.max(timestamp);
+        self.last
+    }
+}
+
+/// A frame waiting in the reorder buffer.
+struct PendingFrame {
+    timestamp: u64,
+    track: Track,
+    is_key: bool,
+    data: Vec<u8>,
+}
+
+struct MatroskaWriter {
+    file: BufWriter<File>,
+    segment_size_offset: u64,
+    segment_data_offset: u64,
+    duration_offset: u64,
+    /// Offsets of the width and height placeholders of the video tracks.
+    dimension_offsets: [Option<(u64, u64)>; 4],
+    dimensions: [Option<(u32, u32)>; 4],
+    clocks: [TrackClock; 4],
+    /// Frames not written yet, ordered by timestamp.
+    pending: Vec<PendingFrame>,
+    /// Timestamp of the last written block, which later blocks never go before.
+    last_written: u64,
+    cluster: Vec<u8>,
+    cluster_timestamp: Option<u64>,
+    duration: u64,
+}
+
+impl MatroskaWriter {
+    async fn create(
+        path: &Path,
+        local_audio: AudioConfig,
+        remote_audio: AudioConfig,
+    ) -> Result<Self, FreeVoipError> {
+        let mut buf = vec![];
+
+        write_header(&mut buf);
+
+        // Segment size is unknown until the recording stops
+        write_id(&mut buf, SEGMENT);
+        let segment_size_offset = buf.len() as u64;
+        buf.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let segment_data_offset = buf.len() as u64;
+
+        let mut info = vec![];
+        write_uint(&mut info, TIMESTAMP_SCALE, DEFAULT_TIMESTAMP_SCALE);
+        write_element(&mut info, MUXING_APP, b"free-voip");
+        write_element(&mut info, WRITING_APP, b"free-voip");
+        write_id(&mut buf, INFO);
+        write_size(&mut buf, info.len() as u64 + 11);
+        buf.extend_from_slice(&info);
+        let duration_offset = write_float(&mut buf, DURATION, 0.0);
+
+        // Placeholder offsets are made absolute by adding everything written before them
+        let mut tracks = vec![];
+        let mut dimension_offsets = [None; 4];
+        for track in Track::ALL {
+            let audio_config = match track {
+                Track::LocalAudio => Some(local_audio),
+                Track::RemoteAudio => Some(remote_audio),
+                Track::LocalVideo | Track::RemoteVideo => None,
+            };
+            if let Some(config) = audio_config {
+                write_aac_track(&mut tracks, track.number(), track.name(), config);
+                continue;
+            }
+
+            let mut entry = vec![];
+            write_uint(&mut entry, TRACK_NUMBER, track.number());
+            write_uint(&mut entry, TRACK_UID, track.number());
+            write_uint(&mut entry, FLAG_LACING, 0);
+            write_element(&mut entry, NAME, track.name().as_bytes());
+            write_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_VIDEO);
+            write_element(&mut entry, CODEC_ID, b"V_VP8");
+
+            let mut video = vec![];
+            let width_offset = write_fixed_uint(&mut video, PIXEL_WIDTH);
+            let height_offset = write_fixed_uint(&mut video, PIXEL_HEIGHT);
+            write_id(&mut entry, VIDEO);
+            write_size(&mut entry, video.len() as u64);
+
+            // Tracks header (ID and 8-byte size) and entry header (ID and 1-byte size) come
+            // before this
+            let base = buf.len() as u64 + 4 + 8 + tracks.len() as u64 + 2 + entry.len() as u64;
+            dimension_offsets[track.index()] = Some((base + width_offset, base + height_offset));
+            entry.extend_from_slice(&video);
+
+            write_element(&mut tracks, TRACK_ENTRY, &entry);
+        }
+
+        // Tracks always uses an 8-byte size so that the offsets above hold
+        write_id(&mut buf, TRACKS);
+        buf.push(0x01);
+        buf.extend_from_slice(&(tracks.len() as u64).to_be_bytes()[1..]);
+        buf.extend_from_slice(&tracks);
+
+        let mut file = BufWriter::new(File::create(path).await?);
+        file.write_all(&buf).await?;
+
+        Ok(Self {
+            file,
+            segment_size_offset,
+            segment_data_offset,
+            duration_offset,
+            dimension_offsets,
+            dimensions: [None; 4],
+            clocks: [TrackClock::default(); 4],
+            pending: vec![],
+            last_written: 0,
+            cluster: vec![],
+            cluster_timestamp: None,
+            duration: 0,
+        })
+    }
+
+    async fn write_media(
+        &mut self,
+        media: CallMedia,
+        local: bool,
+        arrival_ms: u64,
+    ) -> Result<(), FreeVoipError> {
+        let track = Track::for_media(&media, local);
+        let (frame_type, timestamp, frame_data) = match media {
+            CallMedia::Video {
+                frame_type,
+                timestamp,
+                frame_data,
+                ..
+            }
+            | CallMedia::Audio {
+                frame_type,
+                timestamp,
+                frame_data,
+                ..
+            } => (frame_type, timestamp, frame_data),
+        };
+        let is_key = frame_type == "key";
+
+        // Video tracks start at their first key frame, which also carries the frame size
+        if matches!(track, Track::LocalVideo | Track::RemoteVideo)
+            && self.dimensions[track.index()].is_none()
+        {
+            match vp8_dimensions(&frame_data).filter(|_| is_key) {
+                Some(dimensions) => self.dimensions[track.index()] = Some(dimensions),
+                None => return Ok(()),
+            }
+        }
+
+        let timestamp = self.clocks[track.index()].map(timestamp, arrival_ms);
+        self.duration = self.duration.max(timestamp);
+
+        // Tracks are interleaved by timestamp, so hold frames back until the other tracks caught up
+        let index = self.pending.partition_point(|f| f.timestamp <= timestamp);
+        self.pending.insert(
+            index,
+            PendingFrame {
+                timestamp,
+                track,
+                is_key,
+                data: frame_data,
+            },
+        );
+        while self
+            .pending
+            .first()
+            .is_some_and(|f| f.timestamp + REORDER_WINDOW <= self.duration)
+        {
+            let frame = self.pending.remove(0);
+            self.write_block(frame).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn write_block(&mut self, frame: PendingFrame) -> Result<(), FreeVoipError> {
+        // Frames that arrive later than the reorder window are moved up to keep the order
+        let timestamp = frame.timestamp.max(self.last_written);
+        self.last_written = timestamp;
+
+        // Start a new cluster when the block would not fit the current one
+        let cluster_timestamp = match self.cluster_timestamp {
+            Some(cluster_timestamp) if timestamp - cluster_timestamp < MAX_CLUSTER_DURATION => {
+                cluster_timestamp
+            }
+            _ => {
+                self.flush_cluster().await?;
+                self.cluster_timestamp = Some(timestamp);
+                write_uint(&mut self.cluster, CLUSTER_TIMESTAMP, timestamp);
+                timestamp
+            }
+        };
+
+        write_simple_block(
+            &mut self.cluster,
+            frame.track.number(),
+            (timestamp - cluster_timestamp) as i16,
+            frame.is_key,
+            &frame.data,
+        );
+
+        Ok(())
+    }
+
+    async fn flush_cluster(&mut self) -> Result<(), FreeVoipError> {
+        if self.cluster_timestamp.take().is_none() {
+            return Ok(());
+        }
+
+        let mut buf = vec![];
+        write_element(&mut buf, CLUSTER, &self.cluster);
+        self.cluster.clear();
+        self.file.write_all(&buf).await.map_err(Into::into)
+    }
+
+    async fn patch(&mut self, offset: u64, data: &[u8]) -> Result<(), FreeVoipError> {
+        self.file.seek(SeekFrom::Start(offset)).await?;
+        self.file.write_all(data).await.map_err(Into::into)
+    }
+
+    /// Writes the last cluster and fills in the sizes that were unknown while recording.
+    async fn finish(mut self) -> Result<(), FreeVoipError> {
+        for frame in std::mem::take(&mut self.pending) {
+            self.write_block(frame).await?;
+        }
+        self.flush_cluster().await?;
+        let end = self.file.stream_position().await?;
+
+        let mut segment_size = (end - self.segment_data_offset).to_be_bytes();
+        segment_size[0] = 0x01;
+        self.patch(self.segment_size_offset, &segment_size).await?;
+        self.patch(self.duration_offset, &(self.duration as f64).to_be_bytes())
+            .await?;
+
+        for track in Track::ALL {
+            if let (Some((width_offset, height_offset)), Some((width, height))) = (
+                self.dimension_offsets[track.index()],
+                self.dimensions[track.index()],
+            ) {
+                self.patch(width_offset, &width.to_be_bytes()).await?;
+                self.patch(height_offset, &height.to_be_bytes()).await?;
+            }
+        }
+
+        self.file.flush().await.map_err(Into::into)
+    }
+}
+
+/// Records both sides of a call into a Matroska file with VP8 video and AAC audio, one video and
+/// one audio track per participant.
+#[derive(Debug)]
+pub struct CallRecorder {
+    pub path: PathBuf,
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<Result<(), FreeVoipError>>,
+}
+
+impl CallRecorder {
+    /// Starts recording to `path`, with the audio of each side encoded as given.
+    pub async fn start(
+        path: PathBuf,
+        mut local_media_rx: broadcast::Receiver<CallMedia>,
+        mut remote_media_rx: broadcast::Receiver<CallMedia>,
+        local_audio: AudioConfig,
+        remote_audio: AudioConfig,
+    ) -> Result<Self, FreeVoipError> {
+        let mut writer = MatroskaWriter::create(&path, local_audio, remote_audio).await?;
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+
+        let task = tokio::spawn(async move {
+            let start = Instant::now();
+
+            loop {
+                let (media, local) = tokio::select! {
+                    media = local_media_rx.recv() => (media, true),
+                    media = remote_media_rx.recv() => (media, false),
+                    _ = &mut stop_rx => break,
+                };
+
+                match media {
+                    Ok(media) => {
+                        let arrival_ms = start.elapsed().as_millis() as u64;
+                        writer.write_media(media, local, arrival_ms).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        debug!("Call recorder skipped {skipped} frames");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+
+            writer.finish().await
+        });
+
+        Ok(Self {
+            path,
+            stop_tx,
+            task,
+        })
+    }
+
+    /// Stops recording and finalizes the file, returning its path.
+    pub async fn stop(self) -> Result<PathBuf, FreeVoipError> {
+        _ = self.stop_tx.send(());
+        self.task.await??;
+        Ok(self.path)
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::FreeVoipError;
+
+pub const CREDENTIALS_STORE: &str = "credentials.json";
+pub const CONTACTS_STORE: &str = "contacts.json";
+pub const OUTBOX_STORE: &str = "outbox.json";
+pub const CALL_HISTORY_STORE: &str = "call_history.json";
+pub const VOICEMAIL_STORE: &str = "voicemail.json";
+pub const SETTINGS_STORE: &str = "settings.json";
+
+/// Persistent key-value storage for the node, grouped into named stores of JSON values.
+///
+/// The store names are file names so that implementations can map each store to a JSON file.
+pub trait Storage: Send + Sync + 'static {
+    fn get(&self, store: &str, key: &str) -> Result<Option<Value>, FreeVoipError>;
+    fn set(&self, store: &str, key: &str, value: Value) -> Result<(), FreeVoipError>;
+    fn delete(&self, store: &str, key: &str) -> Result<(), FreeVoipError>;
+    fn entries(&self, store: &str) -> Result<Vec<(String, Value)>, FreeVoipError>;
+    fn clear(&self, store: &str) -> Result<(), FreeVoipError>;
+}
+
+/// Storage that only lives as long as the process, for tests and throwaway nodes.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    stores: Mutex<HashMap<String, BTreeMap<String, Value>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, store: &str, key: &str) -> Result<Option<Value>, FreeVoipError> {
+        let stores = self.stores.lock().unwrap();
+        Ok(stores.get(store).and_then(|s| s.get(key)).cloned())
+    }
+
+    fn set(&self, store: &str, key: &str, value: Value) -> Result<(), FreeVoipError> {
+        let mut stores = self.stores.lock().unwrap();
+        stores
+            .entry(store.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    fn delete(&self, store: &str, key: &str) -> Result<(), FreeVoipError> {
+        let mut stores = self.stores.lock().unwrap();
+        if let Some(store) = stores.get_mut(store) {
+            store.remove(key);
+        }
+        Ok(())
+    }
+
+    fn entries(&self, store: &str) -> Result<Vec<(String, Value)>, FreeVoipError> {
+        let stores = self.stores.lock().unwrap();
+        Ok(stores
+            .get(store)
+            .map(|s| s.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    fn clear(&self, store: &str) -> Result<(), FreeVoipError> {
+        self.stores.lock().unwrap().remove(store);
+        Ok(())
+    }
+}
+
+pub(crate) fn get_json<T: DeserializeOwned>(
+    storage: &dyn Storage,
+    store: &str,
+    key: &str,
+) -> Result<Option<T>, FreeVoipError> {
+    storage
+        .get(store, key)?
+        .map(|v| serde_json::from_value::<T>(v).map_err(Into::into))
+        .transpose()
+}
+
+pub(crate) fn set_json<T: Serialize>(
+    storage: &dyn Storage,
+    store: &str,
+    key: &str,
+    value: &T,
+) -> Result<(), FreeVoipError> {
+    let value = serde_json::to_value(value)?;
+    storage.set(store, key, value)
+}
@@ -0,0 +1,393 @@
+use crate::{
+    call::{AudioConfig, CallMedia},
+    contacts::ContactTicket,
+    error::{connection_lost, FreeVoipError},
+    logging::remote_peer,
+    matroska::{
+        self, write_aac_track, write_element, write_float, write_header, write_simple_block,
+        write_uint, CLUSTER, CLUSTER_TIMESTAMP, DEFAULT_TIMESTAMP_SCALE, DURATION, INFO,
+        MAX_CLUSTER_DURATION, MUXING_APP, SEGMENT, TIMESTAMP_SCALE, TRACKS, WRITING_APP,
+    },
+    unix_timestamp,
+};
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    Endpoint, NodeAddr, NodeId,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{broadcast, oneshot, watch},
+    task::JoinHandle,
+};
+use tracing::{field, info, instrument, Span};
+
+pub const ALPN: &[u8] = b"free-voip/voicemail";
+
+/// Track number of the audio in voicemail files.
+const AUDIO_TRACK: u64 = 1;
+
+const RESPONSE_DECLINE: u8 = 0;
+const RESPONSE_ACCEPT: u8 = 1;
+
+pub const MAX_DURATION: Duration = Duration::from_secs(60);
+const MAX_VOICEMAIL_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_HEADER_SIZE: u32 = 4 * 1024;
+/// Further voicemails from a sender are declined until some of theirs are deleted.
+pub const MAX_VOICEMAILS_PER_SENDER: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct VoicemailHeader {
+    sender: ContactTicket,
+    size: u64,
+}
+
+/// A voicemail in the inbox.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VoicemailInfo {
+    /// Hex encoded BLAKE3 hash of the voicemail file.
+    pub id: String,
+    pub sender: ContactTicket,
+    pub received_at: u64,
+    pub duration_ms: u64,
+    /// How the audio was encoded, which the decoder needs to be configured with.
+    #[serde(default)]
+    pub audio_config: AudioConfig,
+}
+
+fn frame_timestamp(media: &CallMedia) -> u64 {
+    match media {
+        CallMedia::Audio { timestamp, .. } | CallMedia::Video { timestamp, .. } => *timestamp,
+    }
+}
+
+/// Duration covered by `frames` in milliseconds, based on their microsecond timestamps.
+pub fn duration_ms(frames: &[CallMedia]) -> u64 {
+    match (frames.first(), frames.last()) {
+        (Some(first), Some(last)) => {
+            frame_timestamp(last).saturating_sub(frame_timestamp(first)) / 1000
+        }
+        _ => 0,
+    }
+}
+
+/// Writes audio `frames` encoded with `config` into a Matroska file with a single AAC track.
+pub fn encode_container(frames: &[CallMedia], config: AudioConfig) -> Vec<u8> {
+    let mut segment = vec![];
+
+    let mut info = vec![];
+    write_uint(&mut info, TIMESTAMP_SCALE, DEFAULT_TIMESTAMP_SCALE);
+    write_element(&mut info, MUXING_APP, b"free-voip");
+    write_element(&mut info, WRITING_APP, b"free-voip");
+    write_float(&mut info, DURATION, duration_ms(frames) as f64);
+    write_element(&mut segment, INFO, &info);
+
+    let mut tracks = vec![];
+    write_aac_track(&mut tracks, AUDIO_TRACK, "Voicemail", config);
+    write_element(&mut segment, TRACKS, &tracks);
+
+    // Block timestamps are in milliseconds since the first frame
+    let first_timestamp = frames.first().map(frame_timestamp).unwrap_or_default();
+    let mut cluster = vec![];
+    let mut cluster_timestamp = None;
+    let mut last = 0;
+    for frame in frames {
+        let CallMedia::Audio {
+            frame_type,
+            frame_data,
+            ..
+        } = frame
+        else {
+            continue;
+        };
+        let timestamp = (frame_timestamp(frame).saturating_sub(first_timestamp) / 1000).max(last);
+        last = timestamp;
+
+        let start = match cluster_timestamp {
+            Some(start) if timestamp - start < MAX_CLUSTER_DURATION => start,
+            _ => {
+                if cluster_timestamp.is_some() {
+                    write_element(&mut segment, CLUSTER, &cluster);
+                    cluster.clear();
+                }
+                cluster_timestamp = Some(timestamp);
+                write_uint(&mut cluster, CLUSTER_TIMESTAMP, timestamp);
+                timestamp
+            }
+        };
+        write_simple_block(
+            &mut cluster,
+            AUDIO_TRACK,
+            (timestamp - start) as i16,
+            frame_type == "key",
+            frame_data,
+        );
+    }
+    if cluster_timestamp.is_some() {
+        write_element(&mut segment, CLUSTER, &cluster);
+    }
+
+    let mut buf = vec![];
+    write_header(&mut buf);
+    write_element(&mut buf, SEGMENT, &segment);
+    buf
+}
+
+/// Reads back the audio frames of a voicemail, with timestamps in microseconds, and how they
+/// were encoded.
+pub fn decode_container(bytes: &[u8]) -> Result<(Vec<CallMedia>, AudioConfig), FreeVoipError> {
+    let segment = matroska::read_segment(bytes)?;
+    let entry = matroska::find_track(&segment, b"A_AAC")?
+        .ok_or_else(|| FreeVoipError::protocol_violation("Voicemail has no audio track"))?;
+    let track = matroska::track_number(entry)?;
+    let config = matroska::audio_config(entry)?;
+
+    let frames = matroska::read_blocks(&segment)?
+        .into_iter()
+        .map(|block| {
+            if block.track != track {
+                return Err(FreeVoipError::protocol_violation(
+                    "Voicemail may only contain audio",
+                ));
+            }
+            Ok(CallMedia::Audio {
+                frame_type: if block.is_key { "key" } else { "delta" }.to_owned(),
+                timestamp: block.timestamp_us,
+                duration: None,
+                byte_length: block.data.len() as u64,
+                frame_data: block.data.to_vec(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((frames, config))
+}
+
+/// Location of the voicemail with the given ID from `sender` in `inbox_dir`.
+pub fn inbox_path(inbox_dir: &Path, sender: NodeId, id: &str) -> PathBuf {
+    inbox_dir.join(sender.to_string()).join(format!("{id}.mka"))
+}
+
+/// Number of voicemails from `sender` in `inbox_dir`.
+async fn stored_count(inbox_dir: &Path, sender: NodeId) -> Result<usize, FreeVoipError> {
+    let mut entries = match fs::read_dir(inbox_dir.join(sender.to_string())).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut count = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().extension().is_some_and(|ext| ext == "mka") {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Sends `on_frame` each frame of the voicemail at `path`, paced by the frame timestamps.
+pub async fn play(path: &Path, mut on_frame: impl FnMut(CallMedia)) -> Result<(), FreeVoipError> {
+    let bytes = fs::read(path).await?;
+    let (frames, _) = decode_container(&bytes)?;
+
+    let start = tokio::time::Instant::now();
+    let first_timestamp = frames.first().map(frame_timestamp).unwrap_or_default();
+    for frame in frames {
+        let offset = frame_timestamp(&frame).saturating_sub(first_timestamp);
+        tokio::time::sleep_until(start + Duration::from_micros(offset)).await;
+        on_frame(frame);
+    }
+    Ok(())
+}
+
+/// Collects outgoing audio frames for a voicemail until stopped or [`MAX_DURATION`] is reached.
+#[derive(Debug)]
+pub struct VoicemailRecorder {
+    pub recipient: NodeId,
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<Vec<CallMedia>>,
+}
+
+impl VoicemailRecorder {
+    pub fn start(recipient: NodeId, mut media_rx: broadcast::Receiver<CallMedia>) -> Self {
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+
+        let task = tokio::spawn(async move {
+            let mut frames = vec![];
+            let deadline = tokio::time::sleep(MAX_DURATION);
+            tokio::pin!(deadline);
+
+            loop {
+                tokio::select! {
+                    media = media_rx.recv() => match media {
+                        Ok(media @ CallMedia::Audio { .. }) => frames.push(media),
+                        Ok(CallMedia::Video { .. }) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = &mut deadline => break,
+                    _ = &mut stop_rx => break,
+                }
+            }
+            frames
+        });
+
+        Self {
+            recipient,
+            stop_tx,
+            task,
+        }
+    }
+
+    pub async fn stop(self) -> Result<Vec<CallMedia>, FreeVoipError> {
+        _ = self.stop_tx.send(());
+        self.task.await.map_err(Into::into)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VoicemailProtocol {
+    received_tx: broadcast::Sender<VoicemailInfo>,
+    inbox_dir: PathBuf,
+    /// Voicemails are only accepted from these nodes.
+    contacts: watch::Receiver<HashSet<NodeId>>,
+}
+
+impl VoicemailProtocol {
+    pub fn new(
+        received_tx: broadcast::Sender<VoicemailInfo>,
+        inbox_dir: PathBuf,
+        contacts: watch::Receiver<HashSet<NodeId>>,
+    ) -> Self {
+        Self {
+            received_tx,
+            inbox_dir,
+            contacts,
+        }
+    }
+
+    /// Delivers the voicemail file at `path` to the recipient.
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "voicemail", peer = field::Empty)
+    )]
+    pub async fn send(
+        endpoint: &Endpoint,
+        recipient_addr: impl Into<NodeAddr>,
+        sender_ticket: &ContactTicket,
+        path: &Path,
+    ) -> Result<(), FreeVoipError> {
+        let bytes = fs::read(path).await?;
+        let header = VoicemailHeader {
+            sender: sender_ticket.clone(),
+            size: bytes.len() as u64,
+        };
+
+        let recipient_addr = recipient_addr.into();
+        Span::current().record("peer", field::display(recipient_addr.node_id));
+
+        let connection = endpoint.connect(recipient_addr, ALPN).await?;
+        let (mut proto_tx, mut proto_rx) = connection.open_bi().await?;
+
+        let serialized_header = postcard::to_stdvec(&header)?;
+        proto_tx
+            .write_u32(serialized_header.len() as u32)
+            .await
+            .map_err(connection_lost)?;
+        proto_tx.write_all(&serialized_header).await?;
+        proto_tx.write_all(&bytes).await?;
+        proto_tx.finish()?;
+
+        // Wait for the recipient to store it
+        let response = proto_rx.read_u8().await.map_err(connection_lost)?;
+        connection.close(0u32.into(), b"Voicemail delivered");
+
+        if response == RESPONSE_ACCEPT {
+            Ok(())
+        } else {
+            Err(FreeVoipError::Declined)
+        }
+    }
+}
+
+impl ProtocolHandler for VoicemailProtocol {
+    #[instrument(
+        name = "connection",
+        skip_all,
+        fields(protocol = "voicemail", peer = %remote_peer(&connection))
+    )]
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let sender = connection.remote_node_id()?;
+        if !self.contacts.borrow().contains(&sender) {
+            return Err(AcceptError::NotAllowed {});
+        }
+        let (mut proto_tx, mut proto_rx) = connection.accept_bi().await?;
+
+        let header = {
+            let header_len = proto_rx.read_u32().await?;
+            if header_len > MAX_HEADER_SIZE {
+                return Err(AcceptError::NotAllowed {});
+            }
+            let mut buf = vec![0u8; header_len as usize];
+            proto_rx
+                .read_exact(&mut buf)
+                .await
+                .map_err(AcceptError::from_err)?;
+            postcard::from_bytes::<VoicemailHeader>(&buf).map_err(AcceptError::from_err)?
+        };
+
+        // Voicemails must come from the node they claim to be from
+        if header.sender.node_id != sender || header.size > MAX_VOICEMAIL_SIZE {
+            return Err(AcceptError::NotAllowed {});
+        }
+
+        if stored_count(&self.inbox_dir, sender)
+            .await
+            .map_err(AcceptError::from_err)?
+            >= MAX_VOICEMAILS_PER_SENDER
+        {
+            info!("Declining voicemail, the sender's inbox is full");
+            proto_tx.write_u8(RESPONSE_DECLINE).await?;
+            proto_tx.finish()?;
+            connection.closed().await;
+            return Ok(());
+        }
+
+        let mut bytes = vec![0u8; header.size as usize];
+        proto_rx
+            .read_exact(&mut bytes)
+            .await
+            .map_err(AcceptError::from_err)?;
+        let (frames, audio_config) = decode_container(&bytes).map_err(AcceptError::from_err)?;
+
+        // Store the voicemail, named by its hash so that redeliveries are deduplicated
+        let info = VoicemailInfo {
+            id: blake3::hash(&bytes).to_hex().to_string(),
+            sender: header.sender,
+            received_at: unix_timestamp(),
+            duration_ms: duration_ms(&frames),
+            audio_config,
+        };
+        let path = inbox_path(&self.inbox_dir, sender, &info.id);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        fs::write(path, &bytes).await?;
+        info!(duration_ms = info.duration_ms, "Received voicemail");
+
+        self.received_tx.send(info).map_err(AcceptError::from_err)?;
+
+        proto_tx.write_u8(RESPONSE_ACCEPT).await?;
+        proto_tx.finish()?;
+        connection.closed().await;
+        Ok(())
+    }
+}
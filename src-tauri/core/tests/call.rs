@@ -0,0 +1,152 @@
+mod harness;
+
+use free_voip_core::{
+    call::{self, CallControl, CallMedia},
+    history::{CallDirection, CallOutcome},
+};
+use harness::{audio_frame, frame_timestamp, recv, respond_with, spawn_peers, video_frame};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_accepted_and_media_round_trips() {
+    let [mut alice, mut bob] = spawn_peers().await;
+
+    let ring = alice.ring(bob.addr());
+    let gui = async {
+        let ticket = recv(&mut bob.rings).await;
+        bob.ring_responses.send(true).unwrap();
+        ticket
+    };
+    let (answered, ticket) = tokio::join!(ring, gui);
+    assert_eq!(answered, Ok(true));
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+
+    // Bob echoes everything Alice sends
+    for timestamp in (0..50).map(|i| i * 20_000) {
+        alice.media_out.send(audio_frame(timestamp)).unwrap();
+
+        let received = recv(&mut bob.media_in).await;
+        assert_eq!(frame_timestamp(&received), timestamp);
+        bob.media_out.send(received).unwrap();
+
+        let CallMedia::Audio { frame_data, .. } = recv(&mut alice.media_in).await else {
+            panic!("Expected audio");
+        };
+        assert_eq!(frame_data, timestamp.to_be_bytes());
+    }
+
+    // Larger video frames both ways
+    for timestamp in (0..10).map(|i| i * 33_333) {
+        bob.media_out
+            .send(video_frame(timestamp, 32 * 1024))
+            .unwrap();
+        let received = recv(&mut alice.media_in).await;
+        assert_eq!(frame_timestamp(&received), timestamp);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disconnect_ends_call_on_both_sides() {
+    let [mut alice, mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+
+    assert_eq!(alice.ring(bob.addr()).await, Ok(true));
+    alice.media_out.send(audio_frame(0)).unwrap();
+    recv(&mut bob.media_in).await;
+
+    assert!(alice.call.disconnect().await);
+    recv(&mut bob.hang_ups).await;
+    recv(&mut alice.hang_ups).await;
+
+    let alice_record = recv(&mut alice.history).await;
+    assert_eq!(alice_record.direction, CallDirection::Outgoing);
+    assert_eq!(alice_record.outcome, CallOutcome::Answered);
+    assert_eq!(alice_record.peer, bob.ticket.node_id);
+
+    let bob_record = recv(&mut bob.history).await;
+    assert_eq!(bob_record.direction, CallDirection::Incoming);
+    assert_eq!(bob_record.outcome, CallOutcome::Answered);
+    assert_eq!(bob_record.peer, alice.ticket.node_id);
+
+    // Nothing left to disconnect, and a new call can be made
+    assert!(!alice.call.disconnect().await);
+    assert_eq!(alice.ring(bob.addr()).await, Ok(true));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_from_caller_with_legacy_ticket() {
+    let [alice, mut bob] = spawn_peers().await;
+
+    // Tickets from before addresses were added end after the node ID
+    let connection = alice
+        .endpoint()
+        .connect(bob.addr(), call::ALPN)
+        .await
+        .unwrap();
+    let (mut proto_tx, _proto_rx) = connection.open_bi().await.unwrap();
+    let legacy_ticket = postcard::to_stdvec(&("Alice", alice.ticket.node_id)).unwrap();
+    proto_tx.write_all(&legacy_ticket).await.unwrap();
+
+    let ticket = recv(&mut bob.rings).await;
+    assert_eq!(ticket.nickname, "Alice");
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_declined() {
+    let [mut alice, mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), false);
+
+    assert_eq!(alice.ring(bob.addr()).await, Ok(false));
+    assert_eq!(
+        recv(&mut alice.history).await.outcome,
+        CallOutcome::Declined
+    );
+    assert_eq!(recv(&mut bob.history).await.outcome, CallOutcome::Declined);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ring_during_call_is_busy() {
+    let [alice, bob, mut carol] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+
+    assert_eq!(alice.ring(bob.addr()).await, Ok(true));
+    assert_eq!(carol.ring(bob.addr()).await, Ok(false));
+    assert_eq!(recv(&mut carol.history).await.outcome, CallOutcome::Busy);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hanging_up_while_ringing_cancels_ring() {
+    let [mut alice, mut bob] = spawn_peers().await;
+
+    let call = alice.call.clone();
+    let (ring, _) = tokio::join!(alice.ring(bob.addr()), async {
+        // Nobody answers, Alice gives up
+        recv(&mut bob.rings).await;
+        call.disconnect().await;
+    });
+
+    assert_eq!(ring, Ok(false));
+    assert_eq!(
+        recv(&mut alice.history).await.outcome,
+        CallOutcome::Cancelled
+    );
+    assert_eq!(recv(&mut bob.history).await.outcome, CallOutcome::Missed);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn control_messages_reach_peer() {
+    let [alice, mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+
+    assert_eq!(alice.ring(bob.addr()).await, Ok(true));
+    alice
+        .call
+        .send_control(&CallControl::Recording { active: true })
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        recv(&mut bob.controls).await,
+        CallControl::Recording { active: true }
+    ));
+}
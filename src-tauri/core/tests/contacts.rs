@@ -0,0 +1,301 @@
+mod harness;
+
+use free_voip_core::{
+    contacts::{self, ContactTicket, ContactsMessage, ContactsProtocol},
+    FreeVoipError, SecretKey,
+};
+use harness::{recv, respond_with, spawn_peers, TIMEOUT};
+use iroh::{Endpoint, NodeAddr, RelayMode};
+use iroh_base::ticket::Ticket;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// An endpoint that only speaks the contacts protocol as it was before [`ContactsMessage`].
+async fn legacy_endpoint() -> Endpoint {
+    Endpoint::builder()
+        .clear_discovery()
+        .relay_mode(RelayMode::Disabled)
+        .alpns(vec![contacts::LEGACY_ALPN.to_vec()])
+        .bind()
+        .await
+        .unwrap()
+}
+
+fn local_addr(endpoint: &Endpoint) -> NodeAddr {
+    let socket = endpoint
+        .bound_sockets()
+        .into_iter()
+        .find(|s| s.is_ipv4())
+        .unwrap();
+    NodeAddr::new(endpoint.node_id()).with_direct_addresses([(
+        std::net::Ipv4Addr::LOCALHOST,
+        socket.port(),
+    )
+        .into()])
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contact_request_accepted() {
+    let [alice, mut bob] = spawn_peers().await;
+
+    let request = ContactsProtocol::send_request(alice.endpoint(), bob.addr(), &alice.ticket);
+    let gui = async {
+        let ticket = recv(&mut bob.contact_requests).await;
+        bob.contact_responses.send(true).unwrap();
+        ticket
+    };
+    let (accepted, ticket) = tokio::join!(request, gui);
+
+    assert_eq!(accepted, Ok(true));
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+    assert_eq!(ticket.nickname, alice.ticket.nickname);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contact_request_declined() {
+    let [alice, bob] = spawn_peers().await;
+    respond_with(
+        bob.contact_requests.resubscribe(),
+        bob.contact_responses.clone(),
+        false,
+    );
+
+    let accepted =
+        ContactsProtocol::send_request(alice.endpoint(), bob.addr(), &alice.ticket).await;
+    assert_eq!(accepted, Ok(false));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ticket_addresses_reach_peer_without_discovery() {
+    let [alice, bob] = spawn_peers().await;
+    respond_with(
+        bob.contact_requests.resubscribe(),
+        bob.contact_responses.clone(),
+        true,
+    );
+    let bob_ticket = ContactTicket {
+        addr: Some(bob.addr()),
+        ..bob.ticket.clone()
+    };
+
+    // The test peers have no discovery, so only the addresses in the ticket can reach Bob
+    let ticket = <ContactTicket as Ticket>::deserialize(&Ticket::serialize(&bob_ticket)).unwrap();
+    assert_eq!(ticket.node_addr(), bob.addr());
+    let accepted =
+        ContactsProtocol::send_request(alice.endpoint(), ticket.node_addr(), &alice.ticket).await;
+    assert_eq!(accepted, Ok(true));
+}
+
+#[test]
+fn ticket_without_addresses_still_parses() {
+    let node_id = SecretKey::from_bytes(&[7; 32]).public();
+    let legacy = postcard::to_stdvec(&("Carol".to_owned(), node_id)).unwrap();
+
+    let ticket = <ContactTicket as Ticket>::from_bytes(&legacy).unwrap();
+    assert_eq!(ticket.nickname, "Carol");
+    assert_eq!(ticket.node_id, node_id);
+    assert!(ticket.addr.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invited_request_accepted_without_asking() {
+    let [alice, mut bob] = spawn_peers().await;
+    let invite = bob.invite(Duration::from_secs(60), None);
+
+    let accepted =
+        ContactsProtocol::send_invited_request(alice.endpoint(), &alice.ticket, &invite).await;
+    assert_eq!(accepted, Ok(true));
+    let ticket = recv(&mut bob.invited_requests).await;
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+    assert!(bob.contact_requests.is_empty());
+    assert_eq!(bob.invites.borrow()[0].uses, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn used_expired_revoked_or_forged_invites_need_approval() {
+    let [alice, mut bob] = spawn_peers().await;
+    respond_with(
+        bob.contact_requests.resubscribe(),
+        bob.contact_responses.clone(),
+        false,
+    );
+
+    let used = bob.invite(Duration::from_secs(60), None);
+    ContactsProtocol::send_invited_request(alice.endpoint(), &alice.ticket, &used)
+        .await
+        .unwrap();
+    recv(&mut bob.invited_requests).await;
+
+    let expired = bob.invite(Duration::ZERO, None);
+    let revoked = bob.invite(Duration::from_secs(60), Some(5));
+    bob.invites
+        .send_modify(|invites| invites.retain(|i| i.id != revoked.id()));
+    let mut forged = bob.invite(Duration::from_secs(60), None);
+    forged.expires_at += 3600;
+
+    for invite in [used, expired, revoked, forged] {
+        let accepted =
+            ContactsProtocol::send_invited_request(alice.endpoint(), &alice.ticket, &invite).await;
+        assert_eq!(accepted, Ok(false));
+    }
+    assert!(bob.invited_requests.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contact_request_to_offline_peer_fails() {
+    let [alice, bob] = spawn_peers().await;
+    let bob_addr = bob.addr();
+    bob.router.shutdown().await.unwrap();
+
+    let result = ContactsProtocol::send_request(alice.endpoint(), bob_addr, &alice.ticket).await;
+    assert!(matches!(result, Err(FreeVoipError::PeerUnreachable(_))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missed_call_notice_delivered() {
+    let [alice, mut bob] = spawn_peers().await;
+
+    let message = ContactsMessage::MissedCall {
+        caller: alice.ticket.clone(),
+        timestamp: 1234,
+    };
+    let accepted =
+        ContactsProtocol::send_message(alice.endpoint(), bob.addr(), &message, || {}).await;
+    assert_eq!(accepted, Ok(true));
+
+    let (caller, timestamp) = recv(&mut bob.missed_calls).await;
+    assert_eq!(caller.node_id, alice.ticket.node_id);
+    assert_eq!(timestamp, 1234);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missed_call_notice_for_someone_else_rejected() {
+    let [alice, mut bob, carol] = spawn_peers().await;
+
+    // Alice claims that Carol called
+    let message = ContactsMessage::MissedCall {
+        caller: carol.ticket.clone(),
+        timestamp: 1234,
+    };
+    let result =
+        ContactsProtocol::send_message(alice.endpoint(), bob.addr(), &message, || {}).await;
+    assert!(result.is_err());
+    assert!(bob.missed_calls.try_recv().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn profile_update_delivered() {
+    let [mut alice, mut bob] = spawn_peers().await;
+
+    alice.ticket.nickname = "Alice Smith".to_owned();
+    let avatar = blake3::hash(b"avatar").to_hex().to_string();
+    let message = ContactsMessage::ProfileUpdate {
+        contact: alice.ticket.clone(),
+        avatar: Some(avatar.clone()),
+    };
+    let accepted =
+        ContactsProtocol::send_message(alice.endpoint(), bob.addr(), &message, || {}).await;
+    assert_eq!(accepted, Ok(true));
+
+    let (ticket, received_avatar) = recv(&mut bob.profile_updates).await;
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+    assert_eq!(ticket.nickname, "Alice Smith");
+    assert_eq!(received_avatar, Some(avatar));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn profile_update_for_someone_else_rejected() {
+    let [alice, mut bob, mut carol] = spawn_peers().await;
+
+    // Alice tries to rename Carol
+    carol.ticket.nickname = "Mallory".to_owned();
+    let message = ContactsMessage::ProfileUpdate {
+        contact: carol.ticket.clone(),
+        avatar: None,
+    };
+    let result =
+        ContactsProtocol::send_message(alice.endpoint(), bob.addr(), &message, || {}).await;
+    assert!(result.is_err());
+    assert!(bob.profile_updates.try_recv().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contact_request_reaches_legacy_peer() {
+    let [alice] = spawn_peers().await;
+    let legacy = legacy_endpoint().await;
+    let legacy_addr = local_addr(&legacy);
+
+    let (ticket_tx, mut tickets) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(incoming) = legacy.accept().await {
+            let Ok(connection) = incoming.await else {
+                continue;
+            };
+            let Ok((mut tx, mut rx)) = connection.accept_bi().await else {
+                continue;
+            };
+            let mut serialized_ticket = String::new();
+            rx.read_to_string(&mut serialized_ticket).await.unwrap();
+            tx.write_u8(1).await.unwrap();
+            tx.finish().unwrap();
+            connection.closed().await;
+            _ = ticket_tx.send(serialized_ticket);
+        }
+    });
+
+    let accepted = tokio::time::timeout(
+        TIMEOUT,
+        ContactsProtocol::send_request(alice.endpoint(), legacy_addr.clone(), &alice.ticket),
+    )
+    .await
+    .unwrap();
+    let ticket = <ContactTicket as Ticket>::deserialize(&tickets.recv().await.unwrap()).unwrap();
+    assert_eq!(accepted, Ok(true));
+    assert_eq!(ticket.node_id, alice.ticket.node_id);
+    assert_eq!(ticket.nickname, alice.ticket.nickname);
+
+    // Notices are dropped rather than retried forever
+    let message = ContactsMessage::MissedCall {
+        caller: alice.ticket.clone(),
+        timestamp: 1234,
+    };
+    let accepted = tokio::time::timeout(
+        TIMEOUT,
+        ContactsProtocol::send_message(alice.endpoint(), legacy_addr, &message, || {}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(accepted, Ok(false));
+    assert!(tickets.try_recv().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn contact_request_from_legacy_peer() {
+    let [mut bob] = spawn_peers().await;
+    let legacy = legacy_endpoint().await;
+    let legacy_ticket = ContactTicket::new("Old".to_owned(), legacy.node_id());
+    let bob_addr = bob.addr();
+
+    let request = async {
+        let connection = legacy
+            .connect(bob_addr, contacts::LEGACY_ALPN)
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = connection.open_bi().await.unwrap();
+        tx.write_all(Ticket::serialize(&legacy_ticket).as_bytes())
+            .await
+            .unwrap();
+        tx.finish().unwrap();
+        rx.read_u8().await.unwrap()
+    };
+    let gui = async {
+        let ticket = recv(&mut bob.contact_requests).await;
+        bob.contact_responses.send(true).unwrap();
+        ticket
+    };
+    let (response, ticket) = tokio::join!(request, gui);
+
+    assert_eq!(response, 1);
+    assert_eq!(ticket.node_id, legacy.node_id());
+    assert_eq!(ticket.nickname, "Old");
+}
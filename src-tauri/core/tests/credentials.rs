@@ -0,0 +1,247 @@
+mod harness;
+
+use std::{sync::Arc, time::Duration};
+
+use free_voip_core::{
+    backup::BackupSource,
+    contacts::ContactTicket,
+    outbox::OutboxPayload,
+    storage::{MemoryStorage, Storage, OUTBOX_STORE},
+    Event, FreeVoipError, SecretKey,
+};
+
+#[tokio::test]
+async fn stored_identity_is_locked_until_unlocked() {
+    let storage = Arc::new(MemoryStorage::default());
+    let node = harness::node_with_storage(storage.clone()).await;
+    node.login("Alice".to_owned(), "correct horse", None)
+        .await
+        .unwrap();
+    let ticket = node.self_ticket().await.unwrap();
+    node.logout(false).await.unwrap();
+
+    // A fresh start can't get at the identity without the passphrase either
+    let node = harness::node_with_storage(storage).await;
+    assert_eq!(node.restore_login().await, Err(FreeVoipError::Locked));
+    assert!(matches!(
+        node.self_ticket().await,
+        Err(FreeVoipError::NotLoggedIn)
+    ));
+    assert_eq!(
+        node.unlock("battery staple").await,
+        Err(FreeVoipError::WrongPassphrase)
+    );
+
+    node.unlock("correct horse").await.unwrap();
+    assert_eq!(node.restore_login().await, Ok(true));
+    assert_eq!(node.self_ticket().await.unwrap().node_id, ticket.node_id);
+    node.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn change_passphrase_requires_current_one() {
+    let node = harness::node().await;
+    node.login("Bob".to_owned(), "old", None).await.unwrap();
+
+    assert_eq!(
+        node.change_passphrase("wrong", "new").await,
+        Err(FreeVoipError::WrongPassphrase)
+    );
+    node.change_passphrase("old", "new").await.unwrap();
+    node.logout(false).await.unwrap();
+
+    assert_eq!(
+        node.unlock("old").await,
+        Err(FreeVoipError::WrongPassphrase)
+    );
+    node.unlock("new").await.unwrap();
+    node.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn updated_profile_survives_locking() {
+    let node = harness::node().await;
+    node.login("Frank".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    node.add_contact(ContactTicket::new(
+        "Grace".to_owned(),
+        SecretKey::from_bytes(&[5; 32]).public(),
+    ))
+    .unwrap();
+
+    node.update_profile(" Frankie ".to_owned()).await.unwrap();
+    node.set_avatar(Some(b"avatar".to_vec())).await.unwrap();
+    assert_eq!(node.self_ticket().await.unwrap().nickname, "Frankie");
+    // Grace is told about it once she is reachable
+    assert!(node
+        .outbox(None)
+        .iter()
+        .any(|i| matches!(i.payload, OutboxPayload::ProfileUpdate)));
+
+    node.logout(false).await.unwrap();
+    node.unlock("passphrase").await.unwrap();
+    assert_eq!(node.self_ticket().await.unwrap().nickname, "Frankie");
+    assert_eq!(node.avatar(None).await, Ok(Some(b"avatar".to_vec())));
+    node.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn restores_identity_from_mnemonic() {
+    let node = harness::node().await;
+    node.login("Carol".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    let node_id = node.self_ticket().await.unwrap().node_id;
+    let phrase = node.export_mnemonic().await.unwrap();
+    assert_eq!(phrase.split_whitespace().count(), 24);
+    node.logout(false).await.unwrap();
+
+    let reinstalled = harness::node().await;
+    assert_eq!(
+        reinstalled
+            .import_identity(
+                BackupSource::Mnemonic {
+                    phrase: "not a recovery phrase".to_owned(),
+                    nickname: "Carol".to_owned(),
+                },
+                "passphrase",
+            )
+            .await,
+        Err(FreeVoipError::InvalidBackup)
+    );
+    reinstalled
+        .import_identity(
+            BackupSource::Mnemonic {
+                phrase,
+                nickname: "Carol".to_owned(),
+            },
+            "another passphrase",
+        )
+        .await
+        .unwrap();
+    assert_eq!(reinstalled.self_ticket().await.unwrap().node_id, node_id);
+    reinstalled.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn restores_identity_and_contacts_from_backup_file() {
+    let node = harness::node().await;
+    node.login("Dave".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    let contact = ContactTicket::new("Erin".to_owned(), SecretKey::from_bytes(&[4; 32]).public());
+    node.add_contact(contact.clone()).unwrap();
+    let node_id = node.self_ticket().await.unwrap().node_id;
+
+    let path = node.export_identity_file("backup").await.unwrap();
+    let contents = tokio::fs::read_to_string(&path).await.unwrap();
+    _ = tokio::fs::remove_file(&path).await;
+    node.logout(false).await.unwrap();
+
+    let reinstalled = harness::node().await;
+    assert_eq!(
+        reinstalled
+            .import_identity(
+                BackupSource::File {
+                    contents: contents.clone(),
+                    passphrase: "wrong".to_owned(),
+                },
+                "passphrase",
+            )
+            .await,
+        Err(FreeVoipError::WrongPassphrase)
+    );
+    reinstalled
+        .import_identity(
+            BackupSource::File {
+                contents,
+                passphrase: "backup".to_owned(),
+            },
+            "passphrase",
+        )
+        .await
+        .unwrap();
+
+    let self_ticket = reinstalled.self_ticket().await.unwrap();
+    assert_eq!(self_ticket.node_id, node_id);
+    assert_eq!(self_ticket.nickname, "Dave");
+    let contacts = reinstalled.contacts().unwrap();
+    assert_eq!(contacts.len(), 1);
+    assert_eq!(contacts[0].node_id, contact.node_id);
+    reinstalled.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn outbox_stays_with_its_identity() {
+    let alice = SecretKey::from_bytes(&[7; 32]);
+    let grace = ContactTicket::new("Grace".to_owned(), SecretKey::from_bytes(&[8; 32]).public());
+    let node = harness::node().await;
+    node.login("Alice".to_owned(), "passphrase", Some(alice.clone()))
+        .await
+        .unwrap();
+    node.add_contact(grace.clone()).unwrap();
+    node.update_profile("Alicia".to_owned()).await.unwrap();
+    assert_eq!(node.outbox(Some(grace.node_id)).len(), 1);
+    node.logout(false).await.unwrap();
+    assert!(node.outbox(None).is_empty());
+
+    // Another identity on the same device must not send Alice's queued items
+    let mut events = node.events();
+    node.login("Bob".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(node.outbox(None).is_empty());
+    while let Ok(event) = events.try_recv() {
+        if let Event::OutboxUpdated(items) = event {
+            assert!(items.is_empty());
+        }
+    }
+    node.logout(false).await.unwrap();
+
+    // They are still there when Alice is back, until the identity is wiped
+    node.login("Alice".to_owned(), "passphrase", Some(alice.clone()))
+        .await
+        .unwrap();
+    assert_eq!(node.outbox(Some(grace.node_id)).len(), 1);
+    node.logout(true).await.unwrap();
+    node.login("Alice".to_owned(), "passphrase", Some(alice))
+        .await
+        .unwrap();
+    assert!(node.outbox(None).is_empty());
+    node.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn login_without_logout_keeps_previous_outbox() {
+    let alice = SecretKey::from_bytes(&[9; 32]);
+    let grace = ContactTicket::new(
+        "Grace".to_owned(),
+        SecretKey::from_bytes(&[10; 32]).public(),
+    );
+    let storage = Arc::new(MemoryStorage::default());
+    let node = harness::node_with_storage(storage.clone()).await;
+    node.login("Alice".to_owned(), "passphrase", Some(alice.clone()))
+        .await
+        .unwrap();
+    node.add_contact(grace.clone()).unwrap();
+    node.update_profile("Alicia".to_owned()).await.unwrap();
+
+    // Logging in as someone else right away stores Alice's items instead of dropping them
+    node.login("Bob".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    assert!(node.outbox(None).is_empty());
+    assert!(storage
+        .entries(OUTBOX_STORE)
+        .unwrap()
+        .iter()
+        .any(|(key, _)| key == &format!("{}/{}", alice.public(), grace.node_id)));
+
+    node.login("Alice".to_owned(), "passphrase", Some(alice))
+        .await
+        .unwrap();
+    assert_eq!(node.outbox(Some(grace.node_id)).len(), 1);
+    node.logout(false).await.unwrap();
+}
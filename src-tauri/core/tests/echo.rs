@@ -0,0 +1,92 @@
+mod harness;
+
+use std::time::Duration;
+
+use free_voip_core::{
+    contacts::{ContactsMessage, ContactsProtocol},
+    echo::{self, EchoMonitor, LOSS_TIMEOUT},
+};
+use harness::{audio_frame, frame_timestamp, recv, spawn_peers, video_frame};
+
+#[tokio::test(start_paused = true)]
+async fn monitor_measures_round_trips() {
+    let mut monitor = EchoMonitor::default();
+    let stats = monitor.stats();
+    assert_eq!(
+        (stats.sent, stats.loss_ratio, stats.avg_rtt_ms),
+        (0, 0.0, None)
+    );
+
+    // Audio and video with the same timestamp are told apart
+    monitor.on_sent(&audio_frame(0));
+    monitor.on_sent(&video_frame(0, 16));
+    tokio::time::advance(Duration::from_millis(20)).await;
+    monitor.on_echoed(&audio_frame(0));
+    tokio::time::advance(Duration::from_millis(40)).await;
+    monitor.on_echoed(&video_frame(0, 16));
+
+    // Echoes of frames we never sent don't count
+    monitor.on_echoed(&audio_frame(20_000));
+
+    let stats = monitor.stats();
+    assert_eq!((stats.sent, stats.echoed, stats.lost), (2, 2, 0));
+    assert_eq!(stats.last_rtt_ms, Some(60.0));
+    assert_eq!(stats.min_rtt_ms, Some(20.0));
+    assert_eq!(stats.avg_rtt_ms, Some(40.0));
+    assert_eq!(stats.max_rtt_ms, Some(60.0));
+}
+
+#[tokio::test(start_paused = true)]
+async fn monitor_counts_late_frames_as_lost() {
+    let mut monitor = EchoMonitor::default();
+    for timestamp in (0..4).map(|i| i * 20_000) {
+        monitor.on_sent(&audio_frame(timestamp));
+    }
+    monitor.on_echoed(&audio_frame(0));
+
+    // Frames still in flight are neither echoed nor lost
+    let stats = monitor.stats();
+    assert_eq!((stats.echoed, stats.lost, stats.loss_ratio), (1, 0, 0.0));
+
+    tokio::time::advance(LOSS_TIMEOUT).await;
+    let stats = monitor.stats();
+    assert_eq!((stats.echoed, stats.lost, stats.loss_ratio), (1, 3, 0.75));
+
+    // Echoes arriving after that are too late
+    monitor.on_echoed(&audio_frame(20_000));
+    assert_eq!(monitor.stats().echoed, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bot_echoes_calls() {
+    let [mut alice] = spawn_peers().await;
+    let bot = harness::node().await;
+    bot.login("Echo".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    let bot_addr = bot.self_ticket().await.unwrap().node_addr();
+    let serving = tokio::spawn({
+        let bot = bot.node.clone();
+        async move { echo::serve(&bot).await }
+    });
+
+    // Requests are accepted without filling up the bot's contacts
+    let request = ContactsMessage::Request(alice.ticket.clone());
+    assert_eq!(
+        ContactsProtocol::send_message(alice.endpoint(), bot_addr.clone(), &request, || {}).await,
+        Ok(true)
+    );
+    assert!(bot.contacts().unwrap().is_empty());
+
+    assert_eq!(alice.ring(bot_addr).await, Ok(true));
+    for timestamp in (0..20).map(|i| i * 21_333) {
+        alice.media_out.send(audio_frame(timestamp)).unwrap();
+        let echoed = recv(&mut alice.media_in).await;
+        assert_eq!(frame_timestamp(&echoed), timestamp);
+    }
+
+    assert!(alice.call.disconnect().await);
+    recv(&mut alice.hang_ups).await;
+    assert!(!serving.is_finished());
+    serving.abort();
+}
@@ -0,0 +1,179 @@
+mod harness;
+
+use std::path::PathBuf;
+
+use free_voip_core::{files::TransferState, FreeVoipError};
+use harness::{recv, respond_with, spawn_peers, TestPeer};
+
+/// Writes a file of `size` bytes for `peer` to send, returning its path and contents.
+async fn file_to_send(peer: &TestPeer, name: &str, size: usize) -> (PathBuf, Vec<u8>) {
+    let dir = std::env::temp_dir().join(format!("free-voip-uploads-{}", peer.ticket.node_id));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let contents = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let path = dir.join(name);
+    tokio::fs::write(&path, &contents).await.unwrap();
+    (path, contents)
+}
+
+/// Leaves a partial download of `contents` from `sender` at `peer`, as an interrupted transfer
+/// would.
+async fn partial_download(
+    peer: &TestPeer,
+    sender: &TestPeer,
+    contents: &[u8],
+    partial: &[u8],
+) -> PathBuf {
+    tokio::fs::create_dir_all(peer.download_dir.path())
+        .await
+        .unwrap();
+    let part_path = peer.download_dir.path().join(format!(
+        "{}-{}.part",
+        sender.ticket.node_id,
+        blake3::hash(contents).to_hex()
+    ));
+    tokio::fs::write(&part_path, partial).await.unwrap();
+    part_path
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_sent_to_contact() {
+    let [alice, mut bob] = spawn_peers().await;
+    bob.contacts.send_modify(|c| {
+        c.insert(alice.ticket.node_id);
+    });
+    respond_with(
+        bob.file_offers.resubscribe(),
+        bob.file_responses.clone(),
+        true,
+    );
+    let (path, contents) = file_to_send(&alice, "hello.bin", 300_000).await;
+
+    let sent = alice
+        .files
+        .send_file(alice.endpoint(), bob.addr(), &path)
+        .await;
+    assert_eq!(sent, Ok(true));
+
+    let received = tokio::fs::read(bob.download_dir.path().join("hello.bin")).await;
+    assert_eq!(received.unwrap(), contents);
+    let progress = recv(&mut bob.file_progress).await;
+    assert_eq!(progress.state, TransferState::Completed);
+    assert_eq!(progress.peer, alice.ticket.node_id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_from_stranger_refused() {
+    let [alice, mut bob] = spawn_peers().await;
+    let (path, _) = file_to_send(&alice, "spam.bin", 1000).await;
+
+    let sent = alice
+        .files
+        .send_file(alice.endpoint(), bob.addr(), &path)
+        .await;
+    assert!(sent.is_err());
+    assert!(bob.file_offers.try_recv().is_err());
+    assert!(
+        !tokio::fs::try_exists(bob.download_dir.path().join("spam.bin"))
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_download_resumed_without_asking() {
+    let [alice, mut bob] = spawn_peers().await;
+    bob.contacts.send_modify(|c| {
+        c.insert(alice.ticket.node_id);
+    });
+    let (path, contents) = file_to_send(&alice, "resumed.bin", 200_000).await;
+    let part_path = partial_download(&bob, &alice, &contents, &contents[..120_000]).await;
+
+    let sent = alice
+        .files
+        .send_file(alice.endpoint(), bob.addr(), &path)
+        .await;
+    assert_eq!(sent, Ok(true));
+
+    assert!(bob.file_offers.try_recv().is_err());
+    let received = tokio::fs::read(bob.download_dir.path().join("resumed.bin")).await;
+    assert_eq!(received.unwrap(), contents);
+    assert!(!tokio::fs::try_exists(part_path).await.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupted_download_fails_hash_check() {
+    let [alice, bob] = spawn_peers().await;
+    bob.contacts.send_modify(|c| {
+        c.insert(alice.ticket.node_id);
+    });
+    let (path, contents) = file_to_send(&alice, "corrupted.bin", 50_000).await;
+    let part_path = partial_download(&bob, &alice, &contents, &[0xff; 10_000]).await;
+
+    let sent = alice
+        .files
+        .send_file(alice.endpoint(), bob.addr(), &path)
+        .await;
+    assert!(matches!(sent, Err(FreeVoipError::ProtocolViolation(_))));
+
+    // The broken partial download is dropped so that the next attempt starts over
+    assert!(!tokio::fs::try_exists(part_path).await.unwrap());
+    assert!(
+        !tokio::fs::try_exists(bob.download_dir.path().join("corrupted.bin"))
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_download_from_someone_else_asked_again() {
+    let [alice, mut bob, carol] = spawn_peers().await;
+    bob.contacts.send_modify(|c| {
+        c.insert(carol.ticket.node_id);
+    });
+    respond_with(
+        bob.file_offers.resubscribe(),
+        bob.file_responses.clone(),
+        false,
+    );
+    let (_, contents) = file_to_send(&alice, "shared.bin", 20_000).await;
+    partial_download(&bob, &alice, &contents, &contents[..10_000]).await;
+    let (path, _) = file_to_send(&carol, "shared.bin", 20_000).await;
+
+    let sent = carol
+        .files
+        .send_file(carol.endpoint(), bob.addr(), &path)
+        .await;
+    assert_eq!(sent, Ok(false));
+    let (peer, _) = recv(&mut bob.file_offers).await;
+    assert_eq!(peer, carol.ticket.node_id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abandoned_offer_does_not_block_the_next() {
+    let [alice, mut bob] = spawn_peers().await;
+    bob.contacts.send_modify(|c| {
+        c.insert(alice.ticket.node_id);
+    });
+    let (path, _) = file_to_send(&alice, "first.bin", 1000).await;
+
+    // Nobody answers, so Alice gives up
+    let sending = {
+        let alice_files = alice.files.clone();
+        let endpoint = alice.endpoint().clone();
+        let addr = bob.addr();
+        tokio::spawn(async move { alice_files.send_file(&endpoint, addr, &path).await })
+    };
+    assert_eq!(recv(&mut bob.file_offers).await.1.name, "first.bin");
+    sending.abort();
+
+    let (path, _) = file_to_send(&alice, "second.bin", 1000).await;
+    let sending = alice.files.send_file(alice.endpoint(), bob.addr(), &path);
+    let answering = async {
+        let (_, offer) = recv(&mut bob.file_offers).await;
+        bob.file_responses.send(true).unwrap();
+        offer
+    };
+    let (sent, offer) = tokio::join!(sending, answering);
+    assert_eq!(offer.name, "second.bin");
+    assert_eq!(sent, Ok(true));
+}
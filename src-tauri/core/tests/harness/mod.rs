@@ -0,0 +1,444 @@
+//! In-process peers for integration tests: iroh endpoints bound to localhost with discovery and
+//! relays turned off, reaching each other through direct `NodeAddr`s.
+//!
+//! Each peer keeps the other ends of its protocols' broadcast channels, so tests can play the GUI
+//! by answering requests themselves or with [`respond_with`].
+
+#![allow(dead_code)]
+
+pub mod proxy;
+
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use free_voip_core::{
+    call::{self, CallControl, CallMedia, CallProtocol},
+    contacts::{self, ContactTicket, ContactsProtocol},
+    files::{self, FileOffer, FilesProtocol, TransferProgress},
+    history::CallRecord,
+    invite::{Invite, InviteBook, InviteTicket},
+    network::{DiscoveryMode, NetworkSettings, RelayMode as RelaySetting},
+    presence::{self, Presence, PresenceProtocol},
+    profile::{self, AvatarStore, ProfileProtocol},
+    storage::{MemoryStorage, Storage, SETTINGS_STORE},
+    voicemail::{self, VoicemailInfo, VoicemailProtocol},
+    FreeVoipError, Node, NodeConfig,
+};
+use iroh::{protocol::Router, Endpoint, NodeAddr, NodeId, RelayMap, RelayMode, RelayUrl};
+use iroh_relay::server::{AccessConfig, Limits, RelayConfig, Server, ServerConfig};
+use proxy::UdpProxy;
+use tokio::sync::{
+    broadcast::{channel, error::RecvError, Receiver, Sender},
+    mpsc, watch,
+};
+
+/// How long tests wait for something that should happen promptly.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct TestPeer {
+    pub ticket: ContactTicket,
+    pub router: Router,
+    pub call: CallProtocol,
+
+    pub contact_requests: Receiver<ContactTicket>,
+    pub contact_responses: Sender<bool>,
+    pub missed_calls: Receiver<(ContactTicket, u64)>,
+    pub profile_updates: Receiver<(ContactTicket, Option<String>)>,
+    /// Invites this peer issued, see [`TestPeer::invite`].
+    pub invites: watch::Sender<Vec<Invite>>,
+    /// Contacts accepted because of a valid invite.
+    pub invited_requests: Receiver<ContactTicket>,
+
+    pub rings: Receiver<ContactTicket>,
+    pub ring_responses: Sender<bool>,
+    /// Media received from the peer of the ongoing call.
+    pub media_in: Receiver<CallMedia>,
+    /// Media to send to the peer of the ongoing call.
+    pub media_out: Sender<CallMedia>,
+    pub hang_ups: Receiver<()>,
+    pub history: Receiver<CallRecord>,
+    pub controls: Receiver<CallControl>,
+    /// Avatars of this peer and the ones it fetched, kept in `avatar_dir`.
+    pub avatars: AvatarStore,
+    avatar_dir: TempDir,
+    /// Hash of the avatar this peer hands out.
+    pub own_avatar: watch::Sender<Option<String>>,
+    /// Presence this peer answers probes with.
+    pub presence: watch::Sender<Presence>,
+    /// Peers allowed to probe this peer's presence, nobody at first.
+    pub presence_audience: watch::Sender<HashSet<NodeId>>,
+
+    pub files: FilesProtocol,
+    pub file_offers: Receiver<(NodeId, FileOffer)>,
+    pub file_responses: Sender<bool>,
+    pub file_progress: Receiver<TransferProgress>,
+    /// Where received files end up.
+    pub download_dir: TempDir,
+    /// Peers allowed to send files and voicemails to this peer, nobody at first.
+    pub contacts: watch::Sender<HashSet<NodeId>>,
+
+    pub voicemails: Receiver<VoicemailInfo>,
+    voicemail_dir: TempDir,
+}
+
+impl TestPeer {
+    pub async fn spawn(nickname: &str) -> Self {
+        Self::spawn_with(nickname, RelayMode::Disabled).await
+    }
+
+    /// Spawns a peer using the relay at `relay_url`, once it is connected to it.
+    pub async fn spawn_on_relay(nickname: &str, relay_url: RelayUrl) -> Self {
+        let relay_mode = RelayMode::Custom(RelayMap::from_iter([relay_url]));
+        let peer = Self::spawn_with(nickname, relay_mode).await;
+        tokio::time::timeout(TIMEOUT, peer.endpoint().online())
+            .await
+            .expect("Timed out connecting to the relay");
+        peer
+    }
+
+    async fn spawn_with(nickname: &str, relay_mode: RelayMode) -> Self {
+        let endpoint = Endpoint::builder()
+            .clear_discovery()
+            .relay_mode(relay_mode)
+            .bind_addr_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .bind()
+            .await
+            .expect("Failed to bind endpoint");
+        let ticket = ContactTicket::new(nickname.to_owned(), endpoint.node_id());
+
+        let (request_tx, contact_requests) = channel(8);
+        let (contact_responses, response_rx) = channel(8);
+        let (missed_call_tx, missed_calls) = channel(8);
+        let (profile_update_tx, profile_updates) = channel(8);
+        let invites = watch::Sender::new(vec![]);
+        let (invited_tx, invited_requests) = channel(8);
+        let contacts = Arc::new(ContactsProtocol::new(
+            request_tx,
+            response_rx,
+            missed_call_tx,
+            profile_update_tx,
+            InviteBook::new(endpoint.node_id(), invites.clone()),
+            invited_tx,
+        ));
+
+        let (ring_tx, rings) = channel(2);
+        let (ring_responses, ring_response_rx) = channel(2);
+        let (in_media_tx, media_in) = channel(64);
+        let (media_out, out_media_rx) = channel(64);
+        let (hang_up_tx, hang_ups) = channel(8);
+        let (history_tx, history) = channel(8);
+        let (control_tx, controls) = channel(8);
+        let (extra_stream_tx, _) = mpsc::unbounded_channel();
+        let call = CallProtocol::new(
+            ring_tx,
+            ring_response_rx,
+            in_media_tx,
+            out_media_rx,
+            hang_up_tx,
+            extra_stream_tx,
+            history_tx,
+            control_tx,
+        );
+
+        let avatar_dir = TempDir::new();
+        let avatars = AvatarStore::new(avatar_dir.path().to_owned());
+        let (own_avatar, own_avatar_rx) = watch::channel(None);
+        let profile = ProfileProtocol::new(avatars.clone(), own_avatar_rx);
+
+        let (presence, presence_rx) = watch::channel(Presence::default());
+        let (presence_audience, presence_audience_rx) = watch::channel(HashSet::new());
+        let presence_protocol = PresenceProtocol::new(presence_rx, presence_audience_rx);
+
+        let (offer_tx, file_offers) = channel(8);
+        let (file_responses, file_response_rx) = channel(8);
+        let (progress_tx, file_progress) = channel(64);
+        let download_dir = TempDir::new();
+        let (contact_ids, contact_ids_rx) = watch::channel(HashSet::new());
+        let files = FilesProtocol::new(
+            offer_tx,
+            file_response_rx,
+            progress_tx,
+            download_dir.path().to_owned(),
+            contact_ids_rx.clone(),
+        );
+
+        let (voicemail_tx, voicemails) = channel(8);
+        let voicemail_dir = TempDir::new();
+        let voicemail_protocol = VoicemailProtocol::new(
+            voicemail_tx,
+            voicemail_dir.path().to_owned(),
+            contact_ids_rx,
+        );
+
+        let router = Router::builder(endpoint)
+            .accept(contacts::ALPN, contacts.clone())
+            .accept(contacts::LEGACY_ALPN, contacts)
+            .accept(call::ALPN, call.clone())
+            .accept(profile::ALPN, profile)
+            .accept(presence::ALPN, presence_protocol)
+            .accept(files::ALPN, files.clone())
+            .accept(voicemail::ALPN, voicemail_protocol)
+            .spawn();
+
+        Self {
+            ticket,
+            router,
+            call,
+            contact_requests,
+            contact_responses,
+            missed_calls,
+            profile_updates,
+            invites,
+            invited_requests,
+            rings,
+            ring_responses,
+            media_in,
+            media_out,
+            hang_ups,
+            history,
+            controls,
+            avatars,
+            avatar_dir,
+            own_avatar,
+            presence,
+            presence_audience,
+            files,
+            file_offers,
+            file_responses,
+            file_progress,
+            download_dir,
+            contacts: contact_ids,
+            voicemails,
+            voicemail_dir,
+        }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
+    /// The socket this peer is listening on.
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.endpoint()
+            .bound_sockets()
+            .into_iter()
+            .find(|s| s.is_ipv4())
+            .expect("Endpoint is not bound to IPv4")
+    }
+
+    /// Direct address of this peer on localhost.
+    pub fn addr(&self) -> NodeAddr {
+        NodeAddr::new(self.ticket.node_id).with_direct_addresses([self.socket_addr()])
+    }
+
+    /// Every address this peer can be reached at, its relay included.
+    pub fn full_addr(&self) -> NodeAddr {
+        self.endpoint().node_addr()
+    }
+
+    /// Address reaching this peer only through `proxy`.
+    pub fn addr_via(&self, proxy: &UdpProxy) -> NodeAddr {
+        NodeAddr::new(self.ticket.node_id).with_direct_addresses([proxy.addr])
+    }
+
+    /// Issues an invite to this peer, valid for `valid_for`.
+    pub fn invite(&self, valid_for: Duration, max_uses: Option<u32>) -> InviteTicket {
+        let expires_at = SystemTime::now()
+            .checked_add(valid_for)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let ticket = ContactTicket {
+            addr: Some(self.addr()),
+            ..self.ticket.clone()
+        };
+        let invite =
+            InviteTicket::issue(self.endpoint().secret_key(), ticket, expires_at, max_uses);
+        self.invites.send_modify(|i| i.push(Invite::new(&invite)));
+        invite
+    }
+
+    pub async fn ring(&self, addr: NodeAddr) -> Result<bool, FreeVoipError> {
+        self.call.ring(self.endpoint(), addr, &self.ticket).await
+    }
+}
+
+/// Spawns `N` peers named `peer-0`, `peer-1` and so on.
+pub async fn spawn_peers<const N: usize>() -> [TestPeer; N] {
+    let mut peers = vec![];
+    for i in 0..N {
+        peers.push(TestPeer::spawn(&format!("peer-{i}")).await);
+    }
+    match peers.try_into() {
+        Ok(peers) => peers,
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Runs a relay on localhost, over plain HTTP so that nodes accept it without any certificate.
+/// The relay stops once the returned server is dropped.
+pub async fn relay_server() -> (Server, RelayUrl) {
+    let server = Server::spawn(ServerConfig::<(), ()> {
+        relay: Some(RelayConfig {
+            http_bind_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            tls: None,
+            limits: Limits::default(),
+            key_cache_capacity: Some(1024),
+            access: AccessConfig::Everyone,
+        }),
+        quic: None,
+        metrics_addr: None,
+    })
+    .await
+    .expect("Failed to spawn relay");
+    let addr = server.http_addr().expect("Relay has no HTTP server");
+    let url = format!("http://{addr}").parse().unwrap();
+    (server, url)
+}
+
+/// A fresh directory under the system's temporary directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "free-voip-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A [`Node`] that is not logged in, with its directories in a [`TempDir`] of its own.
+pub struct TestNode {
+    pub node: Node,
+    pub dir: TempDir,
+}
+
+impl Deref for TestNode {
+    type Target = Node;
+
+    fn deref(&self) -> &Node {
+        &self.node
+    }
+}
+
+/// Network settings keeping a node off the internet and the local network: no relays, no
+/// discovery and no mDNS, so it is only reached through the direct addresses tests hand out.
+pub fn offline_settings() -> NetworkSettings {
+    NetworkSettings {
+        relay_mode: RelaySetting::Disabled,
+        relay_urls: vec![],
+        discovery: DiscoveryMode::Disabled,
+        local_discovery: false,
+        hide_ip: false,
+    }
+}
+
+/// Creates a node with empty in-memory storage and [`offline_settings`].
+pub async fn node() -> TestNode {
+    node_with_settings(&offline_settings()).await
+}
+
+/// Creates a node with empty in-memory storage and `settings`.
+pub async fn node_with_settings(settings: &NetworkSettings) -> TestNode {
+    let storage = Arc::new(MemoryStorage::default());
+    let settings = serde_json::to_value(settings).unwrap();
+    storage
+        .set(SETTINGS_STORE, "networkSettings", settings)
+        .unwrap();
+    node_with_storage(storage).await
+}
+
+/// Creates a node from what is in `storage`, as if the app was started again. Without network
+/// settings in `storage`, it starts with [`offline_settings`].
+pub async fn node_with_storage(storage: Arc<dyn Storage>) -> TestNode {
+    if storage
+        .get(SETTINGS_STORE, "networkSettings")
+        .unwrap()
+        .is_none()
+    {
+        let settings = serde_json::to_value(offline_settings()).unwrap();
+        storage
+            .set(SETTINGS_STORE, "networkSettings", settings)
+            .unwrap();
+    }
+    let dir = TempDir::new();
+    let config = NodeConfig {
+        download_dir: dir.path().join("downloads"),
+        recordings_dir: dir.path().join("recordings"),
+        data_dir: dir.path().to_owned(),
+    };
+    let node = Node::new(storage, config).await.unwrap();
+    TestNode { node, dir }
+}
+
+/// Plays the GUI, answering every request arriving on `requests` with `response`.
+pub fn respond_with<T: Clone + Send + 'static>(
+    mut requests: Receiver<T>,
+    responses: Sender<bool>,
+    response: bool,
+) {
+    tokio::spawn(async move {
+        while let Ok(_) | Err(RecvError::Lagged(_)) = requests.recv().await {
+            if responses.send(response).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Receives the next message, failing the test if it takes longer than [`TIMEOUT`].
+pub async fn recv<T: Clone>(rx: &mut Receiver<T>) -> T {
+    tokio::time::timeout(TIMEOUT, rx.recv())
+        .await
+        .expect("Timed out waiting for message")
+        .expect("Channel closed")
+}
+
+pub fn audio_frame(timestamp: u64) -> CallMedia {
+    let frame_data = timestamp.to_be_bytes().to_vec();
+    CallMedia::Audio {
+        frame_type: "key".to_owned(),
+        timestamp,
+        duration: Some(20_000),
+        byte_length: frame_data.len() as u64,
+        frame_data,
+    }
+}
+
+pub fn video_frame(timestamp: u64, size: usize) -> CallMedia {
+    CallMedia::Video {
+        frame_type: if timestamp == 0 { "key" } else { "delta" }.to_owned(),
+        timestamp,
+        duration: Some(33_333),
+        byte_length: size as u64,
+        frame_data: vec![timestamp as u8; size],
+    }
+}
+
+pub fn frame_timestamp(media: &CallMedia) -> u64 {
+    match media {
+        CallMedia::Audio { timestamp, .. } | CallMedia::Video { timestamp, .. } => *timestamp,
+    }
+}
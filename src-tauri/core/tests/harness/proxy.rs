@@ -0,0 +1,175 @@
+//! UDP proxy that impairs the traffic it forwards, to put a bad network between two local peers.
+//!
+//! Peers dial the proxy's address instead of the target's. Since relays and discovery are off, the
+//! proxy is the only path between them.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{net::UdpSocket, task::JoinHandle, time::Instant};
+
+/// Packets that would wait longer than this for bandwidth are dropped, like a full router queue.
+const MAX_QUEUE_DELAY: Duration = Duration::from_millis(500);
+
+/// Network conditions, applied to each direction separately.
+#[derive(Debug, Clone, Default)]
+pub struct Impairment {
+    /// Share of packets dropped, from 0 to 1.
+    pub loss: f64,
+    pub delay: Duration,
+    /// Extra delay of up to this much per packet, which also reorders packets.
+    pub jitter: Duration,
+    /// Bytes per second, unlimited if `None`.
+    pub bandwidth: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    /// From the dialing peer to the target.
+    Forward = 0,
+    Backward = 1,
+}
+
+/// Deterministic xorshift generator, so that scripted conditions behave the same every run.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug)]
+struct Link {
+    impairment: Impairment,
+    rng: Rng,
+    /// When each direction is done sending the packets queued so far.
+    busy_until: [Instant; 2],
+}
+
+impl Link {
+    /// When a packet of `len` bytes should come out the other end, or `None` if it is dropped.
+    fn schedule(&mut self, direction: Direction, len: usize) -> Option<Instant> {
+        if self.rng.next_f64() < self.impairment.loss {
+            return None;
+        }
+
+        let now = Instant::now();
+        let departure = match self.impairment.bandwidth {
+            Some(bandwidth) => {
+                let busy_until = &mut self.busy_until[direction as usize];
+                let start = (*busy_until).max(now);
+                if start - now > MAX_QUEUE_DELAY {
+                    return None;
+                }
+                *busy_until = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+                *busy_until
+            }
+            None => now,
+        };
+        let jitter = self.impairment.jitter.mul_f64(self.rng.next_f64());
+
+        Some(departure + self.impairment.delay + jitter)
+    }
+}
+
+pub struct UdpProxy {
+    /// Address to dial instead of the target's.
+    pub addr: SocketAddr,
+    link: Arc<Mutex<Link>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl UdpProxy {
+    pub async fn start(target: SocketAddr, impairment: Impairment) -> Self {
+        let front = Arc::new(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap());
+        let back = Arc::new(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap());
+        back.connect(target).await.unwrap();
+
+        let link = Arc::new(Mutex::new(Link {
+            impairment,
+            rng: Rng(0x2545_f491_4f6c_dd1d),
+            busy_until: [Instant::now(); 2],
+        }));
+        let client = Arc::new(Mutex::new(None::<SocketAddr>));
+
+        let forward = {
+            let (front, back, link, client) =
+                (front.clone(), back.clone(), link.clone(), client.clone());
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                while let Ok((len, from)) = front.recv_from(&mut buf).await {
+                    *client.lock().unwrap() = Some(from);
+
+                    let Some(at) = link.lock().unwrap().schedule(Direction::Forward, len) else {
+                        continue;
+                    };
+                    let (back, packet) = (back.clone(), buf[..len].to_vec());
+                    tokio::spawn(async move {
+                        tokio::time::sleep_until(at).await;
+                        _ = back.send(&packet).await;
+                    });
+                }
+            })
+        };
+
+        let backward = {
+            let (front, back, link) = (front.clone(), back.clone(), link.clone());
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                while let Ok(len) = back.recv(&mut buf).await {
+                    let Some(client) = *client.lock().unwrap() else {
+                        continue;
+                    };
+
+                    let Some(at) = link.lock().unwrap().schedule(Direction::Backward, len) else {
+                        continue;
+                    };
+                    let (front, packet) = (front.clone(), buf[..len].to_vec());
+                    tokio::spawn(async move {
+                        tokio::time::sleep_until(at).await;
+                        _ = front.send_to(&packet, client).await;
+                    });
+                }
+            })
+        };
+
+        Self {
+            addr: front.local_addr().unwrap(),
+            link,
+            tasks: vec![forward, backward],
+        }
+    }
+
+    pub fn set_impairment(&self, impairment: Impairment) {
+        self.link.lock().unwrap().impairment = impairment;
+    }
+
+    /// Applies each impairment at its offset from now.
+    pub fn run_script(&mut self, script: Vec<(Duration, Impairment)>) {
+        let link = self.link.clone();
+        let start = Instant::now();
+
+        self.tasks.push(tokio::spawn(async move {
+            for (offset, impairment) in script {
+                tokio::time::sleep_until(start + offset).await;
+                link.lock().unwrap().impairment = impairment;
+            }
+        }));
+    }
+}
+
+impl Drop for UdpProxy {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,104 @@
+mod harness;
+
+use free_voip_core::{
+    contacts::ContactTicket,
+    history::{CallDirection, CallOutcome, CallRecord},
+    Event,
+};
+use harness::{recv, respond_with, spawn_peers, TestNode, TestPeer};
+use tokio::sync::broadcast::Receiver;
+
+/// Logs in a node with `peer` as its only contact.
+async fn node_with_contact(peer: &TestPeer) -> TestNode {
+    let node = harness::node().await;
+    node.login("Alice".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    node.add_contact(ContactTicket {
+        addr: Some(peer.addr()),
+        ..peer.ticket.clone()
+    })
+    .unwrap();
+    node
+}
+
+/// Waits for the next call to be written to the history, returning it.
+async fn recorded_call(events: &mut Receiver<Event>) -> CallRecord {
+    loop {
+        if let Event::CallHistoryUpdated(mut history) = recv(events).await {
+            return history.pop().expect("History is empty");
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn completed_call_recorded() {
+    let [bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+    let node = node_with_contact(&bob).await;
+    let mut events = node.events();
+
+    assert_eq!(node.ring(bob.ticket.node_id).await, Ok(true));
+    assert_eq!(node.hang_up().await, Ok(true));
+
+    let record = recorded_call(&mut events).await;
+    assert_eq!(record.peer, bob.ticket.node_id);
+    assert_eq!(record.direction, CallDirection::Outgoing);
+    assert_eq!(record.outcome, CallOutcome::Answered);
+    assert!(record.answered_at.is_some());
+    assert_eq!(node.call_history().unwrap().len(), 1);
+
+    node.clear_call_history().unwrap();
+    assert!(node.call_history().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn declined_call_recorded() {
+    let [bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), false);
+    let node = node_with_contact(&bob).await;
+    let mut events = node.events();
+
+    assert_eq!(node.ring(bob.ticket.node_id).await, Ok(false));
+
+    let record = recorded_call(&mut events).await;
+    assert_eq!(record.direction, CallDirection::Outgoing);
+    assert_eq!(record.outcome, CallOutcome::Declined);
+    assert_eq!((record.answered_at, record.duration), (None, 0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn busy_call_recorded() {
+    let [bob, carol] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+    assert_eq!(carol.ring(bob.addr()).await, Ok(true));
+    let node = node_with_contact(&bob).await;
+    let mut events = node.events();
+
+    assert_eq!(node.ring(bob.ticket.node_id).await, Ok(false));
+
+    let record = recorded_call(&mut events).await;
+    assert_eq!(record.outcome, CallOutcome::Busy);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unanswered_call_recorded_as_missed() {
+    let [bob] = spawn_peers().await;
+    let node = node_with_contact(&bob).await;
+    let mut events = node.events();
+    let node_addr = node.self_ticket().await.unwrap().node_addr();
+
+    // Nobody answers, Bob gives up
+    let call = bob.call.clone();
+    let (ring, ()) = tokio::join!(bob.ring(node_addr), async {
+        while !matches!(recv(&mut events).await, Event::RingRequest(_)) {}
+        call.disconnect().await;
+    });
+    assert_eq!(ring, Ok(false));
+
+    let record = recorded_call(&mut events).await;
+    assert_eq!(record.peer, bob.ticket.node_id);
+    assert_eq!(record.direction, CallDirection::Incoming);
+    assert_eq!(record.outcome, CallOutcome::Missed);
+    assert!(matches!(recv(&mut events).await, Event::MissedCall(_)));
+}
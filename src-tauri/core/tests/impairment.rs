@@ -0,0 +1,217 @@
+//! Call media under scripted network conditions. Run with `--nocapture` to see the measurements.
+
+mod harness;
+
+use std::time::Duration;
+
+use free_voip_core::call::CallMedia;
+use harness::{
+    audio_frame, frame_timestamp,
+    proxy::{Impairment, UdpProxy},
+    respond_with, spawn_peers, video_frame,
+};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+
+const AUDIO_INTERVAL: Duration = Duration::from_millis(20);
+const VIDEO_INTERVAL: Duration = Duration::from_micros(33_333);
+
+/// How long frames may still arrive after the last one was sent.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+struct Measurement {
+    sent: usize,
+    /// Timestamps of the delivered frames in order of arrival, with their latency.
+    delivered: Vec<(u64, Duration)>,
+    still_connected: bool,
+}
+
+impl Measurement {
+    fn delivered_ratio(&self) -> f64 {
+        self.delivered.len() as f64 / self.sent as f64
+    }
+
+    fn latency_percentile(&self, percentile: f64) -> Duration {
+        let mut latencies = self.delivered.iter().map(|(_, l)| *l).collect::<Vec<_>>();
+        latencies.sort();
+        let index = ((latencies.len() - 1) as f64 * percentile).round() as usize;
+        latencies[index]
+    }
+
+    fn in_order(&self) -> bool {
+        self.delivered.windows(2).all(|w| w[0].0 < w[1].0)
+    }
+
+    fn report(&self, name: &str) {
+        eprintln!(
+            "{name}: delivered {}/{} ({:.1}%), latency p50 {:?}, p95 {:?}, max {:?}",
+            self.delivered.len(),
+            self.sent,
+            self.delivered_ratio() * 100.0,
+            self.latency_percentile(0.5),
+            self.latency_percentile(0.95),
+            self.latency_percentile(1.0),
+        );
+    }
+}
+
+/// Calls a peer through a proxy with `impairment`, changed according to `script`, and streams
+/// `frames` to them, one every `interval`.
+///
+/// Frame timestamps are expected to be their offset from the first frame in microseconds, which
+/// is used to tell their one-way latency.
+async fn measure(
+    impairment: Impairment,
+    script: Vec<(Duration, Impairment)>,
+    frames: Vec<CallMedia>,
+    interval: Duration,
+) -> Measurement {
+    let [alice, mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+
+    let mut proxy = UdpProxy::start(bob.socket_addr(), impairment).await;
+    assert_eq!(alice.ring(bob.addr_via(&proxy)).await, Ok(true));
+    proxy.run_script(script);
+
+    let sent = frames.len();
+    let start = Instant::now();
+    let deadline = start + interval * sent as u32 + SETTLE_TIME;
+
+    let sending = async {
+        for (i, frame) in frames.into_iter().enumerate() {
+            tokio::time::sleep_until(start + interval * i as u32).await;
+            alice.media_out.send(frame).unwrap();
+        }
+    };
+    let receiving = async {
+        let mut delivered = vec![];
+        while delivered.len() < sent {
+            match tokio::time::timeout_at(deadline, bob.media_in.recv()).await {
+                Ok(Ok(media)) => {
+                    let timestamp = frame_timestamp(&media);
+                    let latency = start.elapsed() - Duration::from_micros(timestamp);
+                    delivered.push((timestamp, latency));
+                }
+                Ok(Err(RecvError::Lagged(_))) => {}
+                Ok(Err(RecvError::Closed)) | Err(_) => break,
+            }
+        }
+        delivered
+    };
+    let ((), delivered) = tokio::join!(sending, receiving);
+
+    Measurement {
+        sent,
+        delivered,
+        still_connected: alice.call.connection().await.is_some(),
+    }
+}
+
+fn audio_frames(count: u64) -> Vec<CallMedia> {
+    (0..count)
+        .map(|i| audio_frame(i * AUDIO_INTERVAL.as_micros() as u64))
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clean_network() {
+    let measurement = measure(
+        Impairment::default(),
+        vec![],
+        audio_frames(100),
+        AUDIO_INTERVAL,
+    )
+    .await;
+    measurement.report("clean");
+
+    assert_eq!(measurement.delivered_ratio(), 1.0);
+    assert!(measurement.in_order());
+    assert!(measurement.latency_percentile(0.5) < Duration::from_millis(50));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delay_adds_latency() {
+    let delay = Duration::from_millis(80);
+    let impairment = Impairment {
+        delay,
+        ..Default::default()
+    };
+    let measurement = measure(impairment, vec![], audio_frames(100), AUDIO_INTERVAL).await;
+    measurement.report("80 ms delay");
+
+    assert_eq!(measurement.delivered_ratio(), 1.0);
+    assert!(measurement.latency_percentile(0.0) >= delay);
+    assert!(measurement.latency_percentile(0.5) < delay + Duration::from_millis(100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loss_is_retransmitted() {
+    let impairment = Impairment {
+        loss: 0.1,
+        delay: Duration::from_millis(20),
+        ..Default::default()
+    };
+    let measurement = measure(impairment, vec![], audio_frames(100), AUDIO_INTERVAL).await;
+    measurement.report("10% loss");
+
+    // Media goes over a reliable stream, so loss costs latency rather than frames
+    assert_eq!(measurement.delivered_ratio(), 1.0);
+    assert!(measurement.in_order());
+    assert!(measurement.still_connected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reordering_keeps_frames_in_order() {
+    let impairment = Impairment {
+        delay: Duration::from_millis(10),
+        jitter: Duration::from_millis(40),
+        ..Default::default()
+    };
+    let measurement = measure(impairment, vec![], audio_frames(100), AUDIO_INTERVAL).await;
+    measurement.report("40 ms jitter");
+
+    assert_eq!(measurement.delivered_ratio(), 1.0);
+    assert!(measurement.in_order());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn limited_bandwidth_drops_frames_not_the_call() {
+    let impairment = Impairment {
+        bandwidth: Some(50_000),
+        ..Default::default()
+    };
+    // About 240 kB/s of video
+    let frames = (0..90)
+        .map(|i| video_frame(i * VIDEO_INTERVAL.as_micros() as u64, 8 * 1024))
+        .collect();
+    let measurement = measure(impairment, vec![], frames, VIDEO_INTERVAL).await;
+    measurement.report("50 kB/s");
+
+    assert!(measurement.delivered_ratio() > 0.0);
+    assert!(measurement.delivered_ratio() < 1.0);
+    assert!(measurement.in_order());
+    assert!(measurement.still_connected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn call_survives_outage() {
+    let outage = Impairment {
+        loss: 1.0,
+        ..Default::default()
+    };
+    let script = vec![
+        (Duration::from_secs(1), outage),
+        (Duration::from_secs(2), Impairment::default()),
+    ];
+    let measurement = measure(
+        Impairment::default(),
+        script,
+        audio_frames(150),
+        AUDIO_INTERVAL,
+    )
+    .await;
+    measurement.report("1 s outage");
+
+    assert_eq!(measurement.delivered_ratio(), 1.0);
+    assert!(measurement.latency_percentile(1.0) >= Duration::from_millis(500));
+    assert!(measurement.still_connected);
+}
@@ -0,0 +1,42 @@
+use free_voip_core::{contacts::ContactTicket, link::DeepLink, FreeVoipError, SecretKey};
+use iroh_base::ticket::Ticket;
+
+#[test]
+fn links_round_trip() {
+    let node_id = SecretKey::from_bytes(&[1; 32]).public();
+    let serialized_ticket = Ticket::serialize(&ContactTicket::new("Alice".to_owned(), node_id));
+
+    let add = DeepLink::parse(&format!("free-voip://add?ticket={serialized_ticket}")).unwrap();
+    assert!(
+        matches!(add, DeepLink::Add { ref ticket, .. } if ticket.node_id == node_id
+            && ticket.nickname == "Alice")
+    );
+    assert!(matches!(
+        DeepLink::parse(&add.to_uri()).unwrap(),
+        DeepLink::Add { serialized_ticket: ref s, .. } if *s == serialized_ticket
+    ));
+
+    let call = DeepLink::parse(&format!("free-voip://call?node={node_id}")).unwrap();
+    assert!(matches!(call, DeepLink::Call { node_id: n } if n == node_id));
+    assert_eq!(call.to_uri(), format!("free-voip://call?node={node_id}"));
+}
+
+#[test]
+fn invalid_links_rejected() {
+    for uri in [
+        "https://add?ticket=x",
+        "free-voip://delete?node=x",
+        "free-voip://call",
+        "free-voip://call?node=not-a-node",
+        "not a link",
+    ] {
+        assert!(
+            matches!(DeepLink::parse(uri), Err(FreeVoipError::InvalidState(_))),
+            "{uri}"
+        );
+    }
+    assert_eq!(
+        DeepLink::parse("free-voip://add?ticket=nodeabc").unwrap_err(),
+        FreeVoipError::InvalidTicket
+    );
+}
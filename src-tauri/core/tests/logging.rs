@@ -0,0 +1,83 @@
+use free_voip_core::{
+    contacts::ContactTicket,
+    invite::InviteTicket,
+    logging::{self, Redactor},
+    SecretKey,
+};
+use iroh_base::ticket::Ticket;
+
+#[test]
+fn redacts_identifying_data() {
+    let alice = SecretKey::from_bytes(&[1; 32]);
+    let bob = SecretKey::from_bytes(&[2; 32]).public();
+    let ticket = ContactTicket::new("Alice".to_owned(), alice.public());
+    let invite = InviteTicket::issue(&alice, ticket.clone(), u64::MAX, None);
+    let alice = alice.public();
+
+    let mut redactor = Redactor::default();
+    let lines = [
+        format!(r#"call{{direction="outgoing" peer={bob}}}: Ringing"#),
+        r#"contact_request: Received contact request nickname="Alice Smith""#.to_owned(),
+        format!("Received {ticket:?} from 192.168.1.20:4433 and [fe80::1]:4433"),
+        format!("Sharing {}", Ticket::serialize(&ticket)),
+        r#"Received file path="/home/alice/Downloads/passport.pdf""#.to_owned(),
+        format!("connection{{peer={bob}}}: done, {alice} is next"),
+        format!("Redeeming {}", Ticket::serialize(&invite)),
+        r#"Connected to relay url=RelayUrl("https://relay.example.com./")"#.to_owned(),
+    ]
+    .map(|line| redactor.redact(&line));
+
+    assert_eq!(
+        lines[0],
+        r#"call{direction="outgoing" peer=<node-1>}: Ringing"#
+    );
+    assert_eq!(
+        lines[1],
+        "contact_request: Received contact request nickname=<redacted>"
+    );
+    assert_eq!(
+        lines[2],
+        "Received ContactTicket { nickname: <redacted>, node_id: PublicKey(<node-2>), addr: None } from <ip> and <ip>"
+    );
+    assert_eq!(lines[3], "Sharing <ticket>");
+    assert_eq!(lines[4], "Received file path=<redacted>");
+    // Pseudonyms are stable within a bundle
+    assert_eq!(
+        lines[5],
+        "connection{peer=<node-1>}: done, <node-2> is next"
+    );
+    assert_eq!(lines[6], "Redeeming <ticket>");
+    assert_eq!(lines[7], r#"Connected to relay url=RelayUrl("<url>")"#);
+}
+
+#[tokio::test]
+async fn exports_redacted_bundle() {
+    let dir = std::env::temp_dir().join(format!("free-voip-logs-test-{}", std::process::id()));
+    let log_dir = dir.join("logs");
+    tokio::fs::create_dir_all(&log_dir).await.unwrap();
+    let node_id = SecretKey::from_bytes(&[3; 32]).public();
+    tokio::fs::write(
+        log_dir.join("free-voip.2024-01-02.log"),
+        format!("INFO second day {node_id}\n"),
+    )
+    .await
+    .unwrap();
+    tokio::fs::write(
+        log_dir.join("free-voip.2024-01-01.log"),
+        "INFO first day from 10.0.0.1:5000\n",
+    )
+    .await
+    .unwrap();
+
+    let path = logging::export_bundle(&log_dir, &dir).await.unwrap();
+    let bundle = tokio::fs::read_to_string(&path).await.unwrap();
+    _ = tokio::fs::remove_dir_all(&dir).await;
+
+    assert_eq!(
+        bundle,
+        "===== free-voip.2024-01-01.log =====\n\
+         INFO first day from <ip>\n\
+         ===== free-voip.2024-01-02.log =====\n\
+         INFO second day <node-1>\n"
+    );
+}
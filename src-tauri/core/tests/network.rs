@@ -0,0 +1,334 @@
+mod harness;
+
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    time::Duration,
+};
+
+use free_voip_core::{
+    contacts::{ContactTicket, ContactsMessage, ContactsProtocol},
+    network::{NetworkSettings, RelayMode},
+    Event, FreeVoipError, Node, NodeId, SecretKey,
+};
+use harness::{offline_settings, recv, respond_with, spawn_peers, TempDir, TestNode, TestPeer};
+use iroh::{endpoint::ConnectionType, NodeAddr, Watcher};
+
+/// Nothing listens here, so a node using it as its relay never gets one.
+const UNREACHABLE_RELAY: &str = "http://127.0.0.1:9";
+
+async fn node_with_settings(nickname: &str, settings: NetworkSettings) -> TestNode {
+    let node = harness::node_with_settings(&settings).await;
+    node.login(nickname.to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    node
+}
+
+async fn node(nickname: &str) -> TestNode {
+    node_with_settings(nickname, offline_settings()).await
+}
+
+/// A node announcing itself with mDNS, and otherwise offline.
+async fn lan_node(nickname: &str) -> TestNode {
+    let settings = NetworkSettings {
+        local_discovery: true,
+        ..offline_settings()
+    };
+    node_with_settings(nickname, settings).await
+}
+
+/// Settings with a relay, which hiding the IP address needs.
+fn unreachable_relay_settings() -> NetworkSettings {
+    NetworkSettings {
+        relay_mode: RelayMode::Custom,
+        relay_urls: vec![UNREACHABLE_RELAY.parse().unwrap()],
+        ..offline_settings()
+    }
+}
+
+async fn finds(node: &Node, node_id: NodeId) -> bool {
+    tokio::time::timeout(Duration::from_secs(20), async {
+        while !node
+            .nearby_devices()
+            .unwrap()
+            .iter()
+            .any(|d| d.node_id == node_id)
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_on_local_network_find_each_other() {
+    let alice = lan_node("Alice").await;
+    let bob = lan_node("Bob").await;
+    let bob_id = bob.self_ticket().await.unwrap().node_id;
+
+    assert!(finds(&alice, bob_id).await);
+
+    alice.logout(false).await.unwrap();
+    bob.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn local_discovery_can_be_turned_off() {
+    let carol = lan_node("Carol").await;
+    assert!(carol.network_settings().unwrap().local_discovery);
+
+    let settings = offline_settings();
+    carol.set_network_settings(settings.clone()).await.unwrap();
+    assert_eq!(carol.network_settings(), Ok(settings));
+
+    // Still applies after going back online
+    carol.logout(false).await.unwrap();
+    carol.unlock("passphrase").await.unwrap();
+    assert!(carol.nearby_devices().unwrap().is_empty());
+    carol.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn relays_disabled_while_online() {
+    let dave = node_with_settings("Dave", unreachable_relay_settings()).await;
+    let node_id = dave.self_ticket().await.unwrap().node_id;
+
+    dave.set_network_settings(offline_settings()).await.unwrap();
+
+    // Same identity on a new endpoint, which has no relay to advertise
+    let ticket = dave.self_ticket().await.unwrap();
+    assert_eq!(ticket.node_id, node_id);
+    assert_eq!(ticket.addr.unwrap().relay_url, None);
+    dave.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn settings_change_stops_echo_test_and_voicemail() {
+    let heidi = node("Heidi").await;
+    let mut events = heidi.events();
+    heidi.start_echo_test().await.unwrap();
+    heidi
+        .start_voicemail(SecretKey::from_bytes(&[6; 32]).public())
+        .await
+        .unwrap();
+
+    heidi
+        .set_network_settings(NetworkSettings {
+            local_discovery: true,
+            ..offline_settings()
+        })
+        .await
+        .unwrap();
+
+    // Both ran on the media channels of the previous endpoint
+    assert!(matches!(
+        heidi.stop_echo_test().await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    assert!(matches!(
+        heidi.stop_voicemail(true).await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    let mut echo_stats = false;
+    while let Ok(event) = events.try_recv() {
+        echo_stats |= matches!(event, Event::EchoStats(_));
+    }
+    assert!(echo_stats);
+    heidi.logout(false).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn settings_kept_during_call() {
+    let [mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+    let ivan = node("Ivan").await;
+    ivan.add_contact(ContactTicket {
+        addr: Some(bob.addr()),
+        ..bob.ticket.clone()
+    })
+    .unwrap();
+    let mut media = ivan.subscribe_call_media().await.unwrap();
+    assert_eq!(ivan.ring(bob.ticket.node_id).await, Ok(true));
+
+    let settings = NetworkSettings {
+        local_discovery: true,
+        ..offline_settings()
+    };
+    assert!(matches!(
+        ivan.set_network_settings(settings.clone()).await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    assert_eq!(ivan.network_settings(), Ok(offline_settings()));
+
+    // The media channel subscribed to before still carries the call
+    ivan.send_call_media(harness::audio_frame(0)).await.unwrap();
+    let received = recv(&mut bob.media_in).await;
+    bob.media_out.send(received).unwrap();
+    recv(&mut media).await;
+
+    ivan.hang_up().await.unwrap();
+    recv(&mut bob.hang_ups).await;
+    ivan.set_network_settings(settings.clone()).await.unwrap();
+    assert_eq!(ivan.network_settings(), Ok(settings));
+    ivan.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn custom_relays_need_urls() {
+    let erin = node("Erin").await;
+    let settings = NetworkSettings {
+        relay_mode: RelayMode::Custom,
+        ..offline_settings()
+    };
+
+    assert!(matches!(
+        erin.set_network_settings(settings).await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    assert_eq!(erin.network_settings(), Ok(offline_settings()));
+    erin.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn hidden_ip_kept_out_of_tickets() {
+    let frank = node("Frank").await;
+    frank
+        .set_network_settings(NetworkSettings {
+            hide_ip: true,
+            ..unreachable_relay_settings()
+        })
+        .await
+        .unwrap();
+
+    let ticket = frank.self_ticket().await.unwrap();
+    assert!(ticket.addr.unwrap().direct_addresses.is_empty());
+    assert!(frank.nearby_devices().unwrap().is_empty());
+    assert!(matches!(
+        frank.call_stats().await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    frank.logout(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn hidden_ip_needs_relays() {
+    let grace = node("Grace").await;
+    let settings = NetworkSettings {
+        hide_ip: true,
+        ..offline_settings()
+    };
+
+    assert!(matches!(
+        grace.set_network_settings(settings).await,
+        Err(FreeVoipError::InvalidState(_))
+    ));
+    assert_eq!(grace.network_settings(), Ok(offline_settings()));
+    grace.logout(false).await.unwrap();
+}
+
+/// Waits for hole punching to have had its chance, then checks how `peer` reaches `node`. The
+/// node's sockets only listen on loopback, so no direct path exists unless they share a machine,
+/// as they do in tests.
+async fn assert_reached_through_relay(peer: &TestPeer, node: &Node) {
+    let node_id = node.self_ticket().await.unwrap().node_id;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let conn_type = peer.endpoint().conn_type(node_id).unwrap().get();
+    match conn_type {
+        ConnectionType::Relay(_) => {}
+        ConnectionType::Direct(addr) | ConnectionType::Mixed(addr, _)
+            if addr.ip().is_loopback() => {}
+        _ => panic!("reached through {conn_type}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hidden_ip_reached_only_through_relay() {
+    let (_relay, relay_url) = harness::relay_server().await;
+    let judy = node_with_settings(
+        "Judy",
+        NetworkSettings {
+            relay_mode: RelayMode::Custom,
+            relay_urls: vec![relay_url.clone()],
+            hide_ip: true,
+            ..offline_settings()
+        },
+    )
+    .await;
+    let judy_id = judy.self_ticket().await.unwrap().node_id;
+    let dir = TempDir::new();
+    tokio::fs::create_dir_all(dir.path()).await.unwrap();
+
+    // Each peer becomes a contact right before Judy first reaches it, stored with a direct
+    // address that Judy has to leave alone
+    let trap = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    trap.set_nonblocking(true).unwrap();
+    let add_peer = |nickname| {
+        let judy = &judy;
+        let relay_url = relay_url.clone();
+        let trap_addr = trap.local_addr().unwrap();
+        async move {
+            let peer = TestPeer::spawn_on_relay(nickname, relay_url.clone()).await;
+            peer.contacts.send_replace([judy_id].into());
+            let addr = NodeAddr::new(peer.ticket.node_id)
+                .with_relay_url(relay_url)
+                .with_direct_addresses([trap_addr]);
+            judy.add_contact(ContactTicket {
+                addr: Some(addr),
+                ..peer.ticket.clone()
+            })
+            .unwrap();
+            peer
+        }
+    };
+
+    let mut outbox_peer = add_peer("outbox").await;
+    judy.set_avatar(Some(b"judy".to_vec())).await.unwrap();
+    recv(&mut outbox_peer.profile_updates).await;
+    assert_reached_through_relay(&outbox_peer, &judy).await;
+
+    let files_peer = add_peer("files").await;
+    respond_with(
+        files_peer.file_offers.resubscribe(),
+        files_peer.file_responses.clone(),
+        true,
+    );
+    let path = dir.path().join("hello.txt");
+    tokio::fs::write(&path, b"hello").await.unwrap();
+    assert_eq!(
+        judy.send_file(files_peer.ticket.node_id, path).await,
+        Ok(true)
+    );
+    assert_reached_through_relay(&files_peer, &judy).await;
+
+    let mut voicemail_peer = add_peer("voicemail").await;
+    judy.start_voicemail(voicemail_peer.ticket.node_id)
+        .await
+        .unwrap();
+    judy.send_call_media(harness::audio_frame(0)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(judy.stop_voicemail(true).await, Ok(Some(true)));
+    recv(&mut voicemail_peer.voicemails).await;
+    assert_reached_through_relay(&voicemail_peer, &judy).await;
+
+    let avatar_peer = add_peer("avatar").await;
+    let hash = avatar_peer.avatars.put(b"avatar").await.unwrap();
+    avatar_peer.own_avatar.send_replace(Some(hash.clone()));
+    let update = ContactsMessage::ProfileUpdate {
+        contact: avatar_peer.ticket.clone(),
+        avatar: Some(hash),
+    };
+    let judy_addr = judy.self_ticket().await.unwrap().node_addr();
+    ContactsProtocol::send_message(avatar_peer.endpoint(), judy_addr, &update, || {})
+        .await
+        .unwrap();
+    assert_eq!(
+        judy.avatar(Some(avatar_peer.ticket.node_id)).await,
+        Ok(Some(b"avatar".to_vec()))
+    );
+    assert_reached_through_relay(&avatar_peer, &judy).await;
+
+    let mut buf = [0; 1500];
+    assert!(trap.recv_from(&mut buf).is_err(), "dialed a direct address");
+    judy.logout(false).await.unwrap();
+}
@@ -0,0 +1,71 @@
+mod harness;
+
+use std::collections::HashMap;
+
+use free_voip_core::outbox::{DeliveryState, Outbox, OutboxItem, OutboxPayload};
+use harness::{recv, spawn_peers};
+use tokio::sync::{broadcast, watch};
+
+/// Waits for an update of item `id` in `state`.
+async fn wait_for(
+    updates: &mut broadcast::Receiver<OutboxItem>,
+    id: u64,
+    state: DeliveryState,
+) -> OutboxItem {
+    loop {
+        let item = recv(updates).await;
+        if item.id == id && item.state == state {
+            return item;
+        }
+    }
+}
+
+#[tokio::test]
+async fn delivers_once_recipient_is_reachable() {
+    let [alice, mut bob] = spawn_peers().await;
+    let (update_tx, mut updates) = broadcast::channel(32);
+    let (_, own_avatar) = watch::channel(None);
+    let (contact_addrs, contact_addrs_rx) = watch::channel(HashMap::new());
+    let outbox = Outbox::new(update_tx, own_avatar, contact_addrs_rx);
+    outbox.load(alice.ticket.node_id, vec![]);
+    outbox.start(alice.endpoint().clone(), alice.ticket.clone(), false);
+
+    // Without discovery there is no way to reach Bob until we learn Bob's address
+    let item = outbox.enqueue(
+        bob.ticket.node_id,
+        OutboxPayload::MissedCall { timestamp: 42 },
+    );
+    assert_eq!(recv(&mut updates).await.attempts, 0);
+    let failed = wait_for(&mut updates, item.id, DeliveryState::Pending).await;
+    assert_eq!(failed.attempts, 1);
+
+    contact_addrs.send_replace([(bob.ticket.node_id, bob.addr())].into());
+    let delivered = wait_for(&mut updates, item.id, DeliveryState::Delivered).await;
+    assert_eq!(delivered.attempts, 2);
+    let (caller, timestamp) = recv(&mut bob.missed_calls).await;
+    assert_eq!(caller.node_id, alice.ticket.node_id);
+    assert_eq!(timestamp, 42);
+}
+
+#[tokio::test]
+async fn drops_voicemail_without_recording() {
+    let [alice, bob] = spawn_peers().await;
+    let (update_tx, mut updates) = broadcast::channel(32);
+    let (_, own_avatar) = watch::channel(None);
+    let (_, contact_addrs) = watch::channel([(bob.ticket.node_id, bob.addr())].into());
+    let outbox = Outbox::new(update_tx, own_avatar, contact_addrs);
+    outbox.load(alice.ticket.node_id, vec![]);
+    outbox.start(alice.endpoint().clone(), alice.ticket.clone(), false);
+
+    let dir = harness::TempDir::new();
+    let item = outbox.enqueue(
+        bob.ticket.node_id,
+        OutboxPayload::Voicemail {
+            path: dir.path().join("gone.webm"),
+        },
+    );
+    assert_eq!(recv(&mut updates).await.id, item.id);
+    let dropped = recv(&mut updates).await;
+    assert_eq!((dropped.id, dropped.attempts), (item.id, 0));
+    assert!(outbox.items().is_empty());
+}
@@ -0,0 +1,43 @@
+mod harness;
+
+use free_voip_core::presence::{Presence, PresenceProtocol, PresenceState};
+use harness::spawn_peers;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn presence_visible_to_audience() {
+    let [alice, bob] = spawn_peers().await;
+    alice
+        .presence_audience
+        .send_modify(|a| _ = a.insert(bob.ticket.node_id));
+    let busy = Presence {
+        state: PresenceState::Busy,
+        status_text: Some("In a meeting".to_owned()),
+    };
+    alice.presence.send_replace(busy.clone());
+
+    let presence = PresenceProtocol::probe(bob.endpoint(), alice.addr()).await;
+    assert_eq!(presence, Ok(busy));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn presence_hidden_from_others() {
+    let [alice, bob, carol] = spawn_peers().await;
+    alice
+        .presence_audience
+        .send_modify(|a| _ = a.insert(bob.ticket.node_id));
+
+    let result = PresenceProtocol::probe(carol.endpoint(), alice.addr()).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invisible_presence_hidden_from_everyone() {
+    let [alice, bob] = spawn_peers().await;
+    alice
+        .presence_audience
+        .send_modify(|a| _ = a.insert(bob.ticket.node_id));
+    alice.presence.send_replace(Presence::OFFLINE);
+
+    let result = PresenceProtocol::probe(bob.endpoint(), alice.addr()).await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,38 @@
+mod harness;
+
+use free_voip_core::profile::ProfileProtocol;
+use harness::spawn_peers;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn avatar_fetched_and_cached() {
+    let [alice, bob] = spawn_peers().await;
+    let image = b"not really a png".to_vec();
+    let hash = alice.avatars.put(&image).await.unwrap();
+    alice.own_avatar.send_replace(Some(hash.clone()));
+
+    let fetched = ProfileProtocol::fetch_avatar(bob.endpoint(), alice.addr(), &hash, &bob.avatars)
+        .await
+        .unwrap();
+    assert_eq!(fetched, image);
+    assert_eq!(bob.avatars.get(&hash).await.unwrap(), Some(image));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_current_avatar_handed_out() {
+    let [alice, bob, carol] = spawn_peers().await;
+
+    // Alice has Carol's avatar cached, but only hands out her own
+    let hash = alice.avatars.put(b"carol").await.unwrap();
+    carol.own_avatar.send_replace(Some(hash.clone()));
+    let old_hash = alice.avatars.put(b"old alice").await.unwrap();
+    alice
+        .own_avatar
+        .send_replace(Some(alice.avatars.put(b"alice").await.unwrap()));
+
+    for hash in [hash, old_hash] {
+        let result =
+            ProfileProtocol::fetch_avatar(bob.endpoint(), alice.addr(), &hash, &bob.avatars).await;
+        assert!(result.is_err());
+        assert_eq!(bob.avatars.get(&hash).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,37 @@
+mod harness;
+
+use free_voip_core::{
+    contacts::ContactTicket,
+    qr::{self, QrFormat},
+    FreeVoipError, SecretKey,
+};
+use iroh_base::ticket::Ticket;
+
+#[tokio::test]
+async fn ticket_survives_png_round_trip() {
+    let ticket = ContactTicket::new("Alice".to_owned(), SecretKey::from_bytes(&[1; 32]).public());
+    let serialized = Ticket::serialize(&ticket);
+
+    let png = qr::encode(&serialized, QrFormat::Png).unwrap();
+    assert_eq!(
+        harness::node().await.decode_ticket_qr(&png),
+        Ok(serialized.clone())
+    );
+
+    let svg = qr::encode(&serialized, QrFormat::Svg).unwrap();
+    assert!(String::from_utf8(svg).unwrap().contains("<svg"));
+}
+
+#[tokio::test]
+async fn qr_code_without_ticket_rejected() {
+    let node = harness::node().await;
+    let png = qr::encode("https://example.com", QrFormat::Png).unwrap();
+    assert_eq!(
+        node.decode_ticket_qr(&png),
+        Err(FreeVoipError::InvalidTicket)
+    );
+    assert!(matches!(
+        node.decode_ticket_qr(b"not an image"),
+        Err(FreeVoipError::InvalidState(_))
+    ));
+}
@@ -0,0 +1,334 @@
+mod harness;
+
+use std::time::Duration;
+
+use free_voip_core::{
+    call::{AudioConfig, CallControl, CallMedia},
+    contacts::ContactTicket,
+    recording::CallRecorder,
+    Event,
+};
+use harness::{recv, respond_with, spawn_peers, TempDir};
+use tokio::sync::broadcast;
+
+const EBML: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const DURATION: u32 = 0x4489;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// Splits EBML data into its elements, failing if any size runs past the end.
+fn elements(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut elements = vec![];
+    while !data.is_empty() {
+        let id_len = data[0].leading_zeros() as usize + 1;
+        let id = data[..id_len]
+            .iter()
+            .fold(0u32, |id, b| id << 8 | *b as u32);
+
+        let size_len = data[id_len].leading_zeros() as usize + 1;
+        let size = data[id_len..id_len + size_len]
+            .iter()
+            .fold(0u64, |size, b| size << 8 | *b as u64)
+            & !(1 << (7 * size_len));
+        let start = id_len + size_len;
+        let end = start + size as usize;
+        assert!(end <= data.len(), "element {id:#X} overruns its parent");
+
+        elements.push((id, &data[start..end]));
+        data = &data[end..];
+    }
+    elements
+}
+
+fn child(data: &[u8], id: u32) -> &[u8] {
+    elements(data)
+        .into_iter()
+        .find(|(child_id, _)| *child_id == id)
+        .unwrap_or_else(|| panic!("element {id:#X} missing"))
+        .1
+}
+
+fn uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |value, b| value << 8 | *b as u64)
+}
+
+fn video(timestamp_ms: u64, key: bool) -> CallMedia {
+    // VP8 key frames start with the frame size, here 320x240
+    let frame_data = if key {
+        vec![0x10, 0x02, 0x00, 0x9D, 0x01, 0x2A, 0x40, 0x01, 0xF0, 0x00]
+    } else {
+        vec![0x11, 0x02, 0x00]
+    };
+    CallMedia::Video {
+        frame_type: if key { "key" } else { "delta" }.to_owned(),
+        timestamp: timestamp_ms * 1000,
+        duration: None,
+        byte_length: frame_data.len() as u64,
+        frame_data,
+    }
+}
+
+fn audio(timestamp_ms: u64) -> CallMedia {
+    CallMedia::Audio {
+        frame_type: "key".to_owned(),
+        timestamp: timestamp_ms * 1000,
+        duration: None,
+        byte_length: 4,
+        frame_data: vec![0x21, 0x10, 0x04, 0x60],
+    }
+}
+
+#[tokio::test]
+async fn recording_parses_back() {
+    let dir = TempDir::new();
+    tokio::fs::create_dir_all(dir.path()).await.unwrap();
+    let path = dir.path().join("recording.mkv");
+    let (local_tx, local_rx) = broadcast::channel(256);
+    let (remote_tx, remote_rx) = broadcast::channel(256);
+    let recorder = CallRecorder::start(
+        path.clone(),
+        local_rx,
+        remote_rx,
+        AudioConfig::default(),
+        AudioConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    // The remote audio lags behind the local video, so the tracks have to be interleaved
+    let mut frames = 0;
+    for i in 0..25 {
+        local_tx.send(video(i * 100, i % 10 == 0)).unwrap();
+        frames += 1;
+        if i >= 3 {
+            remote_tx.send(audio((i - 3) * 100)).unwrap();
+            frames += 1;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let data = tokio::fs::read(recorder.stop().await.unwrap())
+        .await
+        .unwrap();
+
+    let top = elements(&data);
+    assert_eq!(top.len(), 2);
+    assert_eq!(top[0].0, EBML);
+    assert_eq!(child(top[0].1, DOC_TYPE), b"matroska");
+    assert_eq!(top[1].0, SEGMENT);
+
+    let segment = elements(top[1].1);
+    assert_eq!(segment[0].0, INFO);
+    let duration = f64::from_be_bytes(child(segment[0].1, DURATION).try_into().unwrap());
+    assert!(duration >= 2400.0, "duration is {duration}");
+
+    assert_eq!(segment[1].0, TRACKS);
+    let tracks = elements(segment[1].1);
+    assert_eq!(tracks.len(), 4);
+    let codecs: Vec<(u64, &[u8])> = tracks
+        .iter()
+        .map(|(id, entry)| {
+            assert_eq!(*id, TRACK_ENTRY);
+            (uint(child(entry, TRACK_NUMBER)), child(entry, CODEC_ID))
+        })
+        .collect();
+    assert_eq!(
+        codecs,
+        [
+            (1, &b"V_VP8"[..]),
+            (2, b"V_VP8"),
+            (3, b"A_AAC"),
+            (4, b"A_AAC")
+        ]
+    );
+    let local_video = child(tracks[0].1, VIDEO);
+    assert_eq!(uint(child(local_video, PIXEL_WIDTH)), 320);
+    assert_eq!(uint(child(local_video, PIXEL_HEIGHT)), 240);
+
+    let clusters = &segment[2..];
+    assert!(clusters.len() >= 2);
+    let mut blocks = 0;
+    let mut last_timestamp = 0;
+    let mut tracks_seen = [false; 4];
+    for (id, cluster) in clusters {
+        assert_eq!(*id, CLUSTER);
+        let children = elements(cluster);
+        assert_eq!(children[0].0, CLUSTER_TIMESTAMP);
+        let cluster_timestamp = uint(children[0].1);
+
+        for (id, block) in &children[1..] {
+            assert_eq!(*id, SIMPLE_BLOCK);
+            let track = block[0] & 0x7F;
+            let relative = i16::from_be_bytes([block[1], block[2]]);
+            assert!((0..1000).contains(&relative));
+
+            let timestamp = cluster_timestamp + relative as u64;
+            assert!(timestamp >= last_timestamp, "block went back in time");
+            last_timestamp = timestamp;
+            tracks_seen[track as usize - 1] = true;
+            blocks += 1;
+        }
+    }
+    assert_eq!(blocks, frames);
+    assert_eq!(tracks_seen, [true, false, false, true]);
+}
+
+/// Segment children of the Matroska file `data`.
+fn segment(data: &[u8]) -> Vec<(u32, &[u8])> {
+    let top = elements(data);
+    assert_eq!(top[1].0, SEGMENT);
+    elements(top[1].1)
+}
+
+/// CodecPrivate, sampling frequency and channels of each audio track in `segment`.
+fn audio_tracks(segment: &[(u32, &[u8])]) -> Vec<(Vec<u8>, f64, u64)> {
+    let tracks = child_of(segment, TRACKS);
+    elements(tracks)
+        .into_iter()
+        .filter(|(_, entry)| child(entry, CODEC_ID) == b"A_AAC")
+        .map(|(_, entry)| {
+            let audio = child(entry, AUDIO);
+            let frequency =
+                f64::from_be_bytes(child(audio, SAMPLING_FREQUENCY).try_into().unwrap());
+            (
+                child(entry, CODEC_PRIVATE).to_vec(),
+                frequency,
+                uint(child(audio, CHANNELS)),
+            )
+        })
+        .collect()
+}
+
+fn child_of<'a>(elements: &[(u32, &'a [u8])], id: u32) -> &'a [u8] {
+    elements
+        .iter()
+        .find(|(child_id, _)| *child_id == id)
+        .unwrap_or_else(|| panic!("element {id:#X} missing"))
+        .1
+}
+
+#[tokio::test]
+async fn recording_keeps_gui_frame_spacing() {
+    let dir = TempDir::new();
+    tokio::fs::create_dir_all(dir.path()).await.unwrap();
+    let path = dir.path().join("recording.mkv");
+    let (local_tx, local_rx) = broadcast::channel(256);
+    let (remote_tx, remote_rx) = broadcast::channel(256);
+    let recorder = CallRecorder::start(
+        path.clone(),
+        local_rx,
+        remote_rx,
+        AudioConfig {
+            sample_rate: 44100,
+            channels: 2,
+        },
+        AudioConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    // Two seconds of audio from each side, stamped with `performance.now()` in microseconds as
+    // the GUI's encoders do, each with a clock of its own
+    for i in 0..94 {
+        local_tx
+            .send(harness::audio_frame(8_734_512_345 + i * 21_333))
+            .unwrap();
+        remote_tx
+            .send(harness::audio_frame(1_204_000_017 + i * 21_333))
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let data = tokio::fs::read(recorder.stop().await.unwrap())
+        .await
+        .unwrap();
+
+    let segment = segment(&data);
+    let duration = f64::from_be_bytes(
+        child(child_of(&segment, INFO), DURATION)
+            .try_into()
+            .unwrap(),
+    );
+    assert!(
+        (1900.0..2500.0).contains(&duration),
+        "duration is {duration}"
+    );
+
+    assert_eq!(
+        audio_tracks(&segment),
+        [
+            (vec![0x12, 0x10], 44100.0, 2),
+            (vec![0x11, 0x88], 48000.0, 1)
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recording_uses_both_audio_configs() {
+    let [mut bob] = spawn_peers().await;
+    respond_with(bob.rings.resubscribe(), bob.ring_responses.clone(), true);
+    let node = harness::node().await;
+    node.login("Alice".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    node.add_contact(ContactTicket {
+        addr: Some(bob.addr()),
+        ..bob.ticket.clone()
+    })
+    .unwrap();
+    let mut events = node.events();
+    assert_eq!(node.ring(bob.ticket.node_id).await, Ok(true));
+
+    // Our encoder's config reaches Bob too
+    let own_config = AudioConfig {
+        sample_rate: 24000,
+        channels: 1,
+    };
+    node.set_audio_config(own_config).await.unwrap();
+    assert!(matches!(
+        recv(&mut bob.controls).await,
+        CallControl::AudioConfig(config) if config == own_config
+    ));
+
+    // Controls are handled in order, so Bob's config is known once the second one arrived
+    let bob_config = AudioConfig {
+        sample_rate: 44100,
+        channels: 2,
+    };
+    bob.call
+        .send_control(&CallControl::AudioConfig(bob_config))
+        .await
+        .unwrap();
+    bob.call
+        .send_control(&CallControl::Recording { active: true })
+        .await
+        .unwrap();
+    while !matches!(recv(&mut events).await, Event::PeerRecording(true)) {}
+
+    node.start_recording().await.unwrap();
+    let path = node.stop_recording().await.unwrap();
+    let data = tokio::fs::read(path).await.unwrap();
+
+    assert_eq!(
+        audio_tracks(&segment(&data)),
+        [
+            (vec![0x13, 0x08], 24000.0, 1),
+            (vec![0x12, 0x10], 44100.0, 2)
+        ]
+    );
+    node.hang_up().await.unwrap();
+}
@@ -0,0 +1,142 @@
+mod harness;
+
+use std::path::{Path, PathBuf};
+
+use free_voip_core::{
+    call::{AudioConfig, CallMedia},
+    contacts::ContactTicket,
+    voicemail::{self, VoicemailProtocol, MAX_VOICEMAILS_PER_SENDER},
+    Event, FreeVoipError, Node,
+};
+use harness::{audio_frame, frame_timestamp, recv, spawn_peers, TempDir, TestPeer};
+use tokio::time::Instant;
+
+/// Where `performance.now()` might be when the GUI encodes its first frame, in microseconds.
+const GUI_CLOCK_START: u64 = 8_734_512_345;
+/// 1024 AAC samples at 48 kHz.
+const FRAME_SPACING: u64 = 21_333;
+
+/// Three seconds of audio frames stamped like the GUI's encoder does.
+fn gui_frames() -> Vec<CallMedia> {
+    (0..=3_000_000 / FRAME_SPACING)
+        .map(|i| audio_frame(GUI_CLOCK_START + i * FRAME_SPACING))
+        .collect()
+}
+
+/// Writes a voicemail of `frames` into `dir`, returning its path.
+async fn write_voicemail(dir: &Path, name: &str, frames: &[CallMedia]) -> PathBuf {
+    let path = dir.join(format!("{name}.mka"));
+    tokio::fs::create_dir_all(dir).await.unwrap();
+    tokio::fs::write(
+        &path,
+        voicemail::encode_container(frames, AudioConfig::default()),
+    )
+    .await
+    .unwrap();
+    path
+}
+
+#[test]
+fn container_round_trip_keeps_gui_timing() {
+    let frames = gui_frames();
+    assert_eq!(voicemail::duration_ms(&frames), 2986);
+
+    let bytes = voicemail::encode_container(&frames, AudioConfig::default());
+    // EBML magic
+    assert_eq!(bytes[..4], [0x1A, 0x45, 0xDF, 0xA3]);
+
+    let (decoded, config) = voicemail::decode_container(&bytes).unwrap();
+    assert_eq!(config, AudioConfig::default());
+    assert_eq!(decoded.len(), frames.len());
+    for (original, decoded) in frames.iter().zip(&decoded) {
+        // Blocks have millisecond precision, counted from the first frame
+        let expected = (frame_timestamp(original) - GUI_CLOCK_START) / 1000 * 1000;
+        assert_eq!(frame_timestamp(decoded), expected);
+
+        let (
+            CallMedia::Audio {
+                frame_data: original,
+                ..
+            },
+            CallMedia::Audio {
+                frame_data: decoded,
+                ..
+            },
+        ) = (original, decoded)
+        else {
+            panic!("Voicemail holds more than audio");
+        };
+        assert_eq!(original, decoded);
+    }
+    assert_eq!(voicemail::duration_ms(&decoded), 2986);
+}
+
+#[test]
+fn garbage_is_not_a_voicemail() {
+    assert!(voicemail::decode_container(b"FVVM\x01").is_err());
+
+    // Truncated in the middle of a block
+    let bytes = voicemail::encode_container(&gui_frames(), AudioConfig::default());
+    assert!(voicemail::decode_container(&bytes[..bytes.len() - 3]).is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn playback_paced_in_real_time() {
+    let dir = TempDir::new();
+    let path = write_voicemail(dir.path(), "paced", &gui_frames()).await;
+
+    let mut played = vec![];
+    voicemail::play(&path, |_| played.push(Instant::now()))
+        .await
+        .unwrap();
+
+    let elapsed = *played.last().unwrap() - played[0];
+    assert_eq!(elapsed.as_millis(), 2986);
+}
+
+/// Sends a voicemail of a single frame at `timestamp` from `peer` to `node`.
+async fn send(peer: &TestPeer, node: &Node, timestamp: u64) -> Result<(), FreeVoipError> {
+    let dir = TempDir::new();
+    let path = write_voicemail(dir.path(), "outgoing", &[audio_frame(timestamp)]).await;
+    let addr = node.self_ticket().await.unwrap().node_addr();
+    VoicemailProtocol::send(peer.endpoint(), addr, &peer.ticket, &path).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn voicemail_from_stranger_refused() {
+    let [bob] = spawn_peers().await;
+    let node = harness::node().await;
+    node.login("Alice".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+
+    assert!(send(&bob, &node, 0).await.is_err());
+    assert!(node.voicemails().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn voicemails_capped_per_sender() {
+    let [bob] = spawn_peers().await;
+    let node = harness::node().await;
+    node.login("Alice".to_owned(), "passphrase", None)
+        .await
+        .unwrap();
+    node.add_contact(ContactTicket {
+        addr: Some(bob.addr()),
+        ..bob.ticket.clone()
+    })
+    .unwrap();
+    let mut events = node.events();
+
+    for i in 0..MAX_VOICEMAILS_PER_SENDER as u64 {
+        send(&bob, &node, i).await.unwrap();
+        while !matches!(recv(&mut events).await, Event::VoicemailReceived(_)) {}
+    }
+    assert_eq!(send(&bob, &node, 100).await, Err(FreeVoipError::Declined));
+
+    // Deleting one makes room again
+    let oldest = node.voicemails().unwrap().remove(0);
+    node.delete_voicemail(&oldest.id).await.unwrap();
+    send(&bob, &node, 100).await.unwrap();
+    assert_eq!(node.voicemails().unwrap().len(), MAX_VOICEMAILS_PER_SENDER);
+}
@@ -1,354 +1,338 @@
-mod call;
-mod contacts;
-
-use std::ops::DerefMut;
-
-use contacts::ContactTicket;
-use iroh::{protocol::Router, Endpoint, NodeId, SecretKey};
-use iroh_base::ticket::Ticket;
-use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use free_voip_core::{
+    backup::BackupSource,
+    call::{AudioConfig, CallMedia, CallStats},
+    contacts::ContactTicket,
+    echo::EchoStats,
+    history::CallRecord,
+    invite::Invite,
+    link::DeepLink,
+    logging,
+    network::{NearbyDevice, NetworkSettings},
+    outbox::OutboxItem,
+    presence::{Presence, PresenceSettings},
+    qr::QrFormat,
+    storage::Storage,
+    voicemail::VoicemailInfo,
+    FreeVoipError, Node, NodeConfig, NodeId,
+};
+use serde_json::Value;
 use tauri::{ipc::Channel, AppHandle, Emitter, Manager, State};
 use tauri_plugin_store::StoreExt;
-use tokio::sync::{
-    broadcast::{channel, Receiver, Sender},
-    RwLock,
-};
+use tracing::{error, warn};
 
-use crate::{
-    call::{CallMedia, CallProtocol},
-    contacts::ContactsProtocol,
-};
+fn store_error(e: tauri_plugin_store::Error) -> FreeVoipError {
+    FreeVoipError::StoreError(e.to_string())
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct EndpointCredentials {
-    self_ticket: ContactTicket,
-    secret_key: SecretKey,
-}
-
-#[derive(Default)]
-struct AppStateInner {
-    endpoint_credentials: Option<EndpointCredentials>,
-    router: Option<Router>,
-    call_protocol: Option<CallProtocol>,
-    contact_response_tx: Option<Sender<bool>>,
-    ring_response_tx: Option<Sender<bool>>,
-    media_tx: Option<Sender<CallMedia>>,
-    media_rx: Option<Receiver<CallMedia>>,
-}
-type AppState = RwLock<AppStateInner>;
-
-async fn build_endpoint(
-    secret_key: Option<SecretKey>,
-) -> Result<Endpoint, iroh::endpoint::BindError> {
-    let builder = Endpoint::builder().discovery_n0();
-
-    let builder = if let Some(key) = secret_key {
-        builder.secret_key(key)
-    } else {
-        builder
-    };
+/// [`Storage`] backed by the store plugin, one JSON file per store.
+struct TauriStorage(AppHandle);
 
-    let endpoint = builder.bind().await?;
-    println!("Endpoint created with {:?}", endpoint.node_id());
-
-    Ok(endpoint)
-}
-
-fn build_router(
-    app_handle: AppHandle,
-    app_state: &mut AppStateInner,
-    endpoint: Endpoint,
-) -> Router {
-    let contacts = {
-        // Create and set protocol communication channels
-        let (request_tx, mut request_rx) = channel::<ContactTicket>(8);
-        let (response_tx, response_rx) = channel::<bool>(8);
-        app_state.contact_response_tx = Some(response_tx);
-
-        // Listen to contact requests
-        let app_handle = app_handle.clone();
-        tokio::spawn(async move {
-            while let Ok(ticket) = request_rx.recv().await {
-                if let Err(e) = app_handle.emit("contact-request", ticket) {
-                    eprintln!("Failed to emit contact request event: {}", e);
-                }
-            }
-        });
+impl Storage for TauriStorage {
+    fn get(&self, store: &str, key: &str) -> Result<Option<Value>, FreeVoipError> {
+        let store = self.0.store(store).map_err(store_error)?;
+        Ok(store.get(key))
+    }
 
-        ContactsProtocol::new(request_tx, response_rx)
-    };
+    fn set(&self, store: &str, key: &str, value: Value) -> Result<(), FreeVoipError> {
+        let store = self.0.store(store).map_err(store_error)?;
+        store.set(key, value);
+        store.save().map_err(store_error)
+    }
 
-    let call = {
-        let (ring_tx, mut ring_rx) = channel::<ContactTicket>(2);
-        let (response_tx, response_rx) = channel::<bool>(2);
-        app_state.ring_response_tx = Some(response_tx);
-
-        // Listen to ring requests
-        let app_handle_clone = app_handle.clone();
-        tokio::spawn(async move {
-            while let Ok(ticket) = ring_rx.recv().await {
-                if let Err(e) = app_handle_clone.emit("ring-request", ticket) {
-                    eprintln!("Failed to emit ring request event: {}", e);
-                }
-            }
-        });
-
-        let (in_media_tx, in_media_rx) = channel::<CallMedia>(32);
-        let (out_media_tx, out_media_rx) = channel::<CallMedia>(32);
-        let (hang_up_tx, mut hang_up_rx) = channel::<()>(1);
-        app_state.media_tx = Some(out_media_tx);
-        app_state.media_rx = Some(in_media_rx);
-
-        let app_handle_clone = app_handle.clone();
-        tokio::spawn(async move {
-            while let Ok(()) = hang_up_rx.recv().await {
-                if let Err(err) = app_handle_clone.emit("call-hang-up", ()) {
-                    eprintln!("Failed to emit call hangup event: {}", err);
-                }
-            }
-        });
+    fn delete(&self, store: &str, key: &str) -> Result<(), FreeVoipError> {
+        let store = self.0.store(store).map_err(store_error)?;
+        store.delete(key);
+        store.save().map_err(store_error)
+    }
 
-        CallProtocol::new(ring_tx, response_rx, in_media_tx, out_media_rx, hang_up_tx)
-    };
+    fn entries(&self, store: &str) -> Result<Vec<(String, Value)>, FreeVoipError> {
+        let store = self.0.store(store).map_err(store_error)?;
+        Ok(store.entries())
+    }
+
+    fn clear(&self, store: &str) -> Result<(), FreeVoipError> {
+        let store = self.0.store(store).map_err(store_error)?;
+        store.clear();
+        store.save().map_err(store_error)
+    }
+}
 
-    // HACK: only used to call `ring` because it requires GUI-Iroh bridging channels
-    app_state.call_protocol = Some(call.clone());
+fn node_config(app_handle: &AppHandle) -> Result<NodeConfig, tauri::Error> {
+    let path = app_handle.path();
+    let data_dir = path.app_data_dir()?;
+
+    Ok(NodeConfig {
+        download_dir: path
+            .download_dir()
+            .unwrap_or_else(|_| data_dir.join("downloads")),
+        recordings_dir: path
+            .video_dir()
+            .unwrap_or_else(|_| data_dir.join("recordings")),
+        data_dir,
+    })
+}
 
-    Router::builder(endpoint)
-        .accept(contacts::ALPN, contacts)
-        .accept(call::ALPN, call)
-        .spawn()
+#[tauri::command]
+async fn restore_login(node: State<'_, Node>) -> Result<bool, FreeVoipError> {
+    node.restore_login().await
 }
 
 #[tauri::command]
-async fn restore_login(
-    app_handle: AppHandle,
-    app_state: State<'_, AppState>,
-) -> Result<bool, String> {
-    let mut app_state = app_state.write().await;
+async fn login(
+    node: State<'_, Node>,
+    nickname: String,
+    passphrase: String,
+) -> Result<(), FreeVoipError> {
+    node.login(nickname, &passphrase, None).await
+}
 
-    if app_state.router.is_some() {
-        // Endpoint and Router are already active, nothing to restore.
-        return Ok(true);
-    }
+#[tauri::command]
+async fn update_profile(node: State<'_, Node>, nickname: String) -> Result<(), FreeVoipError> {
+    node.update_profile(nickname).await
+}
 
-    // Create new endpoint if credentials are available
-    if let Some(ref mut credentials) = app_state.endpoint_credentials {
-        let endpoint = build_endpoint(Some(credentials.secret_key.clone()))
-            .await
-            .map_err(|e| e.to_string())?;
-        app_state.router = Some(build_router(app_handle, app_state.deref_mut(), endpoint));
+/// Replaces our avatar with the given image, or removes it.
+#[tauri::command]
+async fn set_avatar(node: State<'_, Node>, image: Option<Vec<u8>>) -> Result<(), FreeVoipError> {
+    node.set_avatar(image).await
+}
 
-        return Ok(true);
-    }
+/// The avatar image of a contact, or ours without a `node_id`.
+#[tauri::command]
+async fn get_avatar(
+    node: State<'_, Node>,
+    node_id: Option<NodeId>,
+) -> Result<Option<Vec<u8>>, FreeVoipError> {
+    node.avatar(node_id).await
+}
 
-    Ok(false)
+#[tauri::command]
+fn get_presence(node: State<'_, Node>) -> Presence {
+    node.presence()
 }
 
 #[tauri::command]
-async fn login(
-    app_handle: AppHandle,
-    nickname: String,
-    app_state: State<'_, AppState>,
-) -> Result<(), String> {
-    println!("Received login request: {}", nickname);
-    let mut app_state = app_state.write().await;
-
-    // Create new endpoint and router
-    let endpoint = build_endpoint(None).await.map_err(|e| e.to_string())?;
-    app_state.endpoint_credentials = Some(EndpointCredentials {
-        self_ticket: ContactTicket {
-            nickname,
-            node_id: endpoint.node_id(),
-        },
-        secret_key: endpoint.secret_key().clone(),
-    });
-    let router = build_router(app_handle.clone(), app_state.deref_mut(), endpoint);
-
-    // Store credentials
-    let credentials_store = app_handle
-        .store("credentials.json")
-        .map_err(|e| e.to_string())?;
-    credentials_store.set(
-        "endpoint",
-        serde_json::to_value(&app_state.endpoint_credentials).map_err(|e| e.to_string())?,
-    );
-    credentials_store.save().map_err(|e| e.to_string())?;
-    credentials_store.close_resource();
-
-    // Close existing endpoint if it exists
-    if let Some(ref existing_router) = app_state.router {
-        existing_router
-            .shutdown()
-            .await
-            .map_err(|e| e.to_string())?;
+fn set_presence(node: State<'_, Node>, presence: Presence) -> Result<(), FreeVoipError> {
+    node.set_presence(presence)
+}
+
+#[tauri::command]
+fn get_presence_settings(node: State<'_, Node>) -> Result<PresenceSettings, FreeVoipError> {
+    node.presence_settings()
+}
+
+#[tauri::command]
+fn set_presence_settings(
+    node: State<'_, Node>,
+    settings: PresenceSettings,
+) -> Result<(), FreeVoipError> {
+    node.set_presence_settings(settings)
+}
+
+#[tauri::command]
+fn get_contact_presences(node: State<'_, Node>) -> HashMap<NodeId, Presence> {
+    node.contact_presences()
+}
+
+#[tauri::command]
+fn get_network_settings(node: State<'_, Node>) -> Result<NetworkSettings, FreeVoipError> {
+    node.network_settings()
+}
+
+#[tauri::command]
+async fn set_network_settings(
+    node: State<'_, Node>,
+    settings: NetworkSettings,
+) -> Result<(), FreeVoipError> {
+    node.set_network_settings(settings).await
+}
+
+#[tauri::command]
+fn get_nearby_devices(node: State<'_, Node>) -> Result<Vec<NearbyDevice>, FreeVoipError> {
+    node.nearby_devices()
+}
+
+/// Returns the recovery phrase, or the path of a backup file encrypted with `backup_passphrase`
+/// if one is given.
+#[tauri::command]
+async fn export_identity(
+    node: State<'_, Node>,
+    backup_passphrase: Option<String>,
+) -> Result<String, FreeVoipError> {
+    match backup_passphrase {
+        Some(backup_passphrase) => {
+            let path = node.export_identity_file(&backup_passphrase).await?;
+            Ok(path.display().to_string())
+        }
+        None => node.export_mnemonic().await,
     }
-    app_state.router = Some(router);
+}
 
-    Ok(())
+#[tauri::command]
+async fn import_identity(
+    node: State<'_, Node>,
+    source: BackupSource,
+    passphrase: String,
+) -> Result<(), FreeVoipError> {
+    node.import_identity(source, &passphrase).await
 }
 
 #[tauri::command]
-async fn get_serialized_self_ticket(
-    app_state: State<'_, AppState>,
-) -> Result<serde_json::Value, String> {
-    let self_ticket = {
-        let app_state = app_state.read().await;
-        let credentials = app_state
-            .endpoint_credentials
-            .as_ref()
-            .ok_or("Credentials not found".to_owned())?;
-        credentials.self_ticket.clone()
-    };
+async fn unlock(node: State<'_, Node>, passphrase: String) -> Result<(), FreeVoipError> {
+    node.unlock(&passphrase).await
+}
+
+#[tauri::command]
+async fn change_passphrase(
+    node: State<'_, Node>,
+    current_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), FreeVoipError> {
+    node.change_passphrase(&current_passphrase, &new_passphrase)
+        .await
+}
+
+#[tauri::command]
+async fn logout(node: State<'_, Node>, wipe: bool) -> Result<(), FreeVoipError> {
+    node.logout(wipe).await
+}
+
+#[tauri::command]
+async fn delete_identity(node: State<'_, Node>) -> Result<(), FreeVoipError> {
+    node.delete_identity().await
+}
+
+#[tauri::command]
+async fn get_serialized_self_ticket(node: State<'_, Node>) -> Result<Value, FreeVoipError> {
+    let self_ticket = node.self_ticket().await?;
 
     let response = serde_json::json!({
         "nickname": self_ticket.nickname,
-        "serializedTicket": Ticket::serialize(&self_ticket),
+        "serializedTicket": node.serialized_self_ticket().await?,
     });
     Ok(response)
 }
 
 #[tauri::command]
-fn get_contacts(app_handle: AppHandle) -> Result<Vec<ContactTicket>, String> {
-    let contacts_store = app_handle
-        .store("contacts.json")
-        .map_err(|e| e.to_string())?;
+async fn get_self_ticket_qr(
+    node: State<'_, Node>,
+    format: QrFormat,
+) -> Result<Vec<u8>, FreeVoipError> {
+    node.self_ticket_qr(format).await
+}
 
-    contacts_store
-        .get("contacts")
-        .map(|v| serde_json::from_value::<Vec<ContactTicket>>(v).map_err(|e| e.to_string()))
-        .unwrap_or(Ok(vec![]))
+/// Reads the ticket in a QR code image, given either as a file or its contents.
+#[tauri::command]
+fn decode_ticket_qr(
+    node: State<'_, Node>,
+    path: Option<PathBuf>,
+    image: Option<Vec<u8>>,
+) -> Result<String, FreeVoipError> {
+    let image = match (path, image) {
+        (_, Some(image)) => image,
+        (Some(path), None) => std::fs::read(path)?,
+        (None, None) => return Err(FreeVoipError::invalid_state("No image given")),
+    };
+    node.decode_ticket_qr(&image)
 }
 
+/// Issues an invite valid for `valid_for_hours`, returning it serialized for sharing.
 #[tauri::command]
-fn add_contact(app_handle: AppHandle, contact_ticket: ContactTicket) -> Result<(), String> {
-    let mut contacts = get_contacts(app_handle.clone())?;
+async fn create_invite(
+    node: State<'_, Node>,
+    valid_for_hours: u64,
+    max_uses: Option<u32>,
+) -> Result<String, FreeVoipError> {
+    let valid_for = Duration::from_secs(valid_for_hours * 60 * 60);
+    node.create_invite(valid_for, max_uses).await
+}
 
-    // Check for duplicates
-    let duplicate_contact = contacts
-        .iter()
-        .find(|c| c.node_id == contact_ticket.node_id);
-    if let Some(duplicate_contact) = duplicate_contact {
-        return Err(format!(
-            "This contact is already in your list as {}.",
-            duplicate_contact.nickname
-        ));
-    }
+#[tauri::command]
+fn get_invites(node: State<'_, Node>) -> Vec<Invite> {
+    node.invites()
+}
 
-    // Update contacts store
-    contacts.push(contact_ticket);
-    let contacts_store = app_handle
-        .store("contacts.json")
-        .map_err(|e| e.to_string())?;
-    contacts_store.set(
-        "contacts",
-        serde_json::to_value(&contacts).map_err(|e| e.to_string())?,
-    );
-    _ = app_handle.emit("contacts-updated", &contacts);
+#[tauri::command]
+fn revoke_invite(node: State<'_, Node>, id: String) -> Result<bool, FreeVoipError> {
+    node.revoke_invite(&id)
+}
 
-    Ok(())
+#[tauri::command]
+fn parse_deep_link(uri: &str) -> Result<DeepLink, FreeVoipError> {
+    DeepLink::parse(uri)
 }
 
 #[tauri::command]
-async fn send_contact_request(
-    serialized_ticket: String,
-    app_state: State<'_, AppState>,
-) -> Result<(ContactTicket, bool), String> {
-    let contact_ticket = <ContactTicket as Ticket>::deserialize(&serialized_ticket)
-        .map_err(|_e| "Invalid contact ticket")?;
-    println!("Sending contact request to {:?}", contact_ticket);
+fn get_contacts(node: State<'_, Node>) -> Result<Vec<ContactTicket>, FreeVoipError> {
+    node.contacts()
+}
 
-    let app_state = app_state.read().await;
-    let router = app_state.router.as_ref().ok_or("Router not initialized")?;
-    let self_ticket = app_state
-        .endpoint_credentials
-        .as_ref()
-        .map(|c| &c.self_ticket)
-        .ok_or("Credentials not found")?;
+#[tauri::command]
+fn add_contact(node: State<'_, Node>, contact_ticket: ContactTicket) -> Result<(), FreeVoipError> {
+    node.add_contact(contact_ticket)
+}
 
-    let accepted =
-        ContactsProtocol::send_request(router.endpoint(), contact_ticket.node_id, self_ticket)
-            .await?;
-    Ok((contact_ticket, accepted))
+#[tauri::command]
+fn get_call_history(node: State<'_, Node>) -> Result<Vec<CallRecord>, FreeVoipError> {
+    node.call_history()
 }
 
 #[tauri::command]
-async fn respond_to_contact_request(
-    app_state: State<'_, AppState>,
-    accept: bool,
-) -> Result<(), String> {
-    let app_state = app_state.read().await;
-
-    if let Some(ref response_tx) = app_state.contact_response_tx {
-        // Send the response to the contact request
-        response_tx.send(accept).map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err("Contact request response channel not initialized".to_owned())
-    }
+fn clear_call_history(node: State<'_, Node>) -> Result<(), FreeVoipError> {
+    node.clear_call_history()
 }
 
+/// Sends a contact request, returning the recipient's response or `None` if the recipient could
+/// not be reached and the request was queued in the outbox.
 #[tauri::command]
-async fn ring_contact(app_state: State<'_, AppState>, node_addr: NodeId) -> Result<bool, String> {
-    println!("Ringing {node_addr:?}");
-    let app_state = app_state.read().await;
+async fn send_contact_request(
+    node: State<'_, Node>,
+    serialized_ticket: String,
+) -> Result<(ContactTicket, Option<bool>), FreeVoipError> {
+    node.send_contact_request(&serialized_ticket).await
+}
 
-    if let (Some(router), Some(call_protocol)) =
-        (app_state.router.as_ref(), app_state.call_protocol.as_ref())
-    {
-        if let Some(credentials) = app_state.endpoint_credentials.as_ref() {
-            call_protocol
-                .ring(router.endpoint(), node_addr, &credentials.self_ticket)
-                .await
-        } else {
-            Err("Endpoint credentials not found".to_owned())
-        }
-    } else {
-        Err("Router/protocol is not initialized".to_owned())
-    }
+#[tauri::command]
+async fn respond_to_contact_request(
+    node: State<'_, Node>,
+    accept: bool,
+) -> Result<(), FreeVoipError> {
+    node.respond_to_contact_request(accept).await
 }
 
 #[tauri::command]
-async fn respond_to_ring(app_state: State<'_, AppState>, accept: bool) -> Result<(), String> {
-    let app_state = app_state.read().await;
+async fn ring_contact(node: State<'_, Node>, node_addr: NodeId) -> Result<bool, FreeVoipError> {
+    node.ring(node_addr).await
+}
 
-    if let Some(ref response_tx) = app_state.ring_response_tx {
-        response_tx.send(accept).map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err("Ring response channel not initialized".to_owned())
-    }
+#[tauri::command]
+async fn respond_to_ring(node: State<'_, Node>, accept: bool) -> Result<(), FreeVoipError> {
+    node.respond_to_ring(accept).await
 }
 
 #[tauri::command]
-async fn send_call_media(app_state: State<'_, AppState>, media: CallMedia) -> Result<(), String> {
-    let app_state = app_state.read().await;
+async fn send_call_media(node: State<'_, Node>, media: CallMedia) -> Result<(), FreeVoipError> {
+    node.send_call_media(media).await
+}
 
-    if let Some(ref media_tx) = app_state.media_tx {
-        media_tx.send(media).map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err("Media channel not intialized".to_owned())
-    }
+#[tauri::command]
+async fn set_audio_config(node: State<'_, Node>, config: AudioConfig) -> Result<(), FreeVoipError> {
+    node.set_audio_config(config).await
 }
 
 #[tauri::command]
 async fn register_media_channel(
-    app_state: State<'_, AppState>,
+    node: State<'_, Node>,
     on_media_received: Channel<CallMedia>,
-) -> Result<(), String> {
-    let app_state = app_state.read().await;
-    let mut media_rx = app_state
-        .media_rx
-        .as_ref()
-        .ok_or("Media receiver not initialized".to_owned())?
-        .resubscribe();
-
-    tokio::spawn(async move {
+) -> Result<(), FreeVoipError> {
+    let mut media_rx = node.subscribe_call_media().await?;
+
+    tauri::async_runtime::spawn(async move {
         while let Ok(media) = media_rx.recv().await {
             if let Err(e) = on_media_received.send(media) {
-                eprintln!("Failed to send call media to media channel: {}", e);
+                warn!("Failed to send call media to media channel: {e}");
             }
         }
     });
@@ -356,50 +340,205 @@ async fn register_media_channel(
 }
 
 #[tauri::command]
-async fn hang_up(app_state: State<'_, AppState>) -> Result<bool, String> {
-    let app_state = app_state.read().await;
-    let call_proto = app_state
-        .call_protocol
-        .as_ref()
-        .ok_or("Call protocol not initialized".to_owned())?;
-    let disconnected = call_proto.disconnect().await;
-    Ok(disconnected)
+async fn hang_up(node: State<'_, Node>) -> Result<bool, FreeVoipError> {
+    node.hang_up().await
+}
+
+/// Sends a file to a contact, returning whether they accepted it.
+#[tauri::command]
+async fn send_file(
+    node: State<'_, Node>,
+    node_id: NodeId,
+    path: PathBuf,
+) -> Result<bool, FreeVoipError> {
+    node.send_file(node_id, path).await
+}
+
+#[tauri::command]
+async fn get_call_stats(node: State<'_, Node>) -> Result<CallStats, FreeVoipError> {
+    node.call_stats().await
+}
+
+/// Sends a file to the peer of the ongoing call, on the call's connection.
+#[tauri::command]
+async fn send_file_in_call(node: State<'_, Node>, path: PathBuf) -> Result<bool, FreeVoipError> {
+    node.send_file_in_call(path).await
+}
+
+#[tauri::command]
+async fn respond_to_file_offer(node: State<'_, Node>, accept: bool) -> Result<(), FreeVoipError> {
+    node.respond_to_file_offer(accept).await
+}
+
+#[tauri::command]
+async fn start_voicemail(node: State<'_, Node>, node_id: NodeId) -> Result<(), FreeVoipError> {
+    node.start_voicemail(node_id).await
+}
+
+#[tauri::command]
+async fn stop_voicemail(node: State<'_, Node>, send: bool) -> Result<Option<bool>, FreeVoipError> {
+    node.stop_voicemail(send).await
+}
+
+#[tauri::command]
+fn get_voicemails(node: State<'_, Node>) -> Result<Vec<VoicemailInfo>, FreeVoipError> {
+    node.voicemails()
+}
+
+/// Plays a voicemail by sending its audio frames to the same decoder as call media.
+#[tauri::command]
+async fn play_voicemail(
+    node: State<'_, Node>,
+    id: String,
+    on_media_received: Channel<CallMedia>,
+) -> Result<(), FreeVoipError> {
+    node.play_voicemail(&id, |media| {
+        if let Err(e) = on_media_received.send(media) {
+            warn!("Failed to send voicemail media to media channel: {e}");
+        }
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_voicemail(node: State<'_, Node>, id: String) -> Result<(), FreeVoipError> {
+    node.delete_voicemail(&id).await
+}
+
+#[tauri::command]
+async fn start_recording(node: State<'_, Node>) -> Result<PathBuf, FreeVoipError> {
+    node.start_recording().await
+}
+
+#[tauri::command]
+async fn stop_recording(node: State<'_, Node>) -> Result<PathBuf, FreeVoipError> {
+    node.stop_recording().await
+}
+
+#[tauri::command]
+async fn start_echo_test(node: State<'_, Node>) -> Result<(), FreeVoipError> {
+    node.start_echo_test().await
+}
+
+#[tauri::command]
+async fn stop_echo_test(node: State<'_, Node>) -> Result<EchoStats, FreeVoipError> {
+    node.stop_echo_test().await
+}
+
+#[tauri::command]
+fn get_outbox(node: State<'_, Node>, node_id: Option<NodeId>) -> Vec<OutboxItem> {
+    node.outbox(node_id)
+}
+
+#[tauri::command]
+fn remove_outbox_item(node: State<'_, Node>, id: u64) -> Result<bool, FreeVoipError> {
+    node.remove_outbox_item(id)
+}
+
+#[tauri::command]
+async fn export_log_bundle(node: State<'_, Node>) -> Result<PathBuf, FreeVoipError> {
+    node.export_log_bundle().await
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    let builder = tauri::Builder::default();
+    // Must be the first plugin, so that a second instance exits before doing anything else
+    #[cfg(any(target_os = "linux", windows))]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+        if let Some(window) = app.get_webview_window("main") {
+            _ = window.set_focus();
+        }
+    }));
+
+    builder
         .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .setup(|app| {
-            // Initialize empty app state
-            let mut app_state = AppStateInner::default();
-
-            // Populate with stored credentials
-            let credential_store = app.store("credentials.json")?;
-            if let Some(credentials_value) = credential_store.get("endpoint") {
-                let credentials = serde_json::from_value::<EndpointCredentials>(credentials_value)?;
-                app_state.endpoint_credentials = Some(credentials);
+            // Only bundled apps register the scheme on install
+            #[cfg(all(debug_assertions, any(target_os = "linux", windows)))]
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                app.deep_link().register_all()?;
             }
-            credential_store.close_resource();
 
-            app.manage(AppState::new(app_state));
+            let storage = Arc::new(TauriStorage(app.handle().clone()));
+            let config = node_config(app.handle())?;
+            let log_guard = logging::init(&config.log_dir(), true)?;
+            app.manage(log_guard);
+            let node = tauri::async_runtime::block_on(Node::new(storage, config))?;
+
+            // Forward node events to the GUI
+            let mut events = node.events();
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                while let Ok(event) = events.recv().await {
+                    if let Err(e) = app_handle.emit(event.name(), &event) {
+                        error!("Failed to emit {}: {e}", event.name());
+                    }
+                }
+            });
+
+            app.manage(node);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             restore_login,
             login,
+            unlock,
+            change_passphrase,
+            update_profile,
+            set_avatar,
+            get_avatar,
+            get_presence,
+            set_presence,
+            get_presence_settings,
+            set_presence_settings,
+            get_contact_presences,
+            get_network_settings,
+            set_network_settings,
+            get_nearby_devices,
+            export_identity,
+            import_identity,
+            logout,
+            delete_identity,
             get_serialized_self_ticket,
+            get_self_ticket_qr,
+            decode_ticket_qr,
+            create_invite,
+            get_invites,
+            revoke_invite,
+            parse_deep_link,
             get_contacts,
             add_contact,
+            get_call_history,
+            clear_call_history,
             send_contact_request,
             respond_to_contact_request,
             ring_contact,
             respond_to_ring,
             send_call_media,
+            set_audio_config,
             register_media_channel,
             hang_up,
+            send_file,
+            get_call_stats,
+            send_file_in_call,
+            respond_to_file_offer,
+            start_voicemail,
+            stop_voicemail,
+            get_voicemails,
+            play_voicemail,
+            delete_voicemail,
+            start_recording,
+            stop_recording,
+            start_echo_test,
+            stop_echo_test,
+            get_outbox,
+            remove_outbox_item,
+            export_log_bundle,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
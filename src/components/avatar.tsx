@@ -0,0 +1,77 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useCallback, useEffect, useState } from "react";
+import { cn } from "@/lib/utils";
+
+/**
+ * Avatar of a contact, or ours without a `nodeId`, falling back to the first
+ * letter of the nickname. `version` can be bumped to load it again.
+ */
+export function Avatar({
+  nickname,
+  nodeId,
+  version,
+  className,
+}: {
+  nickname: string;
+  nodeId?: string;
+  version?: number;
+  className?: string;
+}) {
+  const [url, setUrl] = useState<string>();
+  const [changes, setChanges] = useState(0);
+
+  const fetchAvatar = useCallback(async () => {
+    try {
+      const image = await invoke<number[] | null>("get_avatar", { nodeId });
+      return image && URL.createObjectURL(new Blob([new Uint8Array(image)]));
+    } catch (error) {
+      // The contact may be offline, the fallback will do
+      console.warn("Unable to get avatar", error);
+      return null;
+    }
+  }, [nodeId]);
+
+  useEffect(() => {
+    let objectUrl: string | null = null;
+    let cancelled = false;
+
+    fetchAvatar().then((result) => {
+      objectUrl = result;
+      if (!cancelled) setUrl(result ?? undefined);
+    });
+    return () => {
+      cancelled = true;
+      if (objectUrl) URL.revokeObjectURL(objectUrl);
+    };
+  }, [fetchAvatar, version, changes]);
+
+  // Contacts tell us when they have a new avatar
+  useEffect(() => {
+    if (!nodeId) return;
+
+    const unlisten = listen<string>("avatar-changed", (event) => {
+      if (event.payload === nodeId) setChanges((c) => c + 1);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [nodeId]);
+
+  return (
+    <div
+      className={cn(
+        "size-10 shrink-0 rounded-full overflow-hidden bg-muted flex items-center justify-center",
+        className,
+      )}
+    >
+      {url ? (
+        <img src={url} alt={nickname} className="size-full object-cover" />
+      ) : (
+        <span className="text-muted-foreground font-medium">
+          {nickname.charAt(0).toUpperCase()}
+        </span>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,47 @@
+import { cn } from "@/lib/utils";
+
+export type PresenceState = "online" | "away" | "busy" | "offline";
+
+export interface Presence {
+  state: PresenceState;
+  statusText: string | null;
+}
+
+export interface PresenceSettings {
+  visibility: "contacts" | "nobody";
+  hiddenFrom: string[];
+}
+
+export const PRESENCE_LABELS: Record<PresenceState, string> = {
+  online: "Online",
+  away: "Away",
+  busy: "Busy",
+  offline: "Offline",
+};
+
+const PRESENCE_COLORS: Record<PresenceState, string> = {
+  online: "bg-green-500",
+  away: "bg-yellow-500",
+  busy: "bg-red-500",
+  offline: "bg-muted-foreground",
+};
+
+/** Small coloured dot showing a presence state. */
+export function PresenceDot({
+  state,
+  className,
+}: {
+  state: PresenceState;
+  className?: string;
+}) {
+  return (
+    <span
+      title={PRESENCE_LABELS[state]}
+      className={cn(
+        "inline-block size-2.5 shrink-0 rounded-full",
+        PRESENCE_COLORS[state],
+        className,
+      )}
+    />
+  );
+}
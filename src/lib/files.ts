@@ -0,0 +1,58 @@
+import { getCurrentWebview } from "@tauri-apps/api/webview";
+import { useEffect } from "react";
+
+export interface FileOffer {
+  name: string;
+  size: number;
+  hash: string;
+}
+
+export type TransferState = "inProgress" | "completed" | { failed: string };
+
+export interface TransferProgress {
+  peer: string;
+  direction: "incoming" | "outgoing";
+  offer: FileOffer;
+  transferred: number;
+  state: TransferState;
+}
+
+/** Size in bytes as a short human readable string, e.g. "1.5 MB". */
+export function formatSize(bytes: number): string {
+  const units = ["B", "KB", "MB", "GB"];
+  let size = bytes;
+  let unit = 0;
+  while (size >= 1000 && unit < units.length - 1) {
+    size /= 1000;
+    unit += 1;
+  }
+  return `${size.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+/**
+ * Calls `onDrop` with the paths of files dropped on the window and the element
+ * under the cursor. The webview only hands out paths through its own drag and
+ * drop events, HTML drops don't have them.
+ */
+export function useFileDrop(
+  onDrop: (paths: string[], target: Element | null) => void,
+  enabled = true,
+) {
+  useEffect(() => {
+    if (!enabled) return;
+
+    const unlisten = getCurrentWebview().onDragDropEvent((event) => {
+      if (event.payload.type !== "drop") return;
+
+      const { x, y } = event.payload.position;
+      const target = document.elementFromPoint(
+        x / window.devicePixelRatio,
+        y / window.devicePixelRatio,
+      );
+      onDrop(event.payload.paths, target);
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [onDrop, enabled]);
+}
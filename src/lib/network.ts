@@ -0,0 +1,11 @@
+export type RelayMode = "default" | "custom" | "disabled";
+
+export type DiscoveryMode = "default" | "disabled";
+
+export interface NetworkSettings {
+  relayMode: RelayMode;
+  relayUrls: string[];
+  discovery: DiscoveryMode;
+  localDiscovery: boolean;
+  hideIp: boolean;
+}
@@ -4,3 +4,40 @@ import { twMerge } from "tailwind-merge";
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs));
 }
+
+export type FreeVoipErrorCode =
+  | "NotLoggedIn"
+  | "PeerUnreachable"
+  | "Declined"
+  | "Timeout"
+  | "InvalidTicket"
+  | "StoreError"
+  | "ProtocolViolation"
+  | "ConnectionLost"
+  | "InvalidState"
+  | "Io"
+  | "Locked"
+  | "WrongPassphrase"
+  | "InvalidBackup";
+
+/** Error returned by every backend command. */
+export type FreeVoipError = {
+  code: FreeVoipErrorCode;
+  message: string;
+};
+
+export function isFreeVoipError(error: unknown): error is FreeVoipError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "code" in error &&
+    "message" in error
+  );
+}
+
+/** Human readable description of an error thrown by `invoke`. */
+export function errorMessage(error: unknown): string {
+  if (isFreeVoipError(error)) return error.message;
+  if (typeof error === "string") return error;
+  return String(error);
+}
@@ -0,0 +1,105 @@
+export interface AudioConfig {
+  sampleRate: number;
+  channels: number;
+}
+
+export interface VoicemailInfo {
+  id: string;
+  sender: { nickname: string; nodeId: string };
+  receivedAt: number;
+  durationMs: number;
+  audioConfig: AudioConfig;
+}
+
+export type EncodedAudio = {
+  audio: {
+    type: "key" | "delta";
+    timestamp: number;
+    duration: number | null;
+    byteLength: number;
+    frameData: Array<number>;
+  };
+};
+
+const AAC_SAMPLE_RATES = [
+  96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025,
+  8000, 7350,
+];
+
+/** AudioSpecificConfig of AAC-LC audio, needed to decode raw AAC frames. */
+function aacDescription({ sampleRate, channels }: AudioConfig): Uint8Array {
+  const index = AAC_SAMPLE_RATES.indexOf(sampleRate);
+  if (index === -1) {
+    // Escape index followed by the rate itself
+    return new Uint8Array([
+      0x17,
+      0x80 | (sampleRate >> 17),
+      (sampleRate >> 9) & 0xff,
+      (sampleRate >> 1) & 0xff,
+      ((sampleRate & 1) << 7) | (channels << 3),
+    ]);
+  }
+  return new Uint8Array([
+    0x10 | (index >> 1),
+    ((index & 1) << 7) | (channels << 3),
+  ]);
+}
+
+/**
+ * Decodes AAC frames and plays them back to back, returning a function that
+ * queues a frame and one that stops playback.
+ */
+export function createAudioPlayer(config: AudioConfig) {
+  const context = new AudioContext({ sampleRate: config.sampleRate });
+  let playAt = 0;
+
+  const decoder = new AudioDecoder({
+    output(data) {
+      const buffer = context.createBuffer(
+        data.numberOfChannels,
+        data.numberOfFrames,
+        data.sampleRate,
+      );
+      for (let channel = 0; channel < data.numberOfChannels; channel++) {
+        const samples = new Float32Array(data.numberOfFrames);
+        data.copyTo(samples, { planeIndex: channel, format: "f32-planar" });
+        buffer.copyToChannel(samples, channel);
+      }
+      data.close();
+
+      const source = context.createBufferSource();
+      source.buffer = buffer;
+      source.connect(context.destination);
+      playAt = Math.max(playAt, context.currentTime);
+      source.start(playAt);
+      playAt += buffer.duration;
+    },
+    error(error) {
+      console.error(`Audio decoding ${error.name} error: ${error.message}`);
+    },
+  });
+  decoder.configure({
+    codec: "mp4a.40.2",
+    sampleRate: config.sampleRate,
+    numberOfChannels: config.channels,
+    description: aacDescription(config),
+  });
+
+  return {
+    play({ audio }: EncodedAudio) {
+      if (decoder.state === "closed") return;
+      decoder.decode(
+        new EncodedAudioChunk({
+          type: audio.type,
+          timestamp: audio.timestamp,
+          duration: audio.duration ?? undefined,
+          data: new Uint8Array(audio.frameData),
+        }),
+      );
+    },
+    stop() {
+      if (decoder.state !== "closed") decoder.close();
+      context.close();
+    },
+  };
+}
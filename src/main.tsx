@@ -13,6 +13,14 @@ const router = createBrowserRouter([
     path: "get-started",
     lazy: () => import("./routes/get-started"),
   },
+  {
+    path: "restore",
+    lazy: () => import("./routes/restore"),
+  },
+  {
+    path: "unlock",
+    lazy: () => import("./routes/unlock"),
+  },
   {
     path: "app",
     children: [
@@ -3,23 +3,50 @@
 import { Channel, invoke } from "@tauri-apps/api/core";
 import { emit, listen, type UnlistenFn } from "@tauri-apps/api/event";
 import {
+  CircleDot,
+  EyeOff,
   Mic,
   MicOff,
   Phone,
+  Send,
+  Square,
   SwitchCamera,
+  Trash,
   Video,
   VideoOff,
+  Voicemail,
 } from "lucide-react";
 import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import Draggable from "react-draggable";
 import { useNavigate, useSearchParams } from "react-router";
 import { toast } from "sonner";
 import { Button } from "@/components/ui/button";
+import { useFileDrop } from "@/lib/files";
+import { errorMessage } from "@/lib/utils";
 
 enum CallState {
   Calling = "Calling",
   Ringing = "Ringing",
   InCall = "In Call",
+  NoAnswer = "No Answer",
+  RecordingVoicemail = "Recording Voicemail",
+}
+
+type CallStats = {
+  directAddr: string | null;
+  relayUrl: string | null;
+  rttMs: number;
+  ipHidden: boolean;
+};
+
+/** How often to refresh the call's connection path. */
+const CALL_STATS_INTERVAL_MS = 2000;
+
+function describePath(stats: CallStats): string {
+  if (stats.directAddr && stats.relayUrl) return "Direct and relayed";
+  if (stats.directAddr) return "Direct";
+  if (stats.relayUrl) return `Relayed via ${new URL(stats.relayUrl).host}`;
+  return "Connecting";
 }
 
 type EncodedPayload = {
@@ -39,6 +66,7 @@ var audioDecodeWorker: Worker | undefined;
 async function setupEncodePipeline(
   videoTrack: MediaStreamTrack,
   audioTrack: MediaStreamTrack,
+  { sendAudio = false }: { sendAudio?: boolean } = {},
 ) {
   videoEncodeWorker = new Worker("/video-encoder.js");
   videoEncodeWorker.onmessage = (event) => {
@@ -61,8 +89,18 @@ async function setupEncodePipeline(
   videoEncodeWorker.onerror = console.error;
 
   audioEncodeWorker = new Worker("/audio-encoder.js");
-  /* TODO: uncomment
+  // TODO: always send audio once the peer plays it, for now only voicemails
+  // need it
   audioEncodeWorker.onmessage = (event) => {
+    if ("decoderConfig" in event.data) {
+      const { sampleRate, numberOfChannels } = event.data.decoderConfig;
+      invoke("set_audio_config", {
+        config: { sampleRate, channels: numberOfChannels },
+      }).catch(console.error);
+      return;
+    }
+    if (!sendAudio) return;
+
     const audioChunk = event.data;
 
     const dataBuffer = new ArrayBuffer(audioChunk.byteLength);
@@ -79,7 +117,6 @@ async function setupEncodePipeline(
     };
     invoke("send_call_media", { media });
   };
-  */
   audioEncodeWorker.onerror = console.error;
 
   const audioCtx = new AudioContext();
@@ -151,11 +188,15 @@ export function Component() {
 
   const eventUnlisteners = useRef<UnlistenFn[]>([]);
   const hasCallStarted = useRef(false);
+  const isRecordingVoicemail = useRef(false);
 
   const [callState, setCallState] = useState<CallState>(CallState.Calling);
   const [isSelfVideoOn, setIsSelfVideoOn] = useState<boolean>(true);
   const [isSelfAudioOn, setIsSelfAudioOn] = useState<boolean>(true);
   const [isPeerVideoOn, setIsPeerVideoOn] = useState<boolean>(false);
+  const [callStats, setCallStats] = useState<CallStats | null>(null);
+  const [isRecording, setIsRecording] = useState<boolean>(false);
+  const [isPeerRecording, setIsPeerRecording] = useState<boolean>(false);
 
   const supportsCameraSwitching = useMemo(
     () => navigator.mediaDevices.getSupportedConstraints().facingMode === true,
@@ -214,11 +255,9 @@ export function Component() {
     } catch (error) {
       console.error("Unable to hang up", error);
 
-      if (typeof error === "string") {
-        toast.error("Unable to hang up", {
-          description: error,
-        });
-      }
+      toast.error("Unable to hang up", {
+        description: errorMessage(error),
+      });
       return;
     }
   }, [exitCall]);
@@ -269,17 +308,17 @@ export function Component() {
 
         if (!response) {
           toast.warning(`${contact.nickname} didn't pick up the call`);
-          exitCall();
+          setCallState(CallState.NoAnswer);
           return;
         }
       } catch (error) {
         console.error("Unable to send ring", error);
 
-        if (typeof error === "string") {
-          toast.error("Unable to send ring", {
-            description: error,
-          });
-        }
+        toast.error("Unable to send ring", {
+          description: errorMessage(error),
+        });
+        // Voicemails for contacts that can't be reached are sent later
+        setCallState(CallState.NoAnswer);
         return;
       }
     }
@@ -292,6 +331,13 @@ export function Component() {
     });
     eventUnlisteners.current.push(unlistenCallHangUp);
 
+    // Let the user know when the peer records the call
+    const unlistenPeerRecording = await listen<boolean>(
+      "peer-recording",
+      (event) => setIsPeerRecording(event.payload),
+    );
+    eventUnlisteners.current.push(unlistenPeerRecording);
+
     // Listen for incoming media
     const peerMediaStream = await setupDecodePipeline();
     peerVideoRef.current.srcObject = peerMediaStream;
@@ -360,6 +406,11 @@ export function Component() {
       if (selfVideoRef.current) cleanUpMediaStream(selfVideoRef.current);
       if (peerVideoRef.current) cleanUpMediaStream(peerVideoRef.current);
 
+      // Leaving the page discards an unfinished voicemail
+      if (isRecordingVoicemail.current) {
+        invoke("stop_voicemail", { send: false }).catch(console.error);
+      }
+
       videoEncodeWorker?.terminate();
       videoEncodeWorker = undefined;
 
@@ -374,6 +425,119 @@ export function Component() {
     };
   }, [startCall, cleanUpMediaStream]);
 
+  const startVoicemail = useCallback(async () => {
+    const stream = selfVideoRef.current?.srcObject as MediaStream | null;
+    if (!stream) return;
+
+    try {
+      await invoke("start_voicemail", { nodeId: contact.nodeId });
+      isRecordingVoicemail.current = true;
+      setCallState(CallState.RecordingVoicemail);
+
+      const [videoTrack] = stream.getVideoTracks();
+      const [audioTrack] = stream.getAudioTracks();
+      await setupEncodePipeline(videoTrack, audioTrack, { sendAudio: true });
+    } catch (error) {
+      console.error("Unable to record voicemail", error);
+
+      toast.error("Unable to record voicemail", {
+        description: errorMessage(error),
+      });
+    }
+  }, [contact]);
+
+  const stopVoicemail = useCallback(
+    async (send: boolean) => {
+      try {
+        isRecordingVoicemail.current = false;
+        const delivered = await invoke<boolean | null>("stop_voicemail", {
+          send,
+        });
+
+        if (send && delivered === null) {
+          toast.warning("Nothing was recorded");
+        } else if (delivered === true) {
+          toast.success(`Voicemail sent to ${contact.nickname}`);
+        } else if (delivered === false) {
+          toast.info(`${contact.nickname} is offline`, {
+            description: "Your voicemail will be sent when they come online.",
+          });
+        }
+      } catch (error) {
+        console.error("Unable to send voicemail", error);
+
+        toast.error("Unable to send voicemail", {
+          description: errorMessage(error),
+        });
+      }
+      exitCall();
+    },
+    [contact, exitCall],
+  );
+
+  const toggleRecording = useCallback(async () => {
+    try {
+      if (isRecording) {
+        const path = await invoke<string>("stop_recording");
+        setIsRecording(false);
+        toast.success("Call recording saved", { description: path });
+      } else {
+        await invoke("start_recording");
+        setIsRecording(true);
+      }
+    } catch (error) {
+      console.error("Unable to toggle call recording", error);
+
+      toast.error(
+        isRecording ? "Unable to stop recording" : "Unable to start recording",
+        { description: errorMessage(error) },
+      );
+    }
+  }, [isRecording]);
+
+  const isOfferingVoicemail =
+    callState === CallState.NoAnswer ||
+    callState === CallState.RecordingVoicemail;
+
+  useEffect(() => {
+    if (callState !== CallState.InCall) return;
+
+    const refresh = () =>
+      invoke<CallStats>("get_call_stats")
+        .then(setCallStats)
+        .catch((error) => console.debug("No call stats", error));
+    refresh();
+    const interval = setInterval(refresh, CALL_STATS_INTERVAL_MS);
+    return () => clearInterval(interval);
+  }, [callState]);
+
+  // Files dropped during a call go over the call's connection
+  const sendFiles = useCallback(
+    async (paths: string[]) => {
+      for (const path of paths) {
+        const name = path.split(/[\\/]/).pop();
+        try {
+          const accepted = await invoke<boolean>("send_file_in_call", {
+            path,
+          });
+          if (accepted) {
+            toast.success(`Sent ${name} to ${contact.nickname}`);
+          } else {
+            toast.warning(`${contact.nickname} declined ${name}`);
+          }
+        } catch (error) {
+          console.error("Unable to send file", error);
+
+          toast.error(`Unable to send ${name}`, {
+            description: errorMessage(error),
+          });
+        }
+      }
+    },
+    [contact],
+  );
+  useFileDrop(sendFiles, callState === CallState.InCall);
+
   return (
     <>
       <Draggable nodeRef={selfVideoRef} bounds="body">
@@ -388,6 +552,29 @@ export function Component() {
         <div className="grow flex relative bg-secondary rounded-xl">
           <video ref={peerVideoRef} />
 
+          {(isRecording || isPeerRecording) && (
+            <div className="absolute left-4 bottom-4 flex flex-row items-center gap-1 text-xs text-destructive">
+              <CircleDot className="size-3" />
+              {isRecording
+                ? "Recording"
+                : `${contact.nickname} is recording this call`}
+            </div>
+          )}
+
+          {callStats && (
+            <div className="absolute left-4 top-4 flex flex-row items-center gap-2 text-xs text-muted-foreground">
+              <span>
+                {describePath(callStats)} · {callStats.rttMs.toFixed(0)} ms
+              </span>
+              {callStats.ipHidden && (
+                <span className="flex flex-row items-center gap-1">
+                  <EyeOff className="size-3" />
+                  IP hidden
+                </span>
+              )}
+            </div>
+          )}
+
           {!isPeerVideoOn && (
             <div className="absolute top-[50%] left-[50%] -translate-[50%] flex flex-col text-center">
               <span className="text-xl font-medium">{contact.nickname}</span>
@@ -398,33 +585,71 @@ export function Component() {
           )}
         </div>
 
-        <div className="backdrop-blur-sm rounded-xl border-secondary border-1 z-20 flex flex-row justify-center items-center gap-4 p-2">
-          {/* Left Group */}
-          <div className="flex flex-1 flex-row justify-end gap-2">
-            <Button variant="ghost" onClick={toggleSelfAudio}>
-              {isSelfAudioOn ? <MicOff /> : <Mic />}
+        {callState === CallState.NoAnswer && (
+          <div className="backdrop-blur-sm rounded-xl border-secondary border-1 z-20 flex flex-row justify-center items-center gap-4 p-2">
+            <Button variant="outline" onClick={exitCall}>
+              Close
+            </Button>
+            <Button onClick={startVoicemail}>
+              <Voicemail />
+              Leave a voicemail
             </Button>
           </div>
+        )}
 
-          {/* Hang Up Button */}
-          <Button variant="destructive" className="flex-none" onClick={hangUp}>
-            <Phone className="m-2" />
-          </Button>
-
-          {/* Right Group */}
-          <div className="flex flex-1 flex-row justify-start gap-2">
-            <Button variant="ghost" onClick={toggleSelfVideo}>
-              {isSelfVideoOn ? <VideoOff /> : <Video />}
+        {callState === CallState.RecordingVoicemail && (
+          <div className="backdrop-blur-sm rounded-xl border-secondary border-1 z-20 flex flex-row justify-center items-center gap-4 p-2">
+            <Button variant="outline" onClick={() => stopVoicemail(false)}>
+              <Trash />
+              Discard
+            </Button>
+            <Button onClick={() => stopVoicemail(true)}>
+              <Send />
+              Send
             </Button>
+          </div>
+        )}
+
+        {!isOfferingVoicemail && (
+          <div className="backdrop-blur-sm rounded-xl border-secondary border-1 z-20 flex flex-row justify-center items-center gap-4 p-2">
+            {/* Left Group */}
+            <div className="flex flex-1 flex-row justify-end gap-2">
+              <Button variant="ghost" onClick={toggleSelfAudio}>
+                {isSelfAudioOn ? <MicOff /> : <Mic />}
+              </Button>
+            </div>
+
+            {/* Hang Up Button */}
             <Button
-              variant="ghost"
-              onClick={flipCamera}
-              disabled={!supportsCameraSwitching}
+              variant="destructive"
+              className="flex-none"
+              onClick={hangUp}
             >
-              <SwitchCamera />
+              <Phone className="m-2" />
             </Button>
+
+            {/* Right Group */}
+            <div className="flex flex-1 flex-row justify-start gap-2">
+              <Button variant="ghost" onClick={toggleSelfVideo}>
+                {isSelfVideoOn ? <VideoOff /> : <Video />}
+              </Button>
+              <Button
+                variant="ghost"
+                onClick={flipCamera}
+                disabled={!supportsCameraSwitching}
+              >
+                <SwitchCamera />
+              </Button>
+              <Button
+                variant="ghost"
+                onClick={toggleRecording}
+                disabled={callState !== CallState.InCall}
+              >
+                {isRecording ? <Square /> : <CircleDot />}
+              </Button>
+            </div>
           </div>
-        </div>
+        )}
       </div>
     </>
   );
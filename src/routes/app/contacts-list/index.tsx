@@ -1,10 +1,23 @@
-import { invoke } from "@tauri-apps/api/core";
+import { Channel, invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { Loader, Plus, VideoIcon } from "lucide-react";
+import {
+  History,
+  Loader,
+  Play,
+  Plus,
+  Radar,
+  Send,
+  Square,
+  Trash,
+  VideoIcon,
+  Voicemail,
+} from "lucide-react";
 import QrScanner from "qr-scanner";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { Link } from "react-router";
 import { toast } from "sonner";
+import { Avatar } from "@/components/avatar";
+import { type Presence, PresenceDot } from "@/components/presence";
 import { Button } from "@/components/ui/button";
 import {
   Dialog,
@@ -16,6 +29,14 @@ import {
   DialogTrigger,
 } from "@/components/ui/dialog";
 import { Input } from "@/components/ui/input";
+import { useFileDrop } from "@/lib/files";
+import type { NetworkSettings } from "@/lib/network";
+import { errorMessage } from "@/lib/utils";
+import {
+  createAudioPlayer,
+  type EncodedAudio,
+  type VoicemailInfo,
+} from "@/lib/voicemail";
 
 interface Contact {
   nickname: string;
@@ -25,15 +46,27 @@ interface Contact {
 function ContactItem({
   nickname,
   nodeId,
+  presence,
 }: {
   nickname: string;
   nodeId: string;
+  presence?: Presence;
 }) {
+  const state = presence?.state ?? "offline";
   return (
-    <div className="flex flex-row w-full justify-between items-center">
+    <div
+      className="flex flex-row w-full justify-between items-center gap-2"
+      data-node-id={nodeId}
+    >
+      <Avatar nickname={nickname} nodeId={nodeId} />
       <div className="flex flex-col max-w-9/12 grow justify-center">
-        <span>{nickname}</span>
-        <span className="text-muted-foreground truncate">{nodeId}</span>
+        <span className="flex items-center gap-2">
+          <PresenceDot state={state} />
+          {nickname}
+        </span>
+        <span className="text-muted-foreground truncate">
+          {presence?.statusText ?? nodeId}
+        </span>
       </div>
 
       <div className="flex flex-row gap-2">
@@ -47,6 +80,485 @@ function ContactItem({
   );
 }
 
+interface NearbyDevice {
+  nodeId: string;
+  nickname: string | null;
+}
+
+function NearbyDialog() {
+  const [devices, setDevices] = useState<NearbyDevice[]>([]);
+  const [settings, setSettings] = useState<NetworkSettings>();
+
+  useEffect(() => {
+    invoke<NearbyDevice[]>("get_nearby_devices")
+      .then(setDevices)
+      .catch((error) => console.error("Error fetching nearby devices:", error));
+    invoke<NetworkSettings>("get_network_settings")
+      .then(setSettings)
+      .catch((error) =>
+        console.error("Error fetching network settings:", error),
+      );
+
+    const unlisten = listen<NearbyDevice[]>(
+      "nearby-devices-changed",
+      (event) => setDevices(event.payload),
+    );
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const setLocalDiscovery = useCallback(
+    async (localDiscovery: boolean) => {
+      if (!settings) return;
+
+      const newSettings = { ...settings, localDiscovery };
+      try {
+        await invoke("set_network_settings", { settings: newSettings });
+        setSettings(newSettings);
+      } catch (error) {
+        console.error("Unable to save network settings", error);
+
+        toast.error("Unable to save network settings", {
+          description: errorMessage(error),
+        });
+      }
+    },
+    [settings],
+  );
+
+  return (
+    <Dialog>
+      <DialogTrigger asChild>
+        <Button variant="outline">
+          <Radar />
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Nearby Devices</DialogTitle>
+          <DialogDescription>
+            Devices running Free VoIP on your local network. Contacts among
+            them can be called without internet access.
+          </DialogDescription>
+        </DialogHeader>
+
+        {devices.length === 0 ? (
+          <p className="text-muted-foreground text-center">
+            No devices found
+          </p>
+        ) : (
+          <div className="flex flex-col gap-2">
+            {devices.map((device) => (
+              <div
+                key={device.nodeId}
+                className="flex flex-row justify-between items-center gap-2"
+              >
+                <div className="flex flex-col min-w-0">
+                  <span>{device.nickname ?? "Unknown device"}</span>
+                  <span className="text-muted-foreground text-sm truncate">
+                    {device.nodeId}
+                  </span>
+                </div>
+                {device.nickname !== null && (
+                  <Button asChild>
+                    <Link
+                      to={`call?nickname=${device.nickname}&nodeId=${device.nodeId}`}
+                    >
+                      <VideoIcon />
+                    </Link>
+                  </Button>
+                )}
+              </div>
+            ))}
+          </div>
+        )}
+
+        {settings && (
+          <label className="flex items-center gap-2 text-sm">
+            <input
+              type="checkbox"
+              checked={settings.localDiscovery}
+              onChange={(e) => setLocalDiscovery(e.target.checked)}
+            />
+            Find and be found by devices on the local network
+          </label>
+        )}
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+type OutboxPayload =
+  | { contactRequest: { contact: Contact } }
+  | { missedCall: { timestamp: number } }
+  | { voicemail: { path: string } }
+  | "profileUpdate";
+
+interface OutboxItem {
+  id: number;
+  recipient: string;
+  payload: OutboxPayload;
+  state: "pending" | "sent" | "delivered";
+  createdAt: number;
+  attempts: number;
+  accepted: boolean | null;
+}
+
+const DELIVERY_STATE_LABELS: Record<OutboxItem["state"], string> = {
+  pending: "Pending",
+  sent: "Sent",
+  delivered: "Delivered",
+};
+
+function describeOutboxItem(item: OutboxItem): string {
+  if (item.payload === "profileUpdate") return "Profile update";
+  if ("contactRequest" in item.payload) {
+    if (item.accepted === true) return "Contact request, accepted";
+    if (item.accepted === false) return "Contact request, declined";
+    return "Contact request";
+  }
+  if ("missedCall" in item.payload) return "Missed call notice";
+  return "Voicemail";
+}
+
+function OutboxDialog({ contacts }: { contacts: Contact[] }) {
+  const [items, setItems] = useState<OutboxItem[]>([]);
+
+  useEffect(() => {
+    invoke<OutboxItem[]>("get_outbox")
+      .then(setItems)
+      .catch((error) => console.error("Error fetching outbox:", error));
+
+    const unlisten = listen<OutboxItem[]>("outbox-updated", (event) =>
+      setItems(event.payload),
+    );
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const recipientName = useCallback(
+    (item: OutboxItem) => {
+      const { payload } = item;
+      if (typeof payload === "object" && "contactRequest" in payload) {
+        return payload.contactRequest.contact.nickname;
+      }
+      return (
+        contacts.find((c) => c.nodeId === item.recipient)?.nickname ??
+        item.recipient
+      );
+    },
+    [contacts],
+  );
+
+  const removeItem = useCallback(async (id: number) => {
+    try {
+      await invoke("remove_outbox_item", { id });
+    } catch (error) {
+      console.error("Unable to remove outbox item", error);
+
+      toast.error("Unable to remove outbox item", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const sortedItems = [...items].sort((a, b) => b.createdAt - a.createdAt);
+
+  return (
+    <Dialog>
+      <DialogTrigger asChild>
+        <Button variant="outline">
+          <Send />
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Outbox</DialogTitle>
+          <DialogDescription>
+            Requests and notices for contacts that were offline. They are sent
+            as soon as the contact comes online.
+          </DialogDescription>
+        </DialogHeader>
+
+        {sortedItems.length === 0 ? (
+          <p className="text-muted-foreground text-center">Nothing queued</p>
+        ) : (
+          <div className="flex flex-col gap-2">
+            {sortedItems.map((item) => (
+              <div
+                key={item.id}
+                className="flex flex-row justify-between items-center gap-2"
+              >
+                <div className="flex flex-col min-w-0">
+                  <span className="truncate">{recipientName(item)}</span>
+                  <span className="text-muted-foreground text-sm">
+                    {describeOutboxItem(item)} ·{" "}
+                    {DELIVERY_STATE_LABELS[item.state]}
+                  </span>
+                </div>
+                {item.state !== "delivered" && (
+                  <Button variant="ghost" onClick={() => removeItem(item.id)}>
+                    <Trash />
+                  </Button>
+                )}
+              </div>
+            ))}
+          </div>
+        )}
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+type CallOutcome =
+  | "answered"
+  | "declined"
+  | "missed"
+  | "busy"
+  | "cancelled"
+  | { failed: string };
+
+interface CallRecord {
+  peer: string;
+  direction: "incoming" | "outgoing";
+  startedAt: number;
+  answeredAt: number | null;
+  endedAt: number;
+  duration: number;
+  outcome: CallOutcome;
+}
+
+function describeCall(record: CallRecord): string {
+  const direction = record.direction === "incoming" ? "Incoming" : "Outgoing";
+  const { outcome } = record;
+  if (typeof outcome === "object") return `${direction}, failed`;
+  if (outcome === "answered") {
+    const minutes = Math.floor(record.duration / 60);
+    const seconds = String(record.duration % 60).padStart(2, "0");
+    return `${direction}, ${minutes}:${seconds}`;
+  }
+  return `${direction}, ${outcome}`;
+}
+
+function CallHistoryDialog({ contacts }: { contacts: Contact[] }) {
+  const [history, setHistory] = useState<CallRecord[]>([]);
+
+  useEffect(() => {
+    invoke<CallRecord[]>("get_call_history")
+      .then(setHistory)
+      .catch((error) => console.error("Error fetching call history:", error));
+
+    const unlisten = listen<CallRecord[]>("call-history-updated", (event) =>
+      setHistory(event.payload),
+    );
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const clearHistory = useCallback(async () => {
+    try {
+      await invoke("clear_call_history");
+    } catch (error) {
+      console.error("Unable to clear call history", error);
+
+      toast.error("Unable to clear call history", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const sortedHistory = [...history].sort((a, b) => b.startedAt - a.startedAt);
+
+  return (
+    <Dialog>
+      <DialogTrigger asChild>
+        <Button variant="outline">
+          <History />
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Call History</DialogTitle>
+          <DialogDescription>Calls made and received.</DialogDescription>
+        </DialogHeader>
+
+        {sortedHistory.length === 0 ? (
+          <p className="text-muted-foreground text-center">No calls yet</p>
+        ) : (
+          <div className="flex flex-col gap-2 max-h-96 overflow-y-auto">
+            {sortedHistory.map((record) => {
+              const nickname =
+                contacts.find((c) => c.nodeId === record.peer)?.nickname ??
+                record.peer;
+              return (
+                <div
+                  key={`${record.peer}-${record.startedAt}`}
+                  className="flex flex-row justify-between items-center gap-2"
+                >
+                  <div className="flex flex-col min-w-0">
+                    <span className="truncate">{nickname}</span>
+                    <span className="text-muted-foreground text-sm">
+                      {describeCall(record)} ·{" "}
+                      {new Date(record.startedAt * 1000).toLocaleString()}
+                    </span>
+                  </div>
+                  <Button variant="ghost" asChild>
+                    <Link
+                      to={`call?nickname=${nickname}&nodeId=${record.peer}`}
+                    >
+                      <VideoIcon />
+                    </Link>
+                  </Button>
+                </div>
+              );
+            })}
+          </div>
+        )}
+
+        {sortedHistory.length > 0 && (
+          <DialogFooter>
+            <Button variant="outline" onClick={clearHistory}>
+              <Trash />
+              Clear
+            </Button>
+          </DialogFooter>
+        )}
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function VoicemailDialog() {
+  const [voicemails, setVoicemails] = useState<VoicemailInfo[]>([]);
+  const [playingId, setPlayingId] = useState<string | null>(null);
+  const player = useRef<ReturnType<typeof createAudioPlayer> | null>(null);
+
+  useEffect(() => {
+    invoke<VoicemailInfo[]>("get_voicemails")
+      .then(setVoicemails)
+      .catch((error) => console.error("Error fetching voicemails:", error));
+
+    const unlisten = listen<VoicemailInfo>("voicemail-received", (event) => {
+      setVoicemails((voicemails) => [...voicemails, event.payload]);
+      toast.info(`New voicemail from ${event.payload.sender.nickname}`);
+    });
+    return () => {
+      unlisten.then((f) => f());
+      player.current?.stop();
+    };
+  }, []);
+
+  const stopPlaying = useCallback(() => {
+    player.current?.stop();
+    player.current = null;
+    setPlayingId(null);
+  }, []);
+
+  const playVoicemail = useCallback(async (voicemail: VoicemailInfo) => {
+    player.current?.stop();
+    const current = createAudioPlayer(voicemail.audioConfig);
+    player.current = current;
+    setPlayingId(voicemail.id);
+
+    const onMediaReceived = new Channel<EncodedAudio>();
+    onMediaReceived.onmessage = (media) => current.play(media);
+    try {
+      await invoke("play_voicemail", { id: voicemail.id, onMediaReceived });
+    } catch (error) {
+      console.error("Unable to play voicemail", error);
+
+      toast.error("Unable to play voicemail", {
+        description: errorMessage(error),
+      });
+    } finally {
+      setPlayingId((id) => (id === voicemail.id ? null : id));
+    }
+  }, []);
+
+  const deleteVoicemail = useCallback(async (id: string) => {
+    try {
+      await invoke("delete_voicemail", { id });
+      setVoicemails((voicemails) => voicemails.filter((v) => v.id !== id));
+    } catch (error) {
+      console.error("Unable to delete voicemail", error);
+
+      toast.error("Unable to delete voicemail", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const sortedVoicemails = [...voicemails].sort(
+    (a, b) => b.receivedAt - a.receivedAt,
+  );
+
+  return (
+    <Dialog onOpenChange={(open) => !open && stopPlaying()}>
+      <DialogTrigger asChild>
+        <Button variant="outline">
+          <Voicemail />
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Voicemails</DialogTitle>
+          <DialogDescription>
+            Messages left by contacts who couldn't reach you.
+          </DialogDescription>
+        </DialogHeader>
+
+        {sortedVoicemails.length === 0 ? (
+          <p className="text-muted-foreground text-center">No voicemails</p>
+        ) : (
+          <div className="flex flex-col gap-2 max-h-96 overflow-y-auto">
+            {sortedVoicemails.map((voicemail) => (
+              <div
+                key={voicemail.id}
+                className="flex flex-row justify-between items-center gap-2"
+              >
+                <div className="flex flex-col min-w-0">
+                  <span className="truncate">{voicemail.sender.nickname}</span>
+                  <span className="text-muted-foreground text-sm">
+                    {Math.round(voicemail.durationMs / 1000)} s ·{" "}
+                    {new Date(voicemail.receivedAt * 1000).toLocaleString()}
+                  </span>
+                </div>
+                <div className="flex flex-row gap-2">
+                  {playingId === voicemail.id ? (
+                    <Button variant="ghost" onClick={stopPlaying}>
+                      <Square />
+                    </Button>
+                  ) : (
+                    <Button
+                      variant="ghost"
+                      onClick={() => playVoicemail(voicemail)}
+                    >
+                      <Play />
+                    </Button>
+                  )}
+                  <Button
+                    variant="ghost"
+                    onClick={() => deleteVoicemail(voicemail.id)}
+                  >
+                    <Trash />
+                  </Button>
+                </div>
+              </div>
+            ))}
+          </div>
+        )}
+      </DialogContent>
+    </Dialog>
+  );
+}
+
 function AddContactDialog({
   open,
   onOpenChange,
@@ -83,14 +595,22 @@ function AddContactDialog({
       setIsLoading(true);
 
       try {
-        const [contact, accepted] = await invoke<[Contact, boolean]>(
+        const [contact, accepted] = await invoke<[Contact, boolean | null]>(
           "send_contact_request",
           {
             serializedTicket,
           },
         );
 
-        if (accepted) {
+        if (accepted === null) {
+          // Recipient is offline, request will be delivered later
+          toast.info(`${contact.nickname} is offline`, {
+            description:
+              "Your contact request will be sent when they come online.",
+          });
+          onOpenChange(false);
+          setTicket("");
+        } else if (accepted) {
           // Accepted
           toast.success(`${contact.nickname} accepted your contact request`);
           onOpenChange(false);
@@ -102,11 +622,9 @@ function AddContactDialog({
           } catch (error) {
             console.error("Unable to add contact", error);
 
-            if (typeof error === "string") {
-              toast.error("Unable to add contact", {
-                description: error,
-              });
-            }
+            toast.error("Unable to add contact", {
+              description: errorMessage(error),
+            });
           }
         } else {
           // Rejected
@@ -115,11 +633,9 @@ function AddContactDialog({
       } catch (error) {
         console.error("Unable to send contact request", error);
 
-        if (typeof error === "string") {
-          toast.error("Unable to send contact request", {
-            description: error,
-          });
-        }
+        toast.error("Unable to send contact request", {
+          description: errorMessage(error),
+        });
       } finally {
         setIsLoading(false);
       }
@@ -133,6 +649,24 @@ function AddContactDialog({
     await sendRequest(trimmedTicket);
   }, [sendRequest, ticket]);
 
+  const onImageChosen = useCallback(
+    async (file: File | undefined) => {
+      if (!file) return;
+
+      try {
+        const image = Array.from(new Uint8Array(await file.arrayBuffer()));
+        await sendRequest(await invoke<string>("decode_ticket_qr", { image }));
+      } catch (error) {
+        console.error("Unable to read QR code", error);
+
+        toast.error("Unable to read QR code", {
+          description: errorMessage(error),
+        });
+      }
+    },
+    [sendRequest],
+  );
+
   const onScanClicked = useCallback(() => {
     if (!videoRef.current) return;
 
@@ -164,8 +698,8 @@ function AddContactDialog({
         <DialogHeader>
           <DialogTitle>Add Contact</DialogTitle>
           <DialogDescription>
-            Add a new contact by scanning a <b>Contact Ticket</b> or by entering
-            one manually.
+            Add a new contact by scanning a <b>Contact Ticket</b> or an{" "}
+            <b>Invite</b>, or by entering one manually.
           </DialogDescription>
         </DialogHeader>
 
@@ -173,7 +707,7 @@ function AddContactDialog({
           type="text"
           value={ticket}
           onChange={(e) => setTicket(e.target.value)}
-          placeholder="Contact Ticket"
+          placeholder="Contact Ticket or Invite"
           disabled={isLoading}
           autoFocus={false}
         />
@@ -200,6 +734,18 @@ function AddContactDialog({
             Scan
           </Button>
 
+          <Button variant="outline" disabled={isLoading} asChild>
+            <label>
+              From image
+              <input
+                type="file"
+                accept="image/png,image/jpeg"
+                className="hidden"
+                onChange={(e) => onImageChosen(e.target.files?.[0])}
+              />
+            </label>
+          </Button>
+
           <Button
             onClick={onManualEntrySubmitted}
             disabled={isLoading || ticket.trim().length === 0}
@@ -216,6 +762,7 @@ export function Component() {
   const [isLoading, setIsLoading] = useState(true);
   const [contacts, setContacts] = useState<Contact[]>([]);
   const [addDialogOpen, setAddDialogOpen] = useState(false);
+  const [presences, setPresences] = useState<Record<string, Presence>>({});
 
   const fetchContacts = useCallback(async () => {
     try {
@@ -225,9 +772,9 @@ export function Component() {
     } catch (error) {
       console.error("Error fetching contacts:", error);
 
-      if (typeof error === "string") {
-        toast.error("Unable to fetch contacts", { description: error });
-      }
+      toast.error("Unable to fetch contacts", {
+        description: errorMessage(error),
+      });
     }
   }, []);
   useEffect(() => {
@@ -238,15 +785,73 @@ export function Component() {
     });
   }, [fetchContacts]);
 
+  useEffect(() => {
+    // Contacts are probed in the background, so start from what is known
+    invoke<Record<string, Presence>>("get_contact_presences")
+      .then(setPresences)
+      .catch((error) => console.error("Error fetching presences:", error));
+
+    const unlisten = listen<{ nodeId: string; presence: Presence }>(
+      "presence-changed",
+      (event) => {
+        const { nodeId, presence } = event.payload;
+        setPresences((presences) => ({ ...presences, [nodeId]: presence }));
+      },
+    );
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  const sendFiles = useCallback(
+    async (paths: string[], target: Element | null) => {
+      // Files are sent to the contact they were dropped on
+      const nodeId = target
+        ?.closest("[data-node-id]")
+        ?.getAttribute("data-node-id");
+      const contact = contacts.find((c) => c.nodeId === nodeId);
+      if (!contact) return;
+
+      for (const path of paths) {
+        const name = path.split(/[\\/]/).pop();
+        try {
+          const accepted = await invoke<boolean>("send_file", {
+            nodeId: contact.nodeId,
+            path,
+          });
+          if (accepted) {
+            toast.success(`Sent ${name} to ${contact.nickname}`);
+          } else {
+            toast.warning(`${contact.nickname} declined ${name}`);
+          }
+        } catch (error) {
+          console.error("Unable to send file", error);
+
+          toast.error(`Unable to send ${name}`, {
+            description: errorMessage(error),
+          });
+        }
+      }
+    },
+    [contacts],
+  );
+  useFileDrop(sendFiles);
+
   return (
     <div className="size-full">
       <h2 className="w-full flex flex-row justify-between">
         <span>Contacts</span>
 
-        <AddContactDialog
-          open={addDialogOpen}
-          onOpenChange={setAddDialogOpen}
-        />
+        <span className="flex flex-row gap-2">
+          <NearbyDialog />
+          <CallHistoryDialog contacts={contacts} />
+          <VoicemailDialog />
+          <OutboxDialog contacts={contacts} />
+          <AddContactDialog
+            open={addDialogOpen}
+            onOpenChange={setAddDialogOpen}
+          />
+        </span>
       </h2>
 
       {isLoading ? (
@@ -256,8 +861,15 @@ export function Component() {
       ) : (
         <div className="size-full flex flex-col gap-4 px-2 justify-start items-center-safe">
           {contacts.map((contact) => (
-            <ContactItem key={contact.nodeId} {...contact} />
+            <ContactItem
+              key={contact.nodeId}
+              presence={presences[contact.nodeId]}
+              {...contact}
+            />
           ))}
+          <p className="text-muted-foreground text-sm">
+            Drop a file on a contact to send it to them
+          </p>
         </div>
       )}
     </div>
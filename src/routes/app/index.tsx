@@ -1,5 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
+import { getCurrent, onOpenUrl } from "@tauri-apps/plugin-deep-link";
 import clsx from "clsx";
 import { DynamicIcon, type IconName } from "lucide-react/dynamic";
 import { useCallback, useEffect, useMemo, useState } from "react";
@@ -20,12 +21,29 @@ import {
   NavigationMenuLink,
   NavigationMenuList,
 } from "@/components/ui/navigation-menu";
+import {
+  type FileOffer,
+  formatSize,
+  type TransferProgress,
+} from "@/lib/files";
+import { errorMessage } from "@/lib/utils";
 
 interface ContactRequest {
   nickname: string;
   nodeId: string;
 }
 
+interface IncomingFileOffer {
+  peer: string;
+  offer: FileOffer;
+  /** Nickname of the sender if they are a contact. */
+  nickname?: string;
+}
+
+type DeepLink =
+  | { kind: "add"; ticket: ContactRequest; serializedTicket: string }
+  | { kind: "call"; nodeId: string };
+
 const showNavigationIn = new Set(["/app/my-card", "/app/contacts-list"]);
 
 function NavLink({
@@ -73,6 +91,7 @@ export function Component() {
   const navigate = useNavigate();
   const [contactRequest, setContactRequest] = useState<ContactRequest>();
   const [ringRequest, setRingRequest] = useState<ContactRequest>();
+  const [fileOffer, setFileOffer] = useState<IncomingFileOffer>();
 
   const showNavigation = useMemo(
     () => showNavigationIn.has(location.pathname),
@@ -86,8 +105,106 @@ export function Component() {
     listen<ContactRequest>("ring-request", (event) => {
       setRingRequest(event.payload);
     });
+    listen<ContactRequest>("invite-redeemed", (event) => {
+      toast.success(`${event.payload.nickname} joined with your invite`);
+    });
+    listen<IncomingFileOffer>("file-offer", async (event) => {
+      const contacts = await invoke<ContactRequest[]>("get_contacts").catch(
+        () => [],
+      );
+      const nickname = contacts.find(
+        (c) => c.nodeId === event.payload.peer,
+      )?.nickname;
+      setFileOffer({ ...event.payload, nickname });
+    });
+    listen<TransferProgress>("file-transfer-progress", (event) => {
+      const { direction, offer, state } = event.payload;
+      if (direction !== "incoming" || state === "inProgress") return;
+
+      if (state === "completed") {
+        toast.success(`Received ${offer.name}`);
+      } else {
+        toast.error(`Unable to receive ${offer.name}`, {
+          description: state.failed,
+        });
+      }
+    });
+    // Recordings stopped by hanging up are saved after leaving the call
+    listen<string>("call-recording-saved", (event) => {
+      toast.success("Call recording saved", { description: event.payload });
+    });
+    listen<{ peer: string }>("missed-call", (event) => {
+      // Ring timed out or the caller gave up. Notices of calls missed while
+      // offline arrive late, so leave other rings alone.
+      setRingRequest((ring) =>
+        ring?.nodeId === event.payload.peer ? undefined : ring,
+      );
+    });
   }, []);
 
+  const openDeepLink = useCallback(
+    async (uri: string) => {
+      let link: DeepLink;
+      try {
+        link = await invoke<DeepLink>("parse_deep_link", { uri });
+      } catch (error) {
+        console.error("Unable to open link", error);
+
+        toast.error("Unable to open link", {
+          description: errorMessage(error),
+        });
+        return;
+      }
+
+      if (link.kind === "call") {
+        const contacts = await invoke<ContactRequest[]>("get_contacts");
+        const nickname =
+          contacts.find((c) => c.nodeId === link.nodeId)?.nickname ??
+          link.nodeId;
+        navigate(
+          `/app/contacts-list/call?nickname=${nickname}&nodeId=${link.nodeId}`,
+        );
+        return;
+      }
+
+      try {
+        const [contact, accepted] = await invoke<
+          [ContactRequest, boolean | null]
+        >("send_contact_request", {
+          serializedTicket: link.serializedTicket,
+        });
+
+        if (accepted === null) {
+          toast.info(`${contact.nickname} is offline`, {
+            description:
+              "Your contact request will be sent when they come online.",
+          });
+        } else if (accepted) {
+          toast.success(`${contact.nickname} accepted your contact request`);
+          await invoke("add_contact", { contactTicket: contact });
+        } else {
+          toast.warning(`${contact.nickname} rejected your contact request`);
+        }
+      } catch (error) {
+        console.error("Unable to send contact request", error);
+
+        toast.error("Unable to send contact request", {
+          description: errorMessage(error),
+        });
+      }
+    },
+    [navigate],
+  );
+
+  useEffect(() => {
+    // Links that started the app, then the ones opened while it runs
+    getCurrent().then((uris) => uris?.forEach(openDeepLink));
+    const unlisten = onOpenUrl((uris) => uris.forEach(openDeepLink));
+    return () => {
+      unlisten.then((unlisten) => unlisten());
+    };
+  }, [openDeepLink]);
+
   const respondToContactRequest = useCallback(
     async (accept: boolean) => {
       // Acknowledge the request
@@ -96,11 +213,9 @@ export function Component() {
       } catch (error) {
         console.error("Unable to respond to contact request", error);
 
-        if (typeof error === "string") {
-          toast.error("Unable to respond to contact request", {
-            description: error,
-          });
-        }
+        toast.error("Unable to respond to contact request", {
+          description: errorMessage(error),
+        });
         return;
       }
 
@@ -111,11 +226,9 @@ export function Component() {
         } catch (error) {
           console.error("Unable to add contact", error);
 
-          if (typeof error === "string") {
-            toast.error("Unable to add contact", {
-              description: error,
-            });
-          }
+          toast.error("Unable to add contact", {
+            description: errorMessage(error),
+          });
         }
       }
 
@@ -131,11 +244,9 @@ export function Component() {
       } catch (error) {
         console.error("Unable to respond to ring", error);
 
-        if (typeof error === "string") {
-          toast.error("Unable to respond to ring", {
-            description: error,
-          });
-        }
+        toast.error("Unable to respond to ring", {
+          description: errorMessage(error),
+        });
         return;
       }
 
@@ -150,6 +261,21 @@ export function Component() {
     [navigate, ringRequest],
   );
 
+  const respondToFileOffer = useCallback(async (accept: boolean) => {
+    // Offers that waited too long were declined already, answering is harmless
+    try {
+      await invoke("respond_to_file_offer", { accept });
+    } catch (error) {
+      console.error("Unable to respond to file offer", error);
+
+      toast.error("Unable to respond to file offer", {
+        description: errorMessage(error),
+      });
+    }
+
+    setFileOffer(undefined);
+  }, []);
+
   return (
     <>
       <div className="size-full flex flex-col gap-6">
@@ -231,6 +357,36 @@ export function Component() {
           </DialogFooter>
         </DialogContent>
       </Dialog>
+
+      <Dialog
+        open={!!fileOffer && !ringRequest && !contactRequest}
+        onOpenChange={(open) => {
+          if (!open) {
+            respondToFileOffer(false);
+          }
+        }}
+      >
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>Incoming File</DialogTitle>
+            <DialogDescription>
+              <b>{fileOffer?.nickname ?? fileOffer?.peer}</b> wants to send you{" "}
+              <b>{fileOffer?.offer.name}</b> (
+              {formatSize(fileOffer?.offer.size ?? 0)}).
+            </DialogDescription>
+          </DialogHeader>
+
+          <DialogFooter>
+            <Button
+              variant="destructive"
+              onClick={() => respondToFileOffer(false)}
+            >
+              Decline
+            </Button>
+            <Button onClick={() => respondToFileOffer(true)}>Accept</Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
     </>
   );
 }
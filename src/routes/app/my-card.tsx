@@ -1,17 +1,795 @@
 import { invoke } from "@tauri-apps/api/core";
 import { writeText } from "@tauri-apps/plugin-clipboard-manager";
-import { Copy, Loader } from "lucide-react";
+import {
+  Copy,
+  DatabaseBackup,
+  FileText,
+  KeyRound,
+  Loader,
+  ImageUp,
+  LogOut,
+  Network,
+  Pencil,
+  Trash2,
+  UserPlus,
+  X,
+} from "lucide-react";
 import { useCallback, useEffect, useState } from "react";
 import QRCode from "react-qr-code";
+import { useNavigate } from "react-router";
 import { toast } from "sonner";
+import { Avatar } from "@/components/avatar";
+import {
+  PRESENCE_LABELS,
+  type Presence,
+  type PresenceSettings,
+  type PresenceState,
+  PresenceDot,
+} from "@/components/presence";
 import { Button } from "@/components/ui/button";
+import {
+  Dialog,
+  DialogClose,
+  DialogContent,
+  DialogDescription,
+  DialogFooter,
+  DialogHeader,
+  DialogTitle,
+  DialogTrigger,
+} from "@/components/ui/dialog";
+import { Input } from "@/components/ui/input";
+import type { NetworkSettings, RelayMode } from "@/lib/network";
+import { errorMessage } from "@/lib/utils";
 
 interface SerializedTicketResponse {
   nickname: string;
   serializedTicket: string;
 }
 
+interface Invite {
+  id: string;
+  expiresAt: number;
+  maxUses: number | null;
+  uses: number;
+}
+
+function InviteDialog() {
+  const [invite, setInvite] = useState<string>();
+  const [invites, setInvites] = useState<Invite[]>([]);
+  const [validForHours, setValidForHours] = useState(24);
+  const [maxUses, setMaxUses] = useState(1);
+
+  const fetchInvites = useCallback(async () => {
+    try {
+      setInvites(await invoke<Invite[]>("get_invites"));
+    } catch (error) {
+      console.error("Unable to get invites", error);
+    }
+  }, []);
+
+  const onCreateClicked = useCallback(async () => {
+    try {
+      setInvite(
+        await invoke<string>("create_invite", { validForHours, maxUses }),
+      );
+      fetchInvites();
+    } catch (error) {
+      console.error("Unable to create invite", error);
+
+      toast.error("Unable to create invite", {
+        description: errorMessage(error),
+      });
+    }
+  }, [validForHours, maxUses, fetchInvites]);
+
+  const onRevokeClicked = useCallback(
+    async (id: string) => {
+      try {
+        await invoke("revoke_invite", { id });
+        fetchInvites();
+      } catch (error) {
+        console.error("Unable to revoke invite", error);
+
+        toast.error("Unable to revoke invite", {
+          description: errorMessage(error),
+        });
+      }
+    },
+    [fetchInvites],
+  );
+
+  const onCopyClicked = useCallback(async () => {
+    if (!invite) return;
+
+    try {
+      await writeText(invite);
+      toast.success("Invite copied to clipboard");
+    } catch (error) {
+      console.error("Unable to copy invite to clipboard", error);
+
+      toast.error("Unable to copy invite to clipboard", {
+        description: errorMessage(error),
+      });
+    }
+  }, [invite]);
+
+  return (
+    <Dialog
+      onOpenChange={(open) => {
+        setInvite(undefined);
+        if (open) fetchInvites();
+      }}
+    >
+      <DialogTrigger asChild>
+        <Button variant="ghost">
+          <UserPlus />
+          Invite someone
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Invite Someone</DialogTitle>
+          <DialogDescription>
+            Contact requests that come with an invite are accepted without
+            asking, until it expires or has been used up.
+          </DialogDescription>
+        </DialogHeader>
+
+        {invite ? (
+          <>
+            <QRCode value={invite} className="p-2 size-full bg-white" />
+            <div className="flex justify-between items-center">
+              <span className="text-muted-foreground text-sm truncate">
+                {invite}
+              </span>
+              <Button variant="ghost" onClick={onCopyClicked}>
+                <Copy />
+              </Button>
+            </div>
+          </>
+        ) : (
+          <div className="flex gap-2 items-center text-sm">
+            <span>Valid for</span>
+            <Input
+              type="number"
+              min={1}
+              className="w-20"
+              value={validForHours}
+              onChange={(e) => setValidForHours(Number(e.target.value))}
+            />
+            <span>hours, for</span>
+            <Input
+              type="number"
+              min={1}
+              className="w-20"
+              value={maxUses}
+              onChange={(e) => setMaxUses(Number(e.target.value))}
+            />
+            <span>people</span>
+          </div>
+        )}
+
+        {invites.length > 0 && (
+          <div className="flex flex-col gap-1 text-sm">
+            <span className="text-muted-foreground">Active invites</span>
+            {invites.map((i) => (
+              <div key={i.id} className="flex justify-between items-center">
+                <span>
+                  {i.uses}/{i.maxUses ?? 1} used, expires{" "}
+                  {new Date(i.expiresAt * 1000).toLocaleString()}
+                </span>
+                <Button
+                  variant="ghost"
+                  size="icon"
+                  onClick={() => onRevokeClicked(i.id)}
+                >
+                  <X />
+                </Button>
+              </div>
+            ))}
+          </div>
+        )}
+
+        <DialogFooter>
+          {!invite && (
+            <Button
+              onClick={onCreateClicked}
+              disabled={validForHours < 1 || maxUses < 1}
+            >
+              Create invite
+            </Button>
+          )}
+          <DialogClose asChild>
+            <Button variant="outline">Done</Button>
+          </DialogClose>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function EditNicknameDialog({
+  nickname,
+  onSaved,
+}: {
+  nickname: string;
+  onSaved: () => void;
+}) {
+  const [isOpen, setIsOpen] = useState(false);
+  const [newNickname, setNewNickname] = useState(nickname);
+  const [isSaving, setIsSaving] = useState(false);
+
+  const onSaveClicked = useCallback(async () => {
+    setIsSaving(true);
+    try {
+      await invoke("update_profile", { nickname: newNickname });
+      toast.success("Nickname changed", {
+        description: "Your contacts will see it once they are online.",
+      });
+      setIsOpen(false);
+      onSaved();
+    } catch (error) {
+      console.error("Unable to change nickname", error);
+
+      toast.error("Unable to change nickname", {
+        description: errorMessage(error),
+      });
+    }
+    setIsSaving(false);
+  }, [newNickname, onSaved]);
+
+  const trimmed = newNickname.trim();
+  return (
+    <Dialog
+      open={isOpen}
+      onOpenChange={(open) => {
+        setIsOpen(open);
+        setNewNickname(nickname);
+      }}
+    >
+      <DialogTrigger asChild>
+        <Button variant="ghost" size="icon">
+          <Pencil />
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Change Nickname</DialogTitle>
+          <DialogDescription>
+            This is how your friends will recognize you.
+          </DialogDescription>
+        </DialogHeader>
+
+        <Input
+          placeholder="My name is..."
+          value={newNickname}
+          onChange={(e) => setNewNickname(e.target.value)}
+        />
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button variant="outline" disabled={isSaving}>
+              Cancel
+            </Button>
+          </DialogClose>
+
+          <Button
+            onClick={onSaveClicked}
+            disabled={isSaving || trimmed.length < 3 || trimmed.length > 20}
+          >
+            Save
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function PresenceDialog() {
+  const [isOpen, setIsOpen] = useState(false);
+  const [presence, setPresence] = useState<Presence>();
+  const [settings, setSettings] = useState<PresenceSettings>();
+  const [isSaving, setIsSaving] = useState(false);
+
+  const fetchPresence = useCallback(async () => {
+    try {
+      setPresence(await invoke<Presence>("get_presence"));
+      setSettings(await invoke<PresenceSettings>("get_presence_settings"));
+    } catch (error) {
+      console.error("Unable to get presence", error);
+
+      toast.error("Unable to get presence", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+  useEffect(() => {
+    fetchPresence();
+  }, [fetchPresence]);
+
+  const onSaveClicked = useCallback(async () => {
+    if (!presence || !settings) return;
+
+    setIsSaving(true);
+    try {
+      const statusText = presence.statusText?.trim() || null;
+      await invoke("set_presence", { presence: { ...presence, statusText } });
+      await invoke("set_presence_settings", { settings });
+      setIsOpen(false);
+    } catch (error) {
+      console.error("Unable to change presence", error);
+
+      toast.error("Unable to change presence", {
+        description: errorMessage(error),
+      });
+    }
+    setIsSaving(false);
+  }, [presence, settings]);
+
+  return (
+    <Dialog
+      open={isOpen}
+      onOpenChange={(open) => {
+        setIsOpen(open);
+        fetchPresence();
+      }}
+    >
+      <DialogTrigger asChild>
+        <Button variant="ghost" disabled={!presence}>
+          <PresenceDot state={presence?.state ?? "offline"} />
+          {presence ? PRESENCE_LABELS[presence.state] : "Presence"}
+          {presence?.statusText && (
+            <span className="text-muted-foreground truncate max-w-40">
+              {presence.statusText}
+            </span>
+          )}
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Presence</DialogTitle>
+          <DialogDescription>
+            What your contacts see while the app is running. Appear offline to
+            be invisible.
+          </DialogDescription>
+        </DialogHeader>
+
+        {presence && settings && (
+          <>
+            <div className="flex gap-2">
+              {(Object.keys(PRESENCE_LABELS) as PresenceState[]).map(
+                (state) => (
+                  <Button
+                    key={state}
+                    variant={presence.state === state ? "default" : "outline"}
+                    onClick={() => setPresence({ ...presence, state })}
+                  >
+                    <PresenceDot state={state} />
+                    {PRESENCE_LABELS[state]}
+                  </Button>
+                ),
+              )}
+            </div>
+
+            <Input
+              placeholder="What are you up to?"
+              maxLength={100}
+              value={presence.statusText ?? ""}
+              onChange={(e) =>
+                setPresence({ ...presence, statusText: e.target.value })
+              }
+            />
+
+            <label className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={settings.visibility === "nobody"}
+                onChange={(e) =>
+                  setSettings({
+                    ...settings,
+                    visibility: e.target.checked ? "nobody" : "contacts",
+                  })
+                }
+              />
+              Hide my presence from all contacts
+            </label>
+          </>
+        )}
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button variant="outline" disabled={isSaving}>
+              Cancel
+            </Button>
+          </DialogClose>
+
+          <Button onClick={onSaveClicked} disabled={isSaving}>
+            Save
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function ChangePassphraseDialog() {
+  const [isOpen, setIsOpen] = useState(false);
+  const [currentPassphrase, setCurrentPassphrase] = useState("");
+  const [newPassphrase, setNewPassphrase] = useState("");
+  const [isChanging, setIsChanging] = useState(false);
+
+  const onChangeClicked = useCallback(async () => {
+    setIsChanging(true);
+    try {
+      await invoke("change_passphrase", { currentPassphrase, newPassphrase });
+      toast.success("Passphrase changed");
+      setIsOpen(false);
+      setCurrentPassphrase("");
+      setNewPassphrase("");
+    } catch (error) {
+      console.error("Unable to change passphrase", error);
+
+      toast.error("Unable to change passphrase", {
+        description: errorMessage(error),
+      });
+    }
+    setIsChanging(false);
+  }, [currentPassphrase, newPassphrase]);
+
+  return (
+    <Dialog open={isOpen} onOpenChange={setIsOpen}>
+      <DialogTrigger asChild>
+        <Button variant="ghost">
+          <KeyRound />
+          Change passphrase
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Change Passphrase</DialogTitle>
+          <DialogDescription>
+            Your identity will be encrypted with the new passphrase.
+          </DialogDescription>
+        </DialogHeader>
+
+        <Input
+          type="password"
+          placeholder="Current passphrase"
+          value={currentPassphrase}
+          onChange={(e) => setCurrentPassphrase(e.target.value)}
+        />
+        <Input
+          type="password"
+          placeholder="New passphrase"
+          value={newPassphrase}
+          onChange={(e) => setNewPassphrase(e.target.value)}
+        />
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button variant="outline" disabled={isChanging}>
+              Cancel
+            </Button>
+          </DialogClose>
+
+          <Button
+            onClick={onChangeClicked}
+            disabled={isChanging || newPassphrase.length < 8}
+          >
+            Change
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+const RELAY_MODE_LABELS: Record<RelayMode, string> = {
+  default: "Default",
+  custom: "Custom",
+  disabled: "Off",
+};
+
+function NetworkDialog() {
+  const [isOpen, setIsOpen] = useState(false);
+  const [settings, setSettings] = useState<NetworkSettings>();
+  const [relayUrls, setRelayUrls] = useState("");
+  const [isSaving, setIsSaving] = useState(false);
+
+  const fetchSettings = useCallback(async () => {
+    try {
+      const settings = await invoke<NetworkSettings>("get_network_settings");
+      setSettings(settings);
+      setRelayUrls(settings.relayUrls.join(", "));
+    } catch (error) {
+      console.error("Unable to get network settings", error);
+
+      toast.error("Unable to get network settings", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const onSaveClicked = useCallback(async () => {
+    if (!settings) return;
+
+    setIsSaving(true);
+    try {
+      const urls = relayUrls
+        .split(/[\s,]+/)
+        .map((url) => url.trim())
+        .filter((url) => url.length > 0);
+      await invoke("set_network_settings", {
+        settings: { ...settings, relayUrls: urls },
+      });
+      toast.success("Network settings applied");
+      setIsOpen(false);
+    } catch (error) {
+      console.error("Unable to change network settings", error);
+
+      toast.error("Unable to change network settings", {
+        description: errorMessage(error),
+      });
+    }
+    setIsSaving(false);
+  }, [settings, relayUrls]);
+
+  return (
+    <Dialog
+      open={isOpen}
+      onOpenChange={(open) => {
+        setIsOpen(open);
+        fetchSettings();
+      }}
+    >
+      <DialogTrigger asChild>
+        <Button variant="ghost">
+          <Network />
+          Network
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Network</DialogTitle>
+          <DialogDescription>
+            Relays carry calls when contacts can't reach you directly. Saving
+            reconnects right away and ends any ongoing call.
+          </DialogDescription>
+        </DialogHeader>
+
+        {settings && (
+          <>
+            <div className="flex gap-2">
+              {(Object.keys(RELAY_MODE_LABELS) as RelayMode[]).map((mode) => (
+                <Button
+                  key={mode}
+                  variant={settings.relayMode === mode ? "default" : "outline"}
+                  onClick={() => setSettings({ ...settings, relayMode: mode })}
+                >
+                  {RELAY_MODE_LABELS[mode]}
+                </Button>
+              ))}
+            </div>
+
+            {settings.relayMode === "custom" && (
+              <Input
+                placeholder="https://relay.example.com"
+                value={relayUrls}
+                onChange={(e) => setRelayUrls(e.target.value)}
+              />
+            )}
+
+            <label className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={settings.discovery === "default"}
+                onChange={(e) =>
+                  setSettings({
+                    ...settings,
+                    discovery: e.target.checked ? "default" : "disabled",
+                  })
+                }
+              />
+              Publish my addresses so contacts can find me
+            </label>
+
+            <label className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={settings.localDiscovery && !settings.hideIp}
+                disabled={settings.hideIp}
+                onChange={(e) =>
+                  setSettings({ ...settings, localDiscovery: e.target.checked })
+                }
+              />
+              Find and be found by devices on the local network
+            </label>
+
+            <label className="flex items-center gap-2 text-sm">
+              <input
+                type="checkbox"
+                checked={settings.hideIp}
+                disabled={settings.relayMode === "disabled"}
+                onChange={(e) =>
+                  setSettings({ ...settings, hideIp: e.target.checked })
+                }
+              />
+              Hide my IP address (calls always go through a relay)
+            </label>
+          </>
+        )}
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button variant="outline" disabled={isSaving}>
+              Cancel
+            </Button>
+          </DialogClose>
+
+          <Button onClick={onSaveClicked} disabled={isSaving || !settings}>
+            Save
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function BackupIdentityDialog() {
+  const [mnemonic, setMnemonic] = useState<string>();
+  const [backupPassphrase, setBackupPassphrase] = useState("");
+
+  const onOpenChange = useCallback(async (open: boolean) => {
+    if (!open) {
+      // Don't keep the secret around longer than needed
+      setMnemonic(undefined);
+      setBackupPassphrase("");
+      return;
+    }
+
+    try {
+      setMnemonic(await invoke<string>("export_identity"));
+    } catch (error) {
+      console.error("Unable to get recovery phrase", error);
+
+      toast.error("Unable to get recovery phrase", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const onSaveFileClicked = useCallback(async () => {
+    try {
+      const path = await invoke<string>("export_identity", {
+        backupPassphrase,
+      });
+      toast.success("Backup saved", { description: path });
+    } catch (error) {
+      console.error("Unable to save backup", error);
+
+      toast.error("Unable to save backup", {
+        description: errorMessage(error),
+      });
+    }
+  }, [backupPassphrase]);
+
+  return (
+    <Dialog onOpenChange={onOpenChange}>
+      <DialogTrigger asChild>
+        <Button variant="ghost">
+          <DatabaseBackup />
+          Back up identity
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Back Up Identity</DialogTitle>
+          <DialogDescription>
+            Write down your recovery phrase to keep your contact ticket after
+            a reinstall. Anyone with it can <b>pretend to be you</b>.
+          </DialogDescription>
+        </DialogHeader>
+
+        <p className="font-mono text-sm select-text">
+          {mnemonic ?? <Loader className="animate-spin mx-auto" />}
+        </p>
+
+        <p className="text-muted-foreground text-sm">
+          Or save a backup file with your contacts, encrypted with a
+          passphrase.
+        </p>
+        <div className="flex gap-2">
+          <Input
+            type="password"
+            placeholder="Backup passphrase"
+            value={backupPassphrase}
+            onChange={(e) => setBackupPassphrase(e.target.value)}
+          />
+          <Button
+            variant="outline"
+            onClick={onSaveFileClicked}
+            disabled={backupPassphrase.length < 8}
+          >
+            Save file
+          </Button>
+        </div>
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button>Done</Button>
+          </DialogClose>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+function DeleteIdentityDialog() {
+  const navigate = useNavigate();
+  const [isDeleting, setIsDeleting] = useState(false);
+
+  const onDeleteClicked = useCallback(async () => {
+    setIsDeleting(true);
+    try {
+      await invoke("delete_identity");
+      navigate("/get-started");
+    } catch (error) {
+      console.error("Unable to delete identity", error);
+
+      toast.error("Unable to delete identity", {
+        description: errorMessage(error),
+      });
+      setIsDeleting(false);
+    }
+  }, [navigate]);
+
+  return (
+    <Dialog>
+      <DialogTrigger asChild>
+        <Button variant="ghost" className="text-destructive">
+          <Trash2 />
+          Delete identity
+        </Button>
+      </DialogTrigger>
+
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>Delete Identity</DialogTitle>
+          <DialogDescription>
+            Your contacts, call history and voicemails will be removed from
+            this device. Your contact ticket <b>cannot be recovered</b>, so
+            your contacts will have to add you again with a new one.
+          </DialogDescription>
+        </DialogHeader>
+
+        <DialogFooter>
+          <DialogClose asChild>
+            <Button variant="outline" disabled={isDeleting}>
+              Cancel
+            </Button>
+          </DialogClose>
+
+          <Button
+            variant="destructive"
+            onClick={onDeleteClicked}
+            disabled={isDeleting}
+          >
+            Delete
+          </Button>
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
 export function Component() {
+  const navigate = useNavigate();
   const [selfTicket, setSelfTicket] = useState<SerializedTicketResponse>();
 
   const fetchSelfTicket = useCallback(async () => {
@@ -23,15 +801,51 @@ export function Component() {
     } catch (error) {
       console.error("Error fetching self ticket:", error);
 
-      if (typeof error === "string") {
-        toast.error("Unable to get contact card", { description: error });
-      }
+      toast.error("Unable to get contact card", {
+        description: errorMessage(error),
+      });
     }
   }, []);
   useEffect(() => {
     fetchSelfTicket();
   }, [fetchSelfTicket]);
 
+  // The QR code is rendered by the backend, so it matches what `qr` prints
+  const [qrUrl, setQrUrl] = useState<string>();
+  useEffect(() => {
+    if (!selfTicket) return;
+
+    let url: string | undefined;
+    invoke<number[]>("get_self_ticket_qr", { format: "svg" })
+      .then((svg) => {
+        url = URL.createObjectURL(
+          new Blob([new Uint8Array(svg)], { type: "image/svg+xml" }),
+        );
+        setQrUrl(url);
+      })
+      .catch((error) => console.error("Unable to render QR code", error));
+    return () => {
+      if (url) URL.revokeObjectURL(url);
+    };
+  }, [selfTicket]);
+
+  const [avatarVersion, setAvatarVersion] = useState(0);
+  const onAvatarChosen = useCallback(async (file: File | undefined) => {
+    if (!file) return;
+
+    try {
+      const image = Array.from(new Uint8Array(await file.arrayBuffer()));
+      await invoke("set_avatar", { image });
+      setAvatarVersion((v) => v + 1);
+    } catch (error) {
+      console.error("Unable to change avatar", error);
+
+      toast.error("Unable to change avatar", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
   const onCopyClicked = useCallback(async () => {
     if (!selfTicket) return;
 
@@ -41,14 +855,39 @@ export function Component() {
     } catch (error) {
       console.error("Unable to copy contact ticket to clipboard", error);
 
-      if (typeof error === "string") {
-        toast.error("Unable to copy contact ticket to clipboard", {
-          description: error,
-        });
-      }
+      toast.error("Unable to copy contact ticket to clipboard", {
+        description: errorMessage(error),
+      });
     }
   }, [selfTicket]);
 
+  const onExportLogsClicked = useCallback(async () => {
+    try {
+      const path = await invoke<string>("export_log_bundle");
+      toast.success("Logs exported", { description: path });
+    } catch (error) {
+      console.error("Unable to export logs", error);
+
+      toast.error("Unable to export logs", {
+        description: errorMessage(error),
+      });
+    }
+  }, []);
+
+  const onLogoutClicked = useCallback(async () => {
+    try {
+      await invoke("logout", { wipe: false });
+      // The identity stays stored, locked behind its passphrase
+      navigate("/");
+    } catch (error) {
+      console.error("Unable to log out", error);
+
+      toast.error("Unable to log out", {
+        description: errorMessage(error),
+      });
+    }
+  }, [navigate]);
+
   return (
     <div className="size-full">
       <h2 className="w-full">My Contact Card</h2>
@@ -57,12 +896,38 @@ export function Component() {
         <div className="w-[70%] xs:w-[50%] aspect-square place-content-center text-center">
           {selfTicket ? (
             <div>
-              <QRCode
-                value={selfTicket.serializedTicket}
-                className="p-2 mb-4 size-full bg-white"
-              />
+              {qrUrl ? (
+                <img
+                  src={qrUrl}
+                  alt="Contact ticket QR code"
+                  className="p-2 mb-4 size-full bg-white"
+                />
+              ) : (
+                <Loader className="animate-spin mx-auto mb-4" />
+              )}
 
-              <p className="text-xl">{selfTicket.nickname}</p>
+              <div className="flex justify-center items-center gap-1">
+                <Avatar
+                  nickname={selfTicket.nickname}
+                  version={avatarVersion}
+                />
+                <p className="text-xl">{selfTicket.nickname}</p>
+                <Button variant="ghost" size="icon" asChild>
+                  <label>
+                    <ImageUp />
+                    <input
+                      type="file"
+                      accept="image/*"
+                      className="hidden"
+                      onChange={(e) => onAvatarChosen(e.target.files?.[0])}
+                    />
+                  </label>
+                </Button>
+                <EditNicknameDialog
+                  nickname={selfTicket.nickname}
+                  onSaved={fetchSelfTicket}
+                />
+              </div>
 
               <div className="flex justify-between items-center">
                 <span className="text-muted-foreground text-sm truncate">
@@ -78,6 +943,29 @@ export function Component() {
             <Loader className="animate-spin mx-auto" />
           )}
         </div>
+
+        <PresenceDialog />
+        <InviteDialog />
+        <NetworkDialog />
+
+        <Button variant="ghost" onClick={onExportLogsClicked}>
+          <FileText />
+          Export logs for a bug report
+        </Button>
+
+        <div className="flex gap-2">
+          <BackupIdentityDialog />
+          <ChangePassphraseDialog />
+        </div>
+
+        <div className="flex gap-2">
+          <Button variant="ghost" onClick={onLogoutClicked}>
+            <LogOut />
+            Log out
+          </Button>
+
+          <DeleteIdentityDialog />
+        </div>
       </div>
     </div>
   );
@@ -1,7 +1,7 @@
 import { zodResolver } from "@hookform/resolvers/zod";
 import { invoke } from "@tauri-apps/api/core";
 import { useForm } from "react-hook-form";
-import { useNavigate } from "react-router";
+import { Link, useNavigate } from "react-router";
 import { toast } from "sonner";
 import { z } from "zod/v4";
 import { Button } from "@/components/ui/button";
@@ -15,6 +15,7 @@ import {
   FormMessage,
 } from "@/components/ui/form";
 import { Input } from "@/components/ui/input";
+import { errorMessage } from "@/lib/utils";
 
 const formSchema = z.object({
   nickname: z
@@ -26,6 +27,9 @@ const formSchema = z.object({
     .max(20, {
       error: (p) => `This must be at most ${p.maximum} characters long`,
     }),
+  passphrase: z.string().min(8, {
+    error: (p) => `This must be at least ${p.minimum} characters long`,
+  }),
 });
 
 type FormData = z.infer<typeof formSchema>;
@@ -37,6 +41,7 @@ export function Component() {
     resolver: zodResolver(formSchema),
     defaultValues: {
       nickname: "",
+      passphrase: "",
     },
   });
 
@@ -47,9 +52,7 @@ export function Component() {
     } catch (error) {
       console.error("Unable to login", error);
 
-      if (typeof error === "string") {
-        toast.error(`Unable to login`, { description: error });
-      }
+      toast.error(`Unable to login`, { description: errorMessage(error) });
     }
   }
 
@@ -80,9 +83,31 @@ export function Component() {
                   </FormItem>
                 )}
               />
+              <FormField
+                control={form.control}
+                name="passphrase"
+                render={({ field }) => (
+                  <FormItem className="mt-4">
+                    <FormLabel>Passphrase</FormLabel>
+                    <FormControl>
+                      <Input type="password" {...field} />
+                    </FormControl>
+                    <FormDescription>
+                      Your identity is encrypted with this. It can't be
+                      recovered if you forget it.
+                    </FormDescription>
+                    <FormMessage />
+                  </FormItem>
+                )}
+              />
             </div>
 
-            <Button type="submit">Continue</Button>
+            <div className="flex gap-2">
+              <Button variant="ghost" asChild>
+                <Link to="/restore">Restore from a backup</Link>
+              </Button>
+              <Button type="submit">Continue</Button>
+            </div>
           </form>
         </Form>
       </div>
@@ -3,6 +3,7 @@ import { Loader } from "lucide-react";
 import { useCallback, useEffect, useState } from "react";
 import { Link, useNavigate } from "react-router";
 import { Button } from "@/components/ui/button";
+import { isFreeVoipError } from "@/lib/utils";
 
 export function Component() {
   const navigate = useNavigate();
@@ -14,6 +15,11 @@ export function Component() {
     try {
       isLoggedIn = await invoke("restore_login");
     } catch (error) {
+      if (isFreeVoipError(error) && error.code === "Locked") {
+        navigate("/unlock");
+        return;
+      }
+
       console.error("Unable to restore login state", error);
       isLoggedIn = false;
     }
@@ -0,0 +1,145 @@
+import { invoke } from "@tauri-apps/api/core";
+import { type FormEvent, useState } from "react";
+import { useNavigate } from "react-router";
+import { toast } from "sonner";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { errorMessage } from "@/lib/utils";
+
+type BackupSource =
+  | { kind: "mnemonic"; phrase: string; nickname: string }
+  | { kind: "file"; contents: string; passphrase: string };
+
+export function Component() {
+  const navigate = useNavigate();
+  const [kind, setKind] = useState<BackupSource["kind"]>("mnemonic");
+  const [phrase, setPhrase] = useState("");
+  const [nickname, setNickname] = useState("");
+  const [file, setFile] = useState<File>();
+  const [backupPassphrase, setBackupPassphrase] = useState("");
+  const [passphrase, setPassphrase] = useState("");
+  const [isRestoring, setIsRestoring] = useState(false);
+
+  async function onSubmit(event: FormEvent) {
+    event.preventDefault();
+    setIsRestoring(true);
+
+    try {
+      let source: BackupSource;
+      if (kind === "mnemonic") {
+        source = { kind, phrase, nickname: nickname.trim() };
+      } else {
+        if (!file) throw new Error("Choose a backup file");
+        source = {
+          kind,
+          contents: await file.text(),
+          passphrase: backupPassphrase,
+        };
+      }
+
+      await invoke("import_identity", { source, passphrase });
+      navigate("/app");
+    } catch (error) {
+      console.error("Unable to restore identity", error);
+
+      toast.error("Unable to restore identity", {
+        description: errorMessage(error),
+      });
+      setIsRestoring(false);
+    }
+  }
+
+  return (
+    <div className="flex flex-col size-full justify-center items-center">
+      <h2 className="w-full">Restore Identity</h2>
+
+      <div className="grow w-full grid grid-cols-6">
+        <form
+          className="w-full col-span-full sm:col-span-4 sm:col-start-2 flex flex-col gap-4 items-center"
+          onSubmit={onSubmit}
+        >
+          <div className="flex gap-2">
+            <Button
+              type="button"
+              variant={kind === "mnemonic" ? "default" : "outline"}
+              onClick={() => setKind("mnemonic")}
+            >
+              Recovery phrase
+            </Button>
+            <Button
+              type="button"
+              variant={kind === "file" ? "default" : "outline"}
+              onClick={() => setKind("file")}
+            >
+              Backup file
+            </Button>
+          </div>
+
+          <div className="w-full grow flex flex-col gap-4">
+            {kind === "mnemonic" ? (
+              <>
+                <div className="flex flex-col gap-2">
+                  <Label htmlFor="phrase">Recovery phrase</Label>
+                  <Input
+                    id="phrase"
+                    placeholder="24 words"
+                    value={phrase}
+                    onChange={(e) => setPhrase(e.target.value)}
+                  />
+                </div>
+                <div className="flex flex-col gap-2">
+                  <Label htmlFor="nickname">Nickname</Label>
+                  <Input
+                    id="nickname"
+                    placeholder="My name is..."
+                    value={nickname}
+                    onChange={(e) => setNickname(e.target.value)}
+                  />
+                </div>
+              </>
+            ) : (
+              <>
+                <div className="flex flex-col gap-2">
+                  <Label htmlFor="file">Backup file</Label>
+                  <Input
+                    id="file"
+                    type="file"
+                    accept=".json"
+                    onChange={(e) => setFile(e.target.files?.[0])}
+                  />
+                </div>
+                <div className="flex flex-col gap-2">
+                  <Label htmlFor="backup-passphrase">Backup passphrase</Label>
+                  <Input
+                    id="backup-passphrase"
+                    type="password"
+                    value={backupPassphrase}
+                    onChange={(e) => setBackupPassphrase(e.target.value)}
+                  />
+                </div>
+              </>
+            )}
+
+            <div className="flex flex-col gap-2">
+              <Label htmlFor="passphrase">New passphrase</Label>
+              <Input
+                id="passphrase"
+                type="password"
+                value={passphrase}
+                onChange={(e) => setPassphrase(e.target.value)}
+              />
+              <p className="text-muted-foreground text-sm">
+                Your identity is encrypted with this on this device.
+              </p>
+            </div>
+          </div>
+
+          <Button type="submit" disabled={isRestoring || passphrase.length < 8}>
+            Restore
+          </Button>
+        </form>
+      </div>
+    </div>
+  );
+}
@@ -0,0 +1,84 @@
+import { zodResolver } from "@hookform/resolvers/zod";
+import { invoke } from "@tauri-apps/api/core";
+import { useForm } from "react-hook-form";
+import { useNavigate } from "react-router";
+import { toast } from "sonner";
+import { z } from "zod/v4";
+import { Button } from "@/components/ui/button";
+import {
+  Form,
+  FormControl,
+  FormField,
+  FormItem,
+  FormLabel,
+  FormMessage,
+} from "@/components/ui/form";
+import { Input } from "@/components/ui/input";
+import { errorMessage, isFreeVoipError } from "@/lib/utils";
+
+const formSchema = z.object({
+  passphrase: z.string().min(1, { error: "Enter your passphrase" }),
+});
+
+type FormData = z.infer<typeof formSchema>;
+
+export function Component() {
+  const navigate = useNavigate();
+
+  const form = useForm<FormData>({
+    resolver: zodResolver(formSchema),
+    defaultValues: {
+      passphrase: "",
+    },
+  });
+
+  async function onSubmit(values: FormData) {
+    try {
+      await invoke("unlock", values);
+      navigate("/app");
+    } catch (error) {
+      if (isFreeVoipError(error) && error.code === "WrongPassphrase") {
+        form.setError("passphrase", { message: error.message });
+        return;
+      }
+      console.error("Unable to unlock", error);
+
+      toast.error("Unable to unlock", { description: errorMessage(error) });
+    }
+  }
+
+  return (
+    <div className="flex flex-col size-full justify-center items-center">
+      <h2 className="w-full">Unlock</h2>
+
+      <div className="grow w-full grid grid-cols-6">
+        <Form {...form}>
+          <form
+            className="w-full col-span-full sm:col-span-4 sm:col-start-2 flex flex-col items-center"
+            onSubmit={form.handleSubmit(onSubmit)}
+          >
+            <div className="w-full grow">
+              <FormField
+                control={form.control}
+                name="passphrase"
+                render={({ field }) => (
+                  <FormItem>
+                    <FormLabel>Passphrase</FormLabel>
+                    <FormControl>
+                      <Input type="password" autoFocus {...field} />
+                    </FormControl>
+                    <FormMessage />
+                  </FormItem>
+                )}
+              />
+            </div>
+
+            <Button type="submit" disabled={form.formState.isSubmitting}>
+              Unlock
+            </Button>
+          </form>
+        </Form>
+      </div>
+    </div>
+  );
+}